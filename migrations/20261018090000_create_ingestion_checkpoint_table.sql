@@ -0,0 +1,8 @@
+CREATE TABLE log.checkpoints(
+     source VARCHAR(200) NOT NULL,
+     PRIMARY KEY (source),
+     last_msgtime TIMESTAMP WITH TIME ZONE,
+     last_request_time TIMESTAMP WITH TIME ZONE NOT NULL,
+     log_id UUID REFERENCES log.requests(id),
+     updated_at TIMESTAMP DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE log.requests
+    ADD COLUMN outcome VARCHAR(20);
@@ -0,0 +1,31 @@
+-- Keep the first copy of every message that was ingested more than once.
+DELETE FROM ais.ais_static_data a
+    USING ais.ais_static_data b
+    WHERE a.id > b.id
+      AND a.mmsi = b.mmsi
+      AND a.msgtime = b.msgtime
+      AND a.message_type = b.message_type;
+
+DELETE FROM ais.ais_aton_data a
+    USING ais.ais_aton_data b
+    WHERE a.id > b.id
+      AND a.mmsi = b.mmsi
+      AND a.msgtime = b.msgtime
+      AND a.message_type = b.message_type;
+
+DELETE FROM ais.ais_position_data a
+    USING ais.ais_position_data b
+    WHERE a.id > b.id
+      AND a.mmsi = b.mmsi
+      AND a.msgtime = b.msgtime
+      AND a.message_type = b.message_type;
+
+CREATE UNIQUE INDEX ais_static_data_natural_key
+    ON ais.ais_static_data (mmsi, msgtime, message_type);
+CREATE UNIQUE INDEX ais_aton_data_natural_key
+    ON ais.ais_aton_data (mmsi, msgtime, message_type);
+CREATE UNIQUE INDEX ais_position_data_natural_key
+    ON ais.ais_position_data (mmsi, msgtime, message_type);
+
+ALTER TABLE log.requests
+    ADD COLUMN duplicates_skipped BIGINT;
@@ -0,0 +1,115 @@
+CREATE TABLE ais.vessels (
+                             mmsi BIGINT PRIMARY KEY,
+                             name VARCHAR(255),
+                             call_sign VARCHAR(255),
+                             imo_number BIGINT,
+                             ship_type INT,
+                             ship_length INT,
+                             ship_width INT,
+                             dimension_a INT,
+                             dimension_b INT,
+                             dimension_c INT,
+                             dimension_d INT,
+                             draught INT,
+                             destination VARCHAR(255),
+                             eta VARCHAR(255),
+                             first_seen TIMESTAMP WITH TIME ZONE NOT NULL,
+                             last_seen TIMESTAMP WITH TIME ZONE NOT NULL,
+                             log_id UUID REFERENCES log.requests(id)
+);
+
+CREATE INDEX vessels_imo_number ON ais.vessels (imo_number);
+
+-- change_type is one of name, call_sign, imo_number, ship_type, dimensions, destination,
+-- mmsi_change (the IMO number moved to this MMSI, related_mmsi is the previous one),
+-- flag_change (an mmsi_change where the maritime identification digits differ) or
+-- imo_reuse (another MMSI that is still active reports the same IMO number).
+CREATE TABLE ais.vessel_history (
+                                    id BIGSERIAL PRIMARY KEY,
+                                    mmsi BIGINT NOT NULL REFERENCES ais.vessels(mmsi),
+                                    changed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                                    change_type VARCHAR(50) NOT NULL,
+                                    old_value VARCHAR(255),
+                                    new_value VARCHAR(255),
+                                    related_mmsi BIGINT,
+                                    log_id UUID REFERENCES log.requests(id)
+);
+
+CREATE INDEX vessel_history_mmsi ON ais.vessel_history (mmsi, changed_at);
+
+CREATE FUNCTION ais.record_vessel_changes() RETURNS TRIGGER AS $$
+DECLARE
+    previous RECORD;
+BEGIN
+    IF TG_OP = 'UPDATE' THEN
+        IF NEW.name IS DISTINCT FROM OLD.name THEN
+            INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, log_id)
+            VALUES (NEW.mmsi, NEW.last_seen, 'name', OLD.name, NEW.name, NEW.log_id);
+        END IF;
+        IF NEW.call_sign IS DISTINCT FROM OLD.call_sign THEN
+            INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, log_id)
+            VALUES (NEW.mmsi, NEW.last_seen, 'call_sign', OLD.call_sign, NEW.call_sign, NEW.log_id);
+        END IF;
+        IF NEW.imo_number IS DISTINCT FROM OLD.imo_number THEN
+            INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, log_id)
+            VALUES (NEW.mmsi, NEW.last_seen, 'imo_number', OLD.imo_number::TEXT, NEW.imo_number::TEXT, NEW.log_id);
+        END IF;
+        IF NEW.ship_type IS DISTINCT FROM OLD.ship_type THEN
+            INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, log_id)
+            VALUES (NEW.mmsi, NEW.last_seen, 'ship_type', OLD.ship_type::TEXT, NEW.ship_type::TEXT, NEW.log_id);
+        END IF;
+        IF (NEW.dimension_a, NEW.dimension_b, NEW.dimension_c, NEW.dimension_d)
+            IS DISTINCT FROM (OLD.dimension_a, OLD.dimension_b, OLD.dimension_c, OLD.dimension_d) THEN
+            INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, log_id)
+            VALUES (NEW.mmsi, NEW.last_seen, 'dimensions',
+                    concat_ws('/', OLD.dimension_a, OLD.dimension_b, OLD.dimension_c, OLD.dimension_d),
+                    concat_ws('/', NEW.dimension_a, NEW.dimension_b, NEW.dimension_c, NEW.dimension_d),
+                    NEW.log_id);
+        END IF;
+        IF NEW.destination IS DISTINCT FROM OLD.destination THEN
+            INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, log_id)
+            VALUES (NEW.mmsi, NEW.last_seen, 'destination', OLD.destination, NEW.destination, NEW.log_id);
+        END IF;
+    END IF;
+
+    -- The same IMO number on another MMSI is either the ship changing MMSI (and possibly flag) or
+    -- two ships reporting the same number, depending on whether the other MMSI is still active.
+    IF NEW.imo_number IS NOT NULL AND NEW.imo_number > 0
+        AND (TG_OP = 'INSERT' OR NEW.imo_number IS DISTINCT FROM OLD.imo_number) THEN
+        FOR previous IN
+            SELECT mmsi, last_seen FROM ais.vessels
+            WHERE imo_number = NEW.imo_number AND mmsi <> NEW.mmsi
+        LOOP
+            IF previous.last_seen > NEW.last_seen - INTERVAL '1 day' THEN
+                INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, related_mmsi, log_id)
+                VALUES (NEW.mmsi, NEW.last_seen, 'imo_reuse', NULL, NEW.imo_number::TEXT, previous.mmsi, NEW.log_id);
+            ELSE
+                INSERT INTO ais.vessel_history (mmsi, changed_at, change_type, old_value, new_value, related_mmsi, log_id)
+                VALUES (NEW.mmsi, NEW.last_seen,
+                        CASE WHEN left(previous.mmsi::TEXT, 3) <> left(NEW.mmsi::TEXT, 3)
+                             THEN 'flag_change' ELSE 'mmsi_change' END,
+                        previous.mmsi::TEXT, NEW.mmsi::TEXT, previous.mmsi, NEW.log_id);
+            END IF;
+        END LOOP;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vessels_record_changes
+    AFTER INSERT OR UPDATE ON ais.vessels
+    FOR EACH ROW EXECUTE FUNCTION ais.record_vessel_changes();
+
+-- Seed the registry from what has already been ingested.
+INSERT INTO ais.vessels (
+    mmsi, name, call_sign, imo_number, ship_type, ship_length, ship_width, dimension_a, dimension_b,
+    dimension_c, dimension_d, draught, destination, eta, first_seen, last_seen, log_id
+)
+SELECT DISTINCT ON (mmsi)
+    mmsi, name, call_sign, imo_number, ship_type, ship_length, ship_width, dimension_a, dimension_b,
+    dimension_c, dimension_d, draught, destination, eta,
+    MIN(msgtime) OVER (PARTITION BY mmsi), msgtime, log_id
+FROM ais.ais_static_data
+WHERE mmsi IS NOT NULL AND msgtime IS NOT NULL
+ORDER BY mmsi, msgtime DESC;
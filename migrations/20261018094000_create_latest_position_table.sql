@@ -0,0 +1,48 @@
+CREATE TABLE ais.latest_position (
+                                     mmsi BIGINT PRIMARY KEY,
+                                     msgtime TIMESTAMP WITH TIME ZONE NOT NULL,
+                                     message_type BIGINT,
+                                     ais_class VARCHAR(255),
+                                     latitude DOUBLE PRECISION,
+                                     longitude DOUBLE PRECISION,
+                                     course_over_ground DOUBLE PRECISION,
+                                     speed_over_ground DOUBLE PRECISION,
+                                     true_heading BIGINT,
+                                     rate_of_turn BIGINT,
+                                     navigational_status BIGINT,
+                                     log_id UUID REFERENCES log.requests(id),
+                                     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX latest_position_msgtime ON ais.latest_position (msgtime);
+
+INSERT INTO ais.latest_position (
+    mmsi, msgtime, message_type, ais_class, latitude, longitude, course_over_ground, speed_over_ground,
+    true_heading, rate_of_turn, navigational_status, log_id
+)
+SELECT DISTINCT ON (mmsi)
+    mmsi, msgtime, message_type, ais_class, latitude, longitude, course_over_ground, speed_over_ground,
+    true_heading, rate_of_turn, navigational_status, log_id
+FROM ais.ais_position_data
+WHERE mmsi IS NOT NULL AND msgtime IS NOT NULL
+ORDER BY mmsi, msgtime DESC;
+
+-- The current fleet picture: every vessel's last position with what is known about it.
+CREATE VIEW ais.fleet_picture AS
+SELECT p.mmsi,
+       p.msgtime,
+       p.latitude,
+       p.longitude,
+       p.course_over_ground,
+       p.speed_over_ground,
+       p.true_heading,
+       p.navigational_status,
+       v.name,
+       v.call_sign,
+       v.imo_number,
+       v.ship_type,
+       v.ship_length,
+       v.ship_width,
+       v.destination
+FROM ais.latest_position p
+LEFT JOIN ais.vessels v ON v.mmsi = p.mmsi;
@@ -0,0 +1,53 @@
+-- PostGIS is optional. When the extension is available on the server the position, AtoN and
+-- latest position tables get a geography column kept in sync with latitude/longitude and a GiST
+-- index; otherwise this migration does nothing and the spatial queries fall back to plain
+-- latitude/longitude filtering. Install PostGIS before applying it to get the columns.
+DO $migration$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'postgis') THEN
+        RAISE NOTICE 'PostGIS is not available, skipping geometry columns';
+        RETURN;
+    END IF;
+
+    CREATE EXTENSION IF NOT EXISTS postgis;
+
+    CREATE FUNCTION ais.set_position_geom() RETURNS TRIGGER AS $$
+    BEGIN
+        IF NEW.latitude BETWEEN -90 AND 90 AND NEW.longitude BETWEEN -180 AND 180 THEN
+            NEW.geom := ST_SetSRID(ST_MakePoint(NEW.longitude, NEW.latitude), 4326)::geography;
+        ELSE
+            NEW.geom := NULL;
+        END IF;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    ALTER TABLE ais.ais_position_data ADD COLUMN geom geography(Point, 4326);
+    ALTER TABLE ais.ais_aton_data ADD COLUMN geom geography(Point, 4326);
+    ALTER TABLE ais.latest_position ADD COLUMN geom geography(Point, 4326);
+
+    UPDATE ais.ais_position_data
+        SET geom = ST_SetSRID(ST_MakePoint(longitude, latitude), 4326)::geography
+        WHERE latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180;
+    UPDATE ais.ais_aton_data
+        SET geom = ST_SetSRID(ST_MakePoint(longitude, latitude), 4326)::geography
+        WHERE latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180;
+    UPDATE ais.latest_position
+        SET geom = ST_SetSRID(ST_MakePoint(longitude, latitude), 4326)::geography
+        WHERE latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180;
+
+    CREATE TRIGGER ais_position_data_geom
+        BEFORE INSERT OR UPDATE OF latitude, longitude ON ais.ais_position_data
+        FOR EACH ROW EXECUTE FUNCTION ais.set_position_geom();
+    CREATE TRIGGER ais_aton_data_geom
+        BEFORE INSERT OR UPDATE OF latitude, longitude ON ais.ais_aton_data
+        FOR EACH ROW EXECUTE FUNCTION ais.set_position_geom();
+    CREATE TRIGGER latest_position_geom
+        BEFORE INSERT OR UPDATE OF latitude, longitude ON ais.latest_position
+        FOR EACH ROW EXECUTE FUNCTION ais.set_position_geom();
+
+    CREATE INDEX ais_position_data_geom ON ais.ais_position_data USING GIST (geom);
+    CREATE INDEX ais_aton_data_geom ON ais.ais_aton_data USING GIST (geom);
+    CREATE INDEX latest_position_geom ON ais.latest_position USING GIST (geom);
+END
+$migration$;
@@ -0,0 +1,103 @@
+-- Turn ais.ais_position_data into a table range partitioned on msgtime. Everything starts out in
+-- the default partition; ais.create_position_partition splits ranges out of it as the ingestion
+-- creates daily or monthly partitions, and ais.position_partitions records which ranges exist so
+-- the retention policy can detach old ones.
+ALTER TABLE ais.ais_position_data RENAME TO ais_position_data_unpartitioned;
+ALTER INDEX ais.ais_position_data_natural_key RENAME TO ais_position_data_unpartitioned_natural_key;
+ALTER INDEX IF EXISTS ais.ais_position_data_geom RENAME TO ais_position_data_unpartitioned_geom;
+
+-- The primary key on id is dropped: a unique constraint on a partitioned table has to include
+-- msgtime, which is nullable.
+CREATE TABLE ais.ais_position_data (
+    LIKE ais.ais_position_data_unpartitioned INCLUDING DEFAULTS
+) PARTITION BY RANGE (msgtime);
+ALTER TABLE ais.ais_position_data
+    ADD CONSTRAINT ais_position_data_log_id_fkey FOREIGN KEY (log_id) REFERENCES log.requests(id);
+CREATE TABLE ais.ais_position_data_default PARTITION OF ais.ais_position_data DEFAULT;
+
+CREATE INDEX ais_position_data_id ON ais.ais_position_data (id);
+CREATE UNIQUE INDEX ais_position_data_natural_key
+    ON ais.ais_position_data (mmsi, msgtime, message_type);
+
+DO $migration$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = 'ais' AND table_name = 'ais_position_data' AND column_name = 'geom'
+    ) THEN
+        CREATE INDEX ais_position_data_geom ON ais.ais_position_data USING GIST (geom);
+        CREATE TRIGGER ais_position_data_geom
+            BEFORE INSERT OR UPDATE OF latitude, longitude ON ais.ais_position_data
+            FOR EACH ROW EXECUTE FUNCTION ais.set_position_geom();
+    END IF;
+END
+$migration$;
+
+INSERT INTO ais.ais_position_data SELECT * FROM ais.ais_position_data_unpartitioned;
+ALTER SEQUENCE ais.ais_position_data_id_seq OWNED BY ais.ais_position_data.id;
+DROP TABLE ais.ais_position_data_unpartitioned;
+
+CREATE SCHEMA IF NOT EXISTS ais_archive;
+
+CREATE TABLE ais.position_partitions (
+    partition_name VARCHAR(63) PRIMARY KEY,
+    range_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    range_end TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Creates the partition for [range_start, range_end) and moves the rows the default partition
+-- already holds for that range into it. Returns false without doing anything when the range
+-- overlaps an existing partition, e.g. a daily range inside a month created before the interval
+-- was changed.
+CREATE FUNCTION ais.create_position_partition(
+    new_partition_name TEXT,
+    new_range_start TIMESTAMP WITH TIME ZONE,
+    new_range_end TIMESTAMP WITH TIME ZONE
+) RETURNS BOOLEAN AS $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM ais.position_partitions
+        WHERE range_start < new_range_end AND range_end > new_range_start
+    ) THEN
+        RETURN FALSE;
+    END IF;
+
+    EXECUTE format(
+        'CREATE TABLE ais.%I (LIKE ais.ais_position_data INCLUDING DEFAULTS)',
+        new_partition_name
+    );
+    EXECUTE format(
+        'WITH moved AS (
+            DELETE FROM ais.ais_position_data_default WHERE msgtime >= $1 AND msgtime < $2 RETURNING *
+        ) INSERT INTO ais.%I SELECT * FROM moved',
+        new_partition_name
+    ) USING new_range_start, new_range_end;
+    EXECUTE format(
+        'ALTER TABLE ais.ais_position_data ATTACH PARTITION ais.%I FOR VALUES FROM (%L) TO (%L)',
+        new_partition_name, new_range_start, new_range_end
+    );
+
+    INSERT INTO ais.position_partitions (partition_name, range_start, range_end)
+        VALUES (new_partition_name, new_range_start, new_range_end);
+    RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Detaches a partition created by ais.create_position_partition and either drops it or moves it
+-- to the ais_archive schema, where it is left as a plain table.
+CREATE FUNCTION ais.detach_position_partition(
+    old_partition_name TEXT,
+    archive BOOLEAN
+) RETURNS VOID AS $$
+BEGIN
+    EXECUTE format('ALTER TABLE ais.ais_position_data DETACH PARTITION ais.%I', old_partition_name);
+    IF archive THEN
+        EXECUTE format('ALTER TABLE ais.%I SET SCHEMA ais_archive', old_partition_name);
+    ELSE
+        EXECUTE format('DROP TABLE ais.%I', old_partition_name);
+    END IF;
+
+    DELETE FROM ais.position_partitions WHERE partition_name = old_partition_name;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,3 @@
+-- Messages whose type the ingestion does not recognise are counted instead of silently dropped.
+ALTER TABLE log.requests
+    ADD COLUMN unknown_messages BIGINT;
@@ -0,0 +1,14 @@
+-- Physically impossible values found while validating the messages of a request. Sentinels for
+-- "not available" are normalized without being recorded here.
+CREATE TABLE log.validation_issues (
+    id BIGSERIAL PRIMARY KEY,
+    log_id UUID NOT NULL REFERENCES log.requests(id),
+    message_kind VARCHAR(20) NOT NULL,
+    mmsi BIGINT,
+    msgtime TIMESTAMP WITH TIME ZONE,
+    field VARCHAR(50) NOT NULL,
+    value VARCHAR(255),
+    rejected BOOLEAN NOT NULL
+);
+
+CREATE INDEX validation_issues_log_id ON log.validation_issues (log_id);
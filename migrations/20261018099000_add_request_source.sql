@@ -0,0 +1,3 @@
+-- Where the messages of a request came from, now that they are not only read from BarentsWatch.
+ALTER TABLE log.requests
+    ADD COLUMN source VARCHAR(20) NOT NULL DEFAULT 'barentswatch';
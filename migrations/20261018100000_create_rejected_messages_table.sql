@@ -0,0 +1,16 @@
+-- Messages left out of a request because they did not parse, failed validation in reject mode or
+-- were refused by the database, kept as received so they can be re-processed after a fix.
+CREATE TABLE log.rejected_messages (
+    id BIGSERIAL PRIMARY KEY,
+    log_id UUID NOT NULL REFERENCES log.requests(id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    stage VARCHAR(20) NOT NULL,
+    reason TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    -- Set once the message was ingested again, by the request in reprocessed_log_id.
+    reprocessed_at TIMESTAMP WITH TIME ZONE,
+    reprocessed_log_id UUID REFERENCES log.requests(id)
+);
+
+CREATE INDEX rejected_messages_log_id ON log.rejected_messages (log_id);
+CREATE INDEX rejected_messages_pending ON log.rejected_messages (id) WHERE reprocessed_at IS NULL;
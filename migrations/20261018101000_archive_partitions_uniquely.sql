@@ -0,0 +1,40 @@
+-- A range can be archived more than once, e.g. when late messages recreated its partition after
+-- the first one was archived. The table already in ais_archive is kept and the new one gets the
+-- first free name with a numbered suffix, its indexes are renamed along with it.
+CREATE OR REPLACE FUNCTION ais.detach_position_partition(
+    old_partition_name TEXT,
+    archive BOOLEAN
+) RETURNS VOID AS $$
+DECLARE
+    archived_name TEXT := old_partition_name;
+    suffix INTEGER := 1;
+    index_name TEXT;
+BEGIN
+    EXECUTE format('ALTER TABLE ais.ais_position_data DETACH PARTITION ais.%I', old_partition_name);
+    IF archive THEN
+        WHILE EXISTS (
+            SELECT 1 FROM pg_tables WHERE schemaname = 'ais_archive' AND tablename = archived_name
+        ) LOOP
+            archived_name := old_partition_name || '_' || suffix;
+            suffix := suffix + 1;
+        END LOOP;
+        IF archived_name <> old_partition_name THEN
+            FOR index_name IN
+                SELECT indexname FROM pg_indexes WHERE schemaname = 'ais' AND tablename = old_partition_name
+            LOOP
+                EXECUTE format(
+                    'ALTER INDEX ais.%I RENAME TO %I',
+                    index_name,
+                    left(archived_name || substr(index_name, length(old_partition_name) + 1), 63)
+                );
+            END LOOP;
+            EXECUTE format('ALTER TABLE ais.%I RENAME TO %I', old_partition_name, archived_name);
+        END IF;
+        EXECUTE format('ALTER TABLE ais.%I SET SCHEMA ais_archive', archived_name);
+    ELSE
+        EXECUTE format('DROP TABLE ais.%I', old_partition_name);
+    END IF;
+
+    DELETE FROM ais.position_partitions WHERE partition_name = old_partition_name;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,17 @@
+-- Keeps the ETAs as received next to the column 20261018102000 converts to timestamps, so that
+-- 20261018103000 can convert the ones it could not resolve again. Databases that were converted
+-- before this migration existed already lost that text, there is nothing to keep.
+DO $migration$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = 'ais' AND table_name = 'ais_static_data' AND column_name = 'eta'
+            AND data_type = 'character varying'
+    ) THEN
+        ALTER TABLE ais.ais_static_data ADD COLUMN received_eta TEXT;
+        UPDATE ais.ais_static_data SET received_eta = eta;
+        ALTER TABLE ais.vessels ADD COLUMN received_eta TEXT;
+        UPDATE ais.vessels SET received_eta = eta;
+    END IF;
+END
+$migration$;
@@ -0,0 +1,61 @@
+-- ETAs were stored as text: as received from the API, later as MMDDHHMM without a year. Both
+-- become timestamps with the year taken from the time of the message, the same way as
+-- `Eta::resolve`: within six months of the message. Text that is not an ETA becomes NULL.
+CREATE FUNCTION ais.resolve_stored_eta(eta TEXT, msgtime TIMESTAMP WITH TIME ZONE)
+    RETURNS TIMESTAMP WITH TIME ZONE AS $$
+DECLARE
+    parts TEXT[];
+    eta_month INTEGER;
+    eta_day INTEGER;
+    eta_hour INTEGER;
+    eta_minute INTEGER;
+    message_year INTEGER := EXTRACT(YEAR FROM msgtime AT TIME ZONE 'UTC');
+    resolved TIMESTAMP WITH TIME ZONE;
+BEGIN
+    IF eta IS NULL OR msgtime IS NULL THEN
+        RETURN NULL;
+    END IF;
+    -- A full timestamp, its year is dropped like the ingestion does.
+    parts := regexp_match(eta, '^\d{4}-(\d{2})-(\d{2})T(\d{2}):(\d{2})');
+    IF parts IS NULL THEN
+        parts := regexp_match(trim(eta), '^(\d{2})-?(\d{2})[ T]?(\d{2}):?(\d{2})$');
+    END IF;
+    IF parts IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    eta_month := parts[1];
+    eta_day := parts[2];
+    eta_hour := parts[3];
+    eta_minute := parts[4];
+    -- 0 is the not available month and day, 24 and 60 the not available hour and minute.
+    IF eta_month NOT BETWEEN 1 AND 12 OR eta_day NOT BETWEEN 1 AND 31 OR eta_hour > 24 OR eta_minute > 60 THEN
+        RETURN NULL;
+    END IF;
+    IF eta_hour = 24 THEN
+        eta_hour := 0;
+    END IF;
+    IF eta_minute = 60 THEN
+        eta_minute := 0;
+    END IF;
+
+    resolved := make_timestamptz(message_year, eta_month, eta_day, eta_hour, eta_minute, 0, 'UTC');
+    IF resolved < msgtime - INTERVAL '183 days' THEN
+        resolved := make_timestamptz(message_year + 1, eta_month, eta_day, eta_hour, eta_minute, 0, 'UTC');
+    ELSIF resolved > msgtime + INTERVAL '183 days' THEN
+        resolved := make_timestamptz(message_year - 1, eta_month, eta_day, eta_hour, eta_minute, 0, 'UTC');
+    END IF;
+    RETURN resolved;
+EXCEPTION
+    -- A date that does not exist in that year, such as 30 February.
+    WHEN datetime_field_overflow THEN
+        RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE ais.ais_static_data
+    ALTER COLUMN eta TYPE TIMESTAMP WITH TIME ZONE USING ais.resolve_stored_eta(eta, msgtime);
+ALTER TABLE ais.vessels
+    ALTER COLUMN eta TYPE TIMESTAMP WITH TIME ZONE USING ais.resolve_stored_eta(eta, last_seen);
+
+DROP FUNCTION ais.resolve_stored_eta(TEXT, TIMESTAMP WITH TIME ZONE);
@@ -0,0 +1,74 @@
+-- 20261018102000 only tried the year of the message before moving the ETA a year back or forth,
+-- so an ETA on a leap day in the year after or before the message, such as 02-29 received in
+-- December 2027, became NULL. Those ETAs are resolved again from the text kept by
+-- 20261018101500, trying each candidate year like `Eta::resolve` does.
+CREATE FUNCTION ais.resolve_received_eta(eta TEXT, msgtime TIMESTAMP WITH TIME ZONE)
+    RETURNS TIMESTAMP WITH TIME ZONE AS $$
+DECLARE
+    parts TEXT[];
+    eta_month INTEGER;
+    eta_day INTEGER;
+    eta_hour INTEGER;
+    eta_minute INTEGER;
+    message_year INTEGER := EXTRACT(YEAR FROM msgtime AT TIME ZONE 'UTC');
+    candidate_year INTEGER;
+    resolved TIMESTAMP WITH TIME ZONE;
+BEGIN
+    IF eta IS NULL OR msgtime IS NULL THEN
+        RETURN NULL;
+    END IF;
+    parts := regexp_match(eta, '^\d{4}-(\d{2})-(\d{2})T(\d{2}):(\d{2})');
+    IF parts IS NULL THEN
+        parts := regexp_match(trim(eta), '^(\d{2})-?(\d{2})[ T]?(\d{2}):?(\d{2})$');
+    END IF;
+    IF parts IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    eta_month := parts[1];
+    eta_day := parts[2];
+    eta_hour := parts[3];
+    eta_minute := parts[4];
+    IF eta_month NOT BETWEEN 1 AND 12 OR eta_day NOT BETWEEN 1 AND 31 OR eta_hour > 24 OR eta_minute > 60 THEN
+        RETURN NULL;
+    END IF;
+    IF eta_hour = 24 THEN
+        eta_hour := 0;
+    END IF;
+    IF eta_minute = 60 THEN
+        eta_minute := 0;
+    END IF;
+
+    FOREACH candidate_year IN ARRAY ARRAY[message_year, message_year + 1, message_year - 1] LOOP
+        BEGIN
+            resolved := make_timestamptz(candidate_year, eta_month, eta_day, eta_hour, eta_minute, 0, 'UTC');
+        EXCEPTION
+            -- The date does not exist in that year, such as 29 February.
+            WHEN datetime_field_overflow THEN
+                CONTINUE;
+        END;
+        IF resolved BETWEEN msgtime - INTERVAL '183 days' AND msgtime + INTERVAL '183 days' THEN
+            RETURN resolved;
+        END IF;
+    END LOOP;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DO $migration$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = 'ais' AND table_name = 'ais_static_data' AND column_name = 'received_eta'
+    ) THEN
+        UPDATE ais.ais_static_data SET eta = ais.resolve_received_eta(received_eta, msgtime)
+            WHERE eta IS NULL AND received_eta IS NOT NULL;
+        ALTER TABLE ais.ais_static_data DROP COLUMN received_eta;
+        UPDATE ais.vessels SET eta = ais.resolve_received_eta(received_eta, last_seen)
+            WHERE eta IS NULL AND received_eta IS NOT NULL;
+        ALTER TABLE ais.vessels DROP COLUMN received_eta;
+    END IF;
+END
+$migration$;
+
+DROP FUNCTION ais.resolve_received_eta(TEXT, TIMESTAMP WITH TIME ZONE);
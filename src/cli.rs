@@ -0,0 +1,413 @@
+use barents::database::configuration::NmeaTransport;
+use barents::database::spatial::BoundingBox;
+use barents::live_ais::mock_server::ScriptedFailure;
+use barents::live_ais::query::{MessageKind, ModelFormat, Polygon};
+use barents::live_ais::response_structs::RejectionStage;
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use sqlx::types::Uuid;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "barents", version, about = "Ingest BarentsWatch AIS data into Postgres")]
+pub struct Cli {
+    /// Path to the configuration file.
+    #[arg(long, global = true, default_value = "configuration.yaml")]
+    pub config: PathBuf,
+
+    /// Postgres connection string, overrides the database section of the configuration.
+    #[arg(long, global = true, env = "DATABASE_URL", hide_env_values = true)]
+    pub database_url: Option<String>,
+
+    /// Runs the ingestion mode from the configuration when no command is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Fetch the latest AIS messages once and write them to the database.
+    ///
+    /// This is also the backfill: the API only takes the time to start from, so `--since` reaches
+    /// back as far as the API keeps messages and there are no earlier windows to page through.
+    Fetch(FetchArgs),
+    /// Consume the live AIS stream and write it in micro-batches.
+    Stream(StreamArgs),
+    /// Poll the latest AIS messages on an interval, resuming from the stored checkpoint.
+    Daemon(DaemonArgs),
+    /// Ingest NMEA sentences from a local AIS receiver over TCP or UDP.
+    Listen(ListenArgs),
+    /// Ingest a file of recorded messages.
+    Replay(ReplayArgs),
+    /// Export stored messages as JSON lines or NMEA sentences.
+    Export(ExportArgs),
+    /// Look up stored data.
+    #[command(subcommand)]
+    Query(QueryCommand),
+    /// Inspect and re-process messages that were left out of the ingestion.
+    #[command(subcommand)]
+    Rejected(RejectedCommand),
+    /// Print row counts and the state of the ingestion.
+    Stats,
+    /// Apply pending database migrations.
+    Migrate(MigrateArgs),
+    /// Create upcoming position partitions and apply the retention policy.
+    Partitions(PartitionsArgs),
+    /// Serve a mock of the BarentsWatch API, point the `api` settings at it to run offline.
+    MockServer(MockServerArgs),
+}
+
+#[derive(Args, Default)]
+pub struct FetchArgs {
+    /// Fetch messages newer than this RFC 3339 timestamp.
+    #[arg(long, conflicts_with = "lookback_hours")]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Fetch messages from the last N hours.
+    #[arg(long)]
+    pub lookback_hours: Option<i64>,
+
+    /// Only this vessel, repeat for more. The filter options replace the ones from the
+    /// configuration.
+    #[arg(long)]
+    pub mmsi: Vec<i64>,
+
+    /// Only vessels of this AIS ship type code, repeat for more.
+    #[arg(long = "ship-type")]
+    pub ship_types: Vec<i64>,
+
+    /// Only vessels flagged in this country, as an ISO 3166-1 alpha-3 code such as NOR.
+    #[arg(long = "country")]
+    pub country_codes: Vec<String>,
+
+    #[arg(long = "message-type", value_enum)]
+    pub message_types: Vec<MessageKindArg>,
+
+    /// Only messages inside this polygon, as corners such as "15,68 32,68 32,72 15,72".
+    #[arg(long)]
+    pub area: Option<Polygon>,
+
+    #[arg(long, value_enum)]
+    pub model_format: Option<ModelFormatArg>,
+}
+
+/// [`MessageKind`] as the filters take it.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum MessageKindArg {
+    Position,
+    Static,
+    Aton,
+}
+
+impl From<MessageKindArg> for MessageKind {
+    fn from(kind: MessageKindArg) -> Self {
+        match kind {
+            MessageKindArg::Position => MessageKind::Position,
+            MessageKindArg::Static => MessageKind::Static,
+            MessageKindArg::Aton => MessageKind::Aton,
+        }
+    }
+}
+
+/// [`ModelFormat`] as the filters take it.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ModelFormatArg {
+    Json,
+    /// A feature collection with the position as the geometry of each message.
+    Geojson,
+}
+
+impl From<ModelFormatArg> for ModelFormat {
+    fn from(format: ModelFormatArg) -> Self {
+        match format {
+            ModelFormatArg::Json => ModelFormat::Json,
+            ModelFormatArg::Geojson => ModelFormat::Geojson,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct StreamArgs {
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+
+    #[arg(long)]
+    pub batch_timeout_seconds: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    #[arg(long)]
+    pub interval_seconds: Option<u64>,
+
+    /// How far back to start when no checkpoint has been stored yet.
+    #[arg(long)]
+    pub initial_lookback_hours: Option<i64>,
+}
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    /// Only report the schema version and pending migrations.
+    #[arg(long)]
+    pub status: bool,
+
+    /// Mark migrations up to this version as applied without running them, for databases that
+    /// were created before the migrations were embedded.
+    #[arg(long, conflicts_with = "status")]
+    pub baseline: Option<i64>,
+}
+
+#[derive(Args)]
+pub struct PartitionsArgs {
+    /// Only list the existing partitions.
+    #[arg(long)]
+    pub list: bool,
+}
+
+#[derive(Args)]
+pub struct ListenArgs {
+    #[arg(long, value_enum)]
+    pub transport: Option<TransportArg>,
+
+    /// Address to connect to, or to listen on for `tcp-server` and `udp`.
+    #[arg(long)]
+    pub address: Option<String>,
+
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+
+    #[arg(long)]
+    pub batch_timeout_seconds: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    pub path: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = ReplayFormat::Json)]
+    pub format: ReplayFormat,
+
+    /// Messages written per request, defaults to the stream batch size.
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+
+    /// RFC 3339 receive time of the NMEA messages without a tag block timestamp. Without it they are
+    /// rejected, the time they are replayed at would pass them off as current.
+    #[arg(long)]
+    pub base_time: Option<DateTime<Utc>>,
+}
+
+/// [`NmeaTransport`] as `listen` takes it.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum TransportArg {
+    /// Connect to a receiver that serves sentences over TCP.
+    TcpClient,
+    /// Accept TCP connections from receivers that push sentences.
+    TcpServer,
+    /// Receive sentences as UDP datagrams.
+    Udp,
+}
+
+impl From<TransportArg> for NmeaTransport {
+    fn from(transport: TransportArg) -> Self {
+        match transport {
+            TransportArg::TcpClient => NmeaTransport::TcpClient,
+            TransportArg::TcpServer => NmeaTransport::TcpServer,
+            TransportArg::Udp => NmeaTransport::Udp,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayFormat {
+    /// JSON lines as written by `export`.
+    Json,
+    /// NMEA sentences, one per line.
+    Nmea,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Kind of message to export.
+    #[arg(value_enum)]
+    pub kind: ExportKind,
+
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    #[arg(long)]
+    pub until: Option<DateTime<Utc>>,
+
+    #[arg(long)]
+    pub mmsi: Option<i64>,
+
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    pub format: ExportFormat,
+
+    /// File to write to, defaults to stdout.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    Json,
+    /// `!AIVDM` sentences, one per line.
+    Nmea,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ExportKind {
+    Position,
+    Static,
+    Aton,
+}
+
+#[derive(Subcommand)]
+pub enum QueryCommand {
+    /// Show the registry entry, latest position and change history of a vessel.
+    Vessel { mmsi: i64 },
+    /// Print the last known position of every vessel as JSON lines.
+    Fleet {
+        /// Only vessels heard from since this RFC 3339 timestamp.
+        #[arg(long)]
+        since: Option<DateTime<Utc>>,
+    },
+    /// Print what was reported inside a bounding box as JSON lines.
+    Area {
+        #[arg(value_enum)]
+        kind: AreaKind,
+
+        /// The corners as min_longitude,min_latitude,max_longitude,max_latitude, such as
+        /// "15,68,32,72".
+        #[arg(long, allow_hyphen_values = true)]
+        bbox: BoundingBox,
+
+        #[command(flatten)]
+        filter: AreaFilterArgs,
+    },
+    /// Print what was reported within a distance of a point as JSON lines.
+    Near {
+        #[arg(value_enum)]
+        kind: AreaKind,
+
+        #[arg(long, allow_hyphen_values = true)]
+        latitude: f64,
+
+        #[arg(long, allow_hyphen_values = true)]
+        longitude: f64,
+
+        /// Distance from the point in meters.
+        #[arg(long)]
+        meters: f64,
+
+        #[command(flatten)]
+        filter: AreaFilterArgs,
+    },
+}
+
+/// What `query area` and `query near` look up.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum AreaKind {
+    /// Position reports, oldest first.
+    Position,
+    /// AtoN reports, oldest first.
+    Aton,
+    /// The last known position of each vessel.
+    Fleet,
+}
+
+#[derive(Args)]
+pub struct AreaFilterArgs {
+    /// Only reports from this RFC 3339 timestamp on.
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only reports before this RFC 3339 timestamp.
+    #[arg(long)]
+    pub until: Option<DateTime<Utc>>,
+
+    #[arg(long)]
+    pub mmsi: Option<i64>,
+}
+
+#[derive(Subcommand)]
+pub enum RejectedCommand {
+    /// Print rejected messages as JSON lines, oldest first.
+    List {
+        #[command(flatten)]
+        filter: RejectedFilterArgs,
+
+        /// Also list messages that were already re-processed.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Ingest rejected messages again, for after the cause of the rejection was fixed. Messages
+    /// that are rejected again are recorded as new rejections.
+    Reprocess {
+        #[command(flatten)]
+        filter: RejectedFilterArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct RejectedFilterArgs {
+    #[arg(long, value_enum)]
+    pub stage: Option<StageArg>,
+
+    /// Only messages rejected from this request.
+    #[arg(long)]
+    pub log_id: Option<Uuid>,
+
+    /// Only messages rejected since this RFC 3339 timestamp.
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    #[arg(long)]
+    pub limit: Option<i64>,
+}
+
+/// [`RejectionStage`] as the `rejected` commands take it.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum StageArg {
+    /// Messages with no or an unknown `type`, or that do not parse as their type.
+    Parse,
+    /// Messages with impossible values, rejected by validation in `reject` mode.
+    Validation,
+    /// Messages the database refused.
+    Insert,
+}
+
+impl From<StageArg> for RejectionStage {
+    fn from(stage: StageArg) -> Self {
+        match stage {
+            StageArg::Parse => RejectionStage::Parse,
+            StageArg::Validation => RejectionStage::Validation,
+            StageArg::Insert => RejectionStage::Insert,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct MockServerArgs {
+    #[arg(long, default_value = "127.0.0.1:8090")]
+    pub address: SocketAddr,
+
+    /// JSON lines served by the latest endpoint, defaults to the bundled fixtures.
+    #[arg(long)]
+    pub latest: Option<PathBuf>,
+
+    /// JSON lines sent on every connection to the stream, defaults to the bundled fixtures.
+    #[arg(long)]
+    pub stream: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 3600)]
+    pub token_expires_in: i64,
+
+    /// Answer a request with an error first, as `endpoint:status[:retry_after]` such as
+    /// `latest:503`. Repeat to script more failures, they are served in order.
+    #[arg(long = "fail")]
+    pub failures: Vec<ScriptedFailure>,
+}
@@ -1,6 +1,26 @@
+use crate::live_ais::query::{MessageKind, ModelFormat};
+use crate::live_ais::validation::ValidationMode;
+use std::path::{Path, PathBuf};
+
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
+    #[serde(default)]
+    pub ingestion: IngestionSettings,
+    #[serde(default)]
+    pub partitioning: PartitionSettings,
+    #[serde(default)]
+    pub nmea: NmeaSettings,
+    #[serde(default)]
+    pub sink: SinkSettings,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    #[serde(default)]
+    pub token: TokenSettings,
+    #[serde(default)]
+    pub api: ApiSettings,
+    #[serde(default)]
+    pub filter: FilterSettings,
 }
 
 #[derive(serde::Deserialize)]
@@ -10,6 +30,9 @@ pub struct DatabaseSettings {
     pub host: String,
     pub port: u16,
     pub database_name: String,
+    /// Apply pending migrations on startup instead of refusing to run.
+    #[serde(default)]
+    pub auto_migrate: bool,
 }
 
 impl DatabaseSettings {
@@ -21,12 +44,247 @@ impl DatabaseSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestionMode {
+    /// Fetch the latest messages once and exit.
+    #[default]
+    Latest,
+    /// Consume the live stream and write it in micro-batches.
+    Stream,
+    /// Poll the latest messages on an interval, resuming from the stored checkpoint.
+    Daemon,
+    /// Read NMEA sentences from a local receiver and write them in micro-batches.
+    Nmea,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct IngestionSettings {
+    pub mode: IngestionMode,
+    pub stream_batch_size: usize,
+    pub stream_batch_timeout_seconds: u64,
+    pub poll_interval_seconds: u64,
+    pub initial_lookback_hours: i64,
+    /// Rows written per `INSERT` statement.
+    pub insert_batch_size: usize,
+    /// Commit the request log and all of its messages in one transaction.
+    pub atomic: bool,
+    pub validation: ValidationMode,
+}
+
+impl Default for IngestionSettings {
+    fn default() -> Self {
+        IngestionSettings {
+            mode: IngestionMode::Latest,
+            stream_batch_size: 500,
+            stream_batch_timeout_seconds: 5,
+            poll_interval_seconds: 300,
+            initial_lookback_hours: 1,
+            insert_batch_size: 1000,
+            atomic: true,
+            validation: ValidationMode::Normalize,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionInterval {
+    Daily,
+    #[default]
+    Monthly,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    /// Detach expired partitions and keep them as tables in the `ais_archive` schema.
+    #[default]
+    Archive,
+    /// Detach and drop expired partitions.
+    Drop,
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct PartitionSettings {
+    /// Create position partitions and apply the retention policy while ingesting.
+    pub enabled: bool,
+    pub interval: PartitionInterval,
+    /// Number of partitions to create ahead of the current one.
+    pub premake: u32,
+    /// Partitions that ended more than this many days ago are archived or dropped, together with
+    /// the messages that old in the default partition. Kept forever when unset.
+    pub retention_days: Option<i64>,
+    pub retention_action: RetentionAction,
+}
+
+impl Default for PartitionSettings {
+    fn default() -> Self {
+        PartitionSettings {
+            enabled: true,
+            interval: PartitionInterval::Monthly,
+            premake: 2,
+            retention_days: None,
+            retention_action: RetentionAction::Archive,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NmeaTransport {
+    /// Connect to a receiver that serves sentences over TCP.
+    #[default]
+    TcpClient,
+    /// Accept TCP connections from receivers that push sentences.
+    TcpServer,
+    /// Receive sentences as UDP datagrams.
+    Udp,
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct NmeaSettings {
+    pub transport: NmeaTransport,
+    /// Address to connect to for `tcp_client`, to listen on otherwise.
+    pub address: String,
+    /// Delay before reconnecting after a `tcp_client` connection was lost.
+    pub reconnect_delay_seconds: u64,
+}
+
+impl Default for NmeaSettings {
+    fn default() -> Self {
+        NmeaSettings {
+            transport: NmeaTransport::TcpClient,
+            address: "127.0.0.1:10110".to_owned(),
+            reconnect_delay_seconds: 5,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+    #[default]
+    Postgres,
+    /// Append to JSON lines files, the ingestion then runs without a database.
+    JsonLines,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct SinkSettings {
+    pub kind: SinkKind,
+    /// Directory the `json_lines` sink writes to.
+    pub directory: PathBuf,
+}
+
+impl Default for SinkSettings {
+    fn default() -> Self {
+        SinkSettings {
+            kind: SinkKind::Postgres,
+            directory: PathBuf::from("data"),
+        }
+    }
+}
+
+/// How requests to the BarentsWatch API are retried.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct RetrySettings {
+    /// Attempts per request, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every retry after it.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_seconds: u64,
+    /// Time allowed for a token or latest request, the live stream is not limited.
+    pub request_timeout_seconds: u64,
+    /// Requests that failed in a row, retries included, before requests are paused.
+    pub circuit_breaker_threshold: u32,
+    /// How long requests are paused once the threshold was reached.
+    pub circuit_breaker_cooldown_seconds: u64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        RetrySettings {
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_seconds: 60,
+            request_timeout_seconds: 30,
+            circuit_breaker_threshold: 3,
+            circuit_breaker_cooldown_seconds: 600,
+        }
+    }
+}
+
+/// How access tokens for the BarentsWatch API are kept.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct TokenSettings {
+    /// A token is replaced this long before it expires, so no request is sent with a token that
+    /// expires on the way.
+    pub refresh_skew_seconds: u64,
+    /// File the token is kept in, so that short-lived invocations reuse it instead of fetching a
+    /// new one every run. The token is only kept in memory when unset.
+    pub cache_path: Option<PathBuf>,
+}
+
+impl Default for TokenSettings {
+    fn default() -> Self {
+        TokenSettings {
+            refresh_skew_seconds: 300,
+            cache_path: None,
+        }
+    }
+}
+
+/// Where the BarentsWatch API is reached, pointed elsewhere to run against a mock server.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiSettings {
+    /// Base of the `/v1/latest/ais` and `/v1/ais` endpoints.
+    pub base_url: String,
+    pub token_url: String,
+    /// Send filters to `/v1/latest/combined`. When unset, or when the server does not have that
+    /// endpoint, everything is fetched and the filters are applied here.
+    pub server_filters: bool,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        ApiSettings {
+            base_url: "https://live.ais.barentswatch.no".to_owned(),
+            token_url: "https://id.barentswatch.no/connect/token".to_owned(),
+            server_filters: true,
+        }
+    }
+}
+
+/// Which of the latest messages to fetch, every message when empty.
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub mmsi: Vec<i64>,
+    /// AIS ship type codes, such as 30 for fishing.
+    pub ship_types: Vec<i64>,
+    /// ISO 3166-1 alpha-3 codes of the flag, such as `NOR`.
+    pub country_codes: Vec<String>,
+    pub message_types: Vec<MessageKind>,
+    /// Corners of a polygon as `[longitude, latitude]`, in order.
+    pub area: Option<Vec<[f64; 2]>>,
+    pub model_format: ModelFormat,
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
+    get_configuration_from(Path::new("configuration.yaml"))
+}
+
+pub fn get_configuration_from(path: &Path) -> Result<Settings, config::ConfigError> {
     let settings = config::Config::builder()
-        .add_source(config::File::new(
-            "configuration.yaml",
-            config::FileFormat::Yaml,
-        ))
+        .add_source(config::File::from(path).format(config::FileFormat::Yaml))
         .build()?;
 
     settings.try_deserialize::<Settings>()
@@ -0,0 +1,128 @@
+use crate::live_ais::response_structs::AISPositionData;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::Serialize;
+use sqlx::types::Uuid;
+use sqlx::{query, query_as, Error, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct FleetPosition {
+    pub mmsi: Option<i64>,
+    pub msgtime: Option<DateTime<Utc>>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub course_over_ground: Option<f64>,
+    pub speed_over_ground: Option<f64>,
+    pub true_heading: Option<i64>,
+    pub navigational_status: Option<i64>,
+    pub name: Option<String>,
+    pub call_sign: Option<String>,
+    pub imo_number: Option<i64>,
+    pub ship_type: Option<i32>,
+    pub ship_length: Option<i32>,
+    pub ship_width: Option<i32>,
+    pub destination: Option<String>,
+}
+
+/// Moves `ais.latest_position` forward with a batch of position messages. A vessel's row is only
+/// replaced by a message with a newer msgtime, so late or replayed messages leave it alone.
+pub async fn upsert_latest_positions(
+    tx: &mut Transaction<'_, Postgres>,
+    position_data: &[AISPositionData],
+    log_id: Uuid,
+) -> Result<u64, Error> {
+    let mut latest: HashMap<i64, (DateTime<Utc>, &AISPositionData)> = HashMap::new();
+    for data in position_data {
+        let (Some(mmsi), Some(msgtime)) = (data.mmsi, data.msgtime) else {
+            continue;
+        };
+        match latest.get(&mmsi) {
+            Some((seen, _)) if *seen >= msgtime => {}
+            _ => {
+                latest.insert(mmsi, (msgtime, data));
+            }
+        }
+    }
+    if latest.is_empty() {
+        return Ok(0);
+    }
+
+    let positions: Vec<(i64, DateTime<Utc>, &AISPositionData)> = latest
+        .into_iter()
+        .map(|(mmsi, (msgtime, data))| (mmsi, msgtime, data))
+        .collect();
+    let mmsi: Vec<i64> = positions.iter().map(|(mmsi, _, _)| *mmsi).collect();
+    let msgtime: Vec<DateTime<Utc>> = positions.iter().map(|(_, msgtime, _)| *msgtime).collect();
+    let message_type: Vec<Option<i64>> = positions.iter().map(|(_, _, data)| data.message_type).collect();
+    let ais_class: Vec<Option<String>> = positions.iter().map(|(_, _, data)| data.ais_class.clone()).collect();
+    let latitude: Vec<Option<f64>> = positions.iter().map(|(_, _, data)| data.latitude).collect();
+    let longitude: Vec<Option<f64>> = positions.iter().map(|(_, _, data)| data.longitude).collect();
+    let course_over_ground: Vec<Option<f64>> = positions.iter().map(|(_, _, data)| data.course_over_ground).collect();
+    let speed_over_ground: Vec<Option<f64>> = positions.iter().map(|(_, _, data)| data.speed_over_ground).collect();
+    let true_heading: Vec<Option<i64>> = positions.iter().map(|(_, _, data)| data.true_heading).collect();
+    let rate_of_turn: Vec<Option<i64>> = positions.iter().map(|(_, _, data)| data.rate_of_turn).collect();
+    let navigational_status: Vec<Option<i64>> = positions.iter().map(|(_, _, data)| data.navigational_status.map(i64::from)).collect();
+
+    let rows_affected = query!(
+        "INSERT INTO ais.latest_position (
+            mmsi, msgtime, message_type, ais_class, latitude, longitude, course_over_ground, speed_over_ground,
+            true_heading, rate_of_turn, navigational_status, log_id
+        ) SELECT *, $12 FROM UNNEST(
+            $1::bigint[], $2::timestamptz[], $3::bigint[], $4::varchar[], $5::float8[], $6::float8[],
+            $7::float8[], $8::float8[], $9::bigint[], $10::bigint[], $11::bigint[]
+        )
+        ON CONFLICT (mmsi) DO UPDATE SET
+            msgtime = EXCLUDED.msgtime,
+            message_type = EXCLUDED.message_type,
+            ais_class = EXCLUDED.ais_class,
+            latitude = EXCLUDED.latitude,
+            longitude = EXCLUDED.longitude,
+            course_over_ground = EXCLUDED.course_over_ground,
+            speed_over_ground = EXCLUDED.speed_over_ground,
+            true_heading = EXCLUDED.true_heading,
+            rate_of_turn = EXCLUDED.rate_of_turn,
+            navigational_status = EXCLUDED.navigational_status,
+            log_id = EXCLUDED.log_id,
+            updated_at = NOW()
+        WHERE EXCLUDED.msgtime > latest_position.msgtime",
+        &mmsi as _, &msgtime as _, &message_type as _, &ais_class as _, &latitude as _,
+        &longitude as _, &course_over_ground as _, &speed_over_ground as _, &true_heading as _, &rate_of_turn as _,
+        &navigational_status as _, log_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected)
+}
+
+pub async fn get_fleet_position(db_pool: &PgPool, mmsi: i64) -> Result<Option<FleetPosition>, Error> {
+    query_as!(
+        FleetPosition,
+        "SELECT mmsi, msgtime, latitude, longitude, course_over_ground, speed_over_ground, true_heading,
+            navigational_status, name, call_sign, imo_number, ship_type, ship_length, ship_width, destination
+        FROM ais.fleet_picture
+        WHERE mmsi = $1",
+        mmsi
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Every vessel's last known position, optionally only those heard from since `since`.
+pub fn get_fleet_picture<'a>(
+    db_pool: &'a PgPool,
+    since: Option<DateTime<Utc>>,
+) -> BoxStream<'a, Result<FleetPosition, Error>> {
+    query_as!(
+        FleetPosition,
+        "SELECT mmsi, msgtime, latitude, longitude, course_over_ground, speed_over_ground, true_heading,
+            navigational_status, name, call_sign, imo_number, ship_type, ship_length, ship_width, destination
+        FROM ais.fleet_picture
+        WHERE ($1::timestamptz IS NULL OR msgtime >= $1)
+        ORDER BY mmsi",
+        since
+    )
+    .fetch(db_pool)
+}
@@ -0,0 +1,173 @@
+use log::info;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use thiserror::Error;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("migration error: {0}")]
+    Migration(#[from] MigrateError),
+
+    #[error("migration {0} failed part way through and has to be repaired by hand")]
+    Dirty(i64),
+
+    #[error("database has migrations {0:?} applied that this binary does not know about")]
+    UnknownMigrations(Vec<i64>),
+
+    #[error("migrations {0:?} were changed after they were applied to the database")]
+    ModifiedMigrations(Vec<i64>),
+
+    #[error("database schema is behind, pending migrations: {0:?}. Run `barents migrate`")]
+    PendingMigrations(Vec<i64>),
+}
+
+#[derive(Debug)]
+pub struct SchemaStatus {
+    pub current_version: Option<i64>,
+    pub latest_version: Option<i64>,
+    pub pending: Vec<i64>,
+    pub unknown: Vec<i64>,
+    pub modified: Vec<i64>,
+    pub dirty: Option<i64>,
+}
+
+impl SchemaStatus {
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty() && !self.is_incompatible()
+    }
+
+    pub fn is_incompatible(&self) -> bool {
+        self.dirty.is_some() || !self.unknown.is_empty() || !self.modified.is_empty()
+    }
+}
+
+/// Compares the migrations embedded in the binary with the ones recorded in the database.
+pub async fn schema_status(db_pool: &PgPool) -> Result<SchemaStatus, SchemaError> {
+    let mut conn = db_pool.acquire().await?;
+    let (has_migrations_table,): (bool,) =
+        sqlx::query_as("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(&mut conn)
+            .await?;
+
+    let (applied, dirty) = if has_migrations_table {
+        (
+            conn.list_applied_migrations().await?,
+            conn.dirty_version().await?,
+        )
+    } else {
+        (Vec::new(), None)
+    };
+
+    let embedded: HashMap<i64, &[u8]> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| (migration.version, migration.checksum.as_ref()))
+        .collect();
+    let applied_versions: HashMap<i64, &[u8]> = applied
+        .iter()
+        .map(|migration| (migration.version, migration.checksum.as_ref()))
+        .collect();
+
+    let mut pending: Vec<i64> = embedded
+        .keys()
+        .filter(|version| !applied_versions.contains_key(version))
+        .copied()
+        .collect();
+    pending.sort_unstable();
+    let mut unknown: Vec<i64> = applied_versions
+        .keys()
+        .filter(|version| !embedded.contains_key(version))
+        .copied()
+        .collect();
+    unknown.sort_unstable();
+    let mut modified: Vec<i64> = applied_versions
+        .iter()
+        .filter(|(version, checksum)| {
+            embedded
+                .get(version)
+                .is_some_and(|embedded_checksum| embedded_checksum != *checksum)
+        })
+        .map(|(version, _)| *version)
+        .collect();
+    modified.sort_unstable();
+
+    Ok(SchemaStatus {
+        current_version: applied_versions.keys().max().copied(),
+        latest_version: embedded.keys().max().copied(),
+        pending,
+        unknown,
+        modified,
+        dirty,
+    })
+}
+
+/// Applies every pending migration and returns the resulting status.
+pub async fn run_migrations(db_pool: &PgPool) -> Result<SchemaStatus, SchemaError> {
+    MIGRATOR.run(db_pool).await?;
+    let status = schema_status(db_pool).await?;
+    info!(
+        "Database schema is at version {:?}",
+        status.current_version
+    );
+
+    Ok(status)
+}
+
+/// Records the embedded migrations up to and including `version` as applied without running them,
+/// for databases that were set up by hand before the migrations were embedded.
+pub async fn baseline_migrations(db_pool: &PgPool, version: i64) -> Result<(), SchemaError> {
+    let mut conn = db_pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| migration.version <= version)
+    {
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) \
+          VALUES ($1, $2, TRUE, $3, 0) ON CONFLICT (version) DO NOTHING",
+        )
+        .bind(migration.version)
+        .bind(&*migration.description)
+        .bind(&*migration.checksum)
+        .execute(&mut conn)
+        .await?;
+        info!("Marked migration {} as applied", migration.version);
+    }
+
+    Ok(())
+}
+
+/// Refuses to continue against a schema this binary does not match. Pending migrations are applied
+/// when `auto_migrate` is set, otherwise they are reported as an error.
+pub async fn ensure_compatible_schema(
+    db_pool: &PgPool,
+    auto_migrate: bool,
+) -> Result<SchemaStatus, SchemaError> {
+    let status = schema_status(db_pool).await?;
+    if let Some(version) = status.dirty {
+        return Err(SchemaError::Dirty(version));
+    }
+    if !status.unknown.is_empty() {
+        return Err(SchemaError::UnknownMigrations(status.unknown));
+    }
+    if !status.modified.is_empty() {
+        return Err(SchemaError::ModifiedMigrations(status.modified));
+    }
+    if status.pending.is_empty() {
+        return Ok(status);
+    }
+    if !auto_migrate {
+        return Err(SchemaError::PendingMigrations(status.pending));
+    }
+
+    info!("Applying pending migrations {:?}", status.pending);
+    run_migrations(db_pool).await
+}
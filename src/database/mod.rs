@@ -1,2 +1,9 @@
 pub mod configuration;
+pub mod latest_position;
 pub mod postgres;
+pub mod queries;
+pub mod rejected_messages;
+pub mod spatial;
+pub mod migrations;
+pub mod partitions;
+pub mod vessels;
@@ -0,0 +1,208 @@
+use crate::database::configuration::{PartitionInterval, PartitionSettings, RetentionAction};
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeZone, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::{query, query_as, query_scalar, Error, PgPool};
+
+#[derive(Debug, Serialize)]
+pub struct PositionPartition {
+    pub partition_name: String,
+    pub range_start: DateTime<Utc>,
+    pub range_end: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PartitionReport {
+    pub created: Vec<String>,
+    pub archived: Vec<String>,
+    pub dropped: Vec<String>,
+}
+
+fn partition_start(interval: PartitionInterval, time: DateTime<Utc>) -> NaiveDate {
+    let date = time.date_naive();
+    match interval {
+        PartitionInterval::Daily => date,
+        PartitionInterval::Monthly => date.with_day(1).unwrap_or(date),
+    }
+}
+
+fn next_partition_start(interval: PartitionInterval, start: NaiveDate) -> NaiveDate {
+    match interval {
+        PartitionInterval::Daily => start.succ_opt().unwrap_or(start),
+        PartitionInterval::Monthly => start.checked_add_months(Months::new(1)).unwrap_or(start),
+    }
+}
+
+fn partition_name(interval: PartitionInterval, start: NaiveDate) -> String {
+    match interval {
+        PartitionInterval::Daily => format!("ais_position_data_p{}", start.format("%Y%m%d")),
+        PartitionInterval::Monthly => format!("ais_position_data_p{}", start.format("%Y%m")),
+    }
+}
+
+// The range of messages that expired in the default partition can span many intervals.
+fn expired_partition_name(start: NaiveDate, end: NaiveDate) -> String {
+    format!("ais_position_data_p{}_{}", start.format("%Y%m%d"), end.format("%Y%m%d"))
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+/// Creates the partitions from the oldest message still in the default partition up to `premake`
+/// partitions past the current one. Rows the default partition holds for a new range are moved
+/// into it. Returns the names of the partitions that were created.
+///
+/// With `retention_days` nothing is created for ranges the retention policy would detach right
+/// away, [`apply_position_retention`] takes messages that old out of the default partition.
+pub async fn create_position_partitions(
+    db_pool: &PgPool,
+    interval: PartitionInterval,
+    premake: u32,
+    retention_days: Option<i64>,
+) -> Result<Vec<String>, Error> {
+    let now = Utc::now();
+    let earliest = query_scalar!("SELECT MIN(msgtime) FROM ais.ais_position_data_default")
+        .fetch_one(db_pool)
+        .await?;
+
+    let mut earliest = earliest.map_or(now, |earliest| earliest.min(now));
+    if let Some(retention_days) = retention_days {
+        earliest = earliest.max(now - chrono::Duration::days(retention_days));
+    }
+    let mut start = partition_start(interval, earliest);
+    let mut last = partition_start(interval, now);
+    for _ in 0..premake {
+        last = next_partition_start(interval, last);
+    }
+
+    let mut created = Vec::new();
+    while start <= last {
+        let end = next_partition_start(interval, start);
+        let name = partition_name(interval, start);
+        let was_created = query_scalar!(
+            "SELECT ais.create_position_partition($1, $2, $3)",
+            name,
+            start_of_day(start),
+            start_of_day(end)
+        )
+        .fetch_one(db_pool)
+        .await?
+        .unwrap_or(false);
+        if was_created {
+            info!("Created position partition {}", name);
+            created.push(name);
+        }
+        start = end;
+    }
+
+    Ok(created)
+}
+
+/// Archives or drops every partition whose range ended more than `retention_days` days ago.
+/// Returns the names of the partitions that were detached. An archived partition gets a numbered
+/// suffix when ais_archive already has a table of that name.
+///
+/// Messages the default partition holds from before the first partition the policy keeps, such as
+/// history from before partitioning or late messages, are first moved into a partition of their own
+/// that is detached with the others.
+pub async fn apply_position_retention(
+    db_pool: &PgPool,
+    interval: PartitionInterval,
+    retention_days: i64,
+    action: RetentionAction,
+) -> Result<Vec<String>, Error> {
+    let cutoff = Utc::now() - chrono::Duration::days(retention_days);
+    let mut detached = detach_partitions_before(db_pool, cutoff, action).await?;
+
+    let kept_from = partition_start(interval, cutoff);
+    let earliest = query_scalar!(
+        "SELECT MIN(msgtime) FROM ais.ais_position_data_default WHERE msgtime < $1",
+        start_of_day(kept_from)
+    )
+    .fetch_one(db_pool)
+    .await?;
+    if let Some(earliest) = earliest {
+        let start = partition_start(interval, earliest);
+        let name = expired_partition_name(start, kept_from);
+        let was_created = query_scalar!(
+            "SELECT ais.create_position_partition($1, $2, $3)",
+            name,
+            start_of_day(start),
+            start_of_day(kept_from)
+        )
+        .fetch_one(db_pool)
+        .await?
+        .unwrap_or(false);
+        if was_created {
+            detached.extend(detach_partitions_before(db_pool, cutoff, action).await?);
+        } else {
+            warn!("Expired messages in the default partition overlap partition {}, they are kept", name);
+        }
+    }
+
+    Ok(detached)
+}
+
+async fn detach_partitions_before(
+    db_pool: &PgPool,
+    cutoff: DateTime<Utc>,
+    action: RetentionAction,
+) -> Result<Vec<String>, Error> {
+    let expired = query_scalar!(
+        "SELECT partition_name FROM ais.position_partitions WHERE range_end <= $1 ORDER BY range_start",
+        cutoff
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    for name in &expired {
+        query!(
+            "SELECT ais.detach_position_partition($1, $2)",
+            name,
+            action == RetentionAction::Archive
+        )
+        .execute(db_pool)
+        .await?;
+        match action {
+            RetentionAction::Archive => info!("Archived position partition {} to ais_archive", name),
+            RetentionAction::Drop => info!("Dropped position partition {}", name),
+        }
+    }
+
+    Ok(expired)
+}
+
+/// Creates upcoming partitions and applies the retention policy, if one is configured.
+pub async fn maintain_position_partitions(
+    db_pool: &PgPool,
+    settings: &PartitionSettings,
+) -> Result<PartitionReport, Error> {
+    let mut report = PartitionReport {
+        created: create_position_partitions(db_pool, settings.interval, settings.premake, settings.retention_days)
+            .await?,
+        ..Default::default()
+    };
+    if let Some(retention_days) = settings.retention_days {
+        let detached =
+            apply_position_retention(db_pool, settings.interval, retention_days, settings.retention_action).await?;
+        match settings.retention_action {
+            RetentionAction::Archive => report.archived = detached,
+            RetentionAction::Drop => report.dropped = detached,
+        }
+    }
+
+    Ok(report)
+}
+
+pub async fn get_position_partitions(db_pool: &PgPool) -> Result<Vec<PositionPartition>, Error> {
+    query_as!(
+        PositionPartition,
+        "SELECT partition_name, range_start, range_end, created_at
+        FROM ais.position_partitions
+        ORDER BY range_start"
+    )
+    .fetch_all(db_pool)
+    .await
+}
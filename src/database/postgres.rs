@@ -1,236 +1,621 @@
-use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData};
+use crate::database::latest_position::upsert_latest_positions;
+use crate::database::rejected_messages::insert_rejected_messages;
+use crate::database::vessels::upsert_vessels;
+use crate::live_ais::response_structs::{
+    AISAtonData, AISPositionData, AISStaticData, RejectedMessage, RejectionStage, SplitAISMessages,
+};
+use crate::live_ais::validation::{QualityReport, ValidationIssue};
 use chrono::{DateTime, Utc};
-use log::debug;
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use serde::Serialize;
 use sqlx::types::Uuid;
-use sqlx::{query, Error, PgPool};
-
-pub struct DbMethods {}
-
-// impl DbMethods {
-    // pub fn new(connection_string: String) -> Self {
-    //     return BarentsPostgresConnection {
-    //         connection_string,
-    //         pool: PgPool::connect(&connection_string).await.unwrap(),
-    //     };
-    // }
-
-    // pub async fn init(connection_string: String) -> Result<Self, sqlx::Error> {
-    //     let pool = PgPool::connect(&connection_string).await?;
-    //     Ok(BarentsPostgresConnection {
-    //         connection_string,
-    //         pool,
-    //     })
-    // }
-    // pub async fn insert_request_log(
-    //     &self,
-    //     db_pool: PgPool,
-    //     api_endpoint: String,
-    //     status_code: i32,
-    //     number_of_messages: i64,
-    // ) -> Result<Uuid, Error> {
-    //     // let pool = PgPool::connect(&self.connection_string).await?;
-    //     let mut tx = db_pool.begin().await?;
-    //     let id = query!(
-    //         "INSERT INTO \
-    //       log.requests (api_endpoint, status_code, number_of_messages_received) \
-    //       VALUES ($1, $2, $3) RETURNING id;",
-    //         api_endpoint,
-    //         status_code,
-    //         number_of_messages
-    //     )
-    //     .fetch_one(&mut tx)
-    //     .await?
-    //     .id;
-    //     tx.commit().await?;
-    //
-    //     Ok(id)
-    // }
-    //
-    // pub async fn insert_static_data(
-    //     &self,
-    //     db_pool: PgPool,
-    //     static_data: Vec<AISStaticData>,
-    //     log_id: &Uuid,
-    // ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    //     // let pool = PgPool::connect(&self.connection_string).await?;
-    //     let tx = db_pool.begin().await?;
-    //
-    //     for data in static_data {
-    //         sqlx::query!(
-    //             "INSERT INTO ais.ais_static_data (
-    //                 type_field, message_type, mmsi, msgtime, imo_number, call_sign, destination, eta, name, draught,
-    //                 ship_length, ship_width, ship_type, dimension_a, dimension_b, dimension_c, dimension_d,
-    //                 position_fixing_device_type, report_class, log_id
-    //             ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)",
-    //             data.type_field, data.message_type, data.mmsi, convert_to_datetime_option(data.msgtime), data.imo_number, data.call_sign,
-    //             data.destination, data.eta, data.name, data.draught, data.ship_length, data.ship_width,
-    //             data.ship_type, data.dimension_a, data.dimension_b, data.dimension_c, data.dimension_d,
-    //             data.position_fixing_device_type, data.report_class, log_id
-    //         ).execute(&db_pool).await.unwrap();
-    //     }
-    //     tx.commit().await?;
-    //     Ok(())
-    // }
-
-    // pub async fn insert_aton_data(
-    //     &self,
-    //     db_pool: PgPool,
-    //     aton_data: Vec<AISAtonData>,
-    //     log_id: &Uuid,
-    // ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    //     // let pool = PgPool::connect(&self.connection_string).await?;
-    //     let tx = db_pool.begin().await?;
-    //
-    //     for data in aton_data {
-    //         sqlx::query!(
-    //             "INSERT INTO ais.ais_aton_data (
-    //                 type_field, message_type, mmsi, msgtime, dimension_a, dimension_b, dimension_c, dimension_d,
-    //                 type_of_aids_to_navigation, latitude, longitude, name, type_of_electronic_fixing_device, log_id
-    //             ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
-    //             data.type_field, data.message_type, data.mmsi, convert_to_datetime_option(data.msgtime), data.dimension_a, data.dimension_b,
-    //             data.dimension_c, data.dimension_d, data.type_of_aids_to_navigation, data.latitude,
-    //             data.longitude, data.name, data.type_of_electronic_fixing_device, log_id
-    //         ).execute(&db_pool).await?;
-    //     }
-    //     tx.commit().await?;
-    //
-    //     Ok(())
-    // }
-
-    // pub async fn insert_position_data(
-    //     &self,
-    //     db_pool: PgPool,
-    //     position_data: Vec<AISPositionData>,
-    //     log_id: &Uuid,
-    // ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    //     // let pool = PgPool::connect(&self.connection_string).await?;
-    //     let tx = db_pool.begin().await?;
-    //
-    //     for data in position_data {
-    //         sqlx::query!(
-    //             "INSERT INTO ais.ais_position_data (
-    //                 type_field, message_type, course_over_ground, ais_class, altitude, latitude, longitude,
-    //                 navigational_status, rate_of_turn, speed_over_ground, true_heading, mmsi, msgtime, log_id
-    //             ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
-    //             data.type_field, data.message_type, data.course_over_ground, data.ais_class, data.altitude,
-    //             data.latitude, data.longitude, data.navigational_status, data.rate_of_turn, data.speed_over_ground,
-    //             data.true_heading, data.mmsi, convert_to_datetime_option(data.msgtime), log_id
-    //         ).execute(&db_pool).await?;
-    //     }
-    //     tx.commit().await?;
-    //
-    //     Ok(())
-    // }
-// }
-
-fn convert_to_datetime_option(input: Option<String>) -> Option<DateTime<Utc>> {
-    match input {
-        Some(date_string) => match DateTime::parse_from_rfc3339(&date_string) {
-            Ok(date) => Some(date.with_timezone(&Utc)), // If successful parsing, convert to DateTime<Utc> and wrap in Some
-            Err(_) => None,                             // If parsing fails, return None
-        },
-        None => None, // If input is None, return None
+use sqlx::{query, Acquire, Error, PgConnection, PgExecutor, PgPool, Postgres, Transaction};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestOutcome {
+    Success,
+    Partial,
+    Failed,
+}
+
+impl RequestOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestOutcome::Success => "success",
+            RequestOutcome::Partial => "partial",
+            RequestOutcome::Failed => "failed",
+        }
     }
 }
 
-// Silly attempt
-pub async fn insert_aton_data(
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestSource {
+    /// The BarentsWatch live AIS API.
+    BarentsWatch,
+    /// NMEA sentences read from a TCP connection to or from a local receiver.
+    NmeaTcp,
+    /// NMEA sentences received as UDP datagrams.
+    NmeaUdp,
+    /// NMEA sentences read from a file.
+    NmeaFile,
+    /// A JSON lines file of previously received messages.
+    Replay,
+    /// Messages from log.rejected_messages ingested again.
+    Reprocess,
+}
+
+impl RequestSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestSource::BarentsWatch => "barentswatch",
+            RequestSource::NmeaTcp => "nmea_tcp",
+            RequestSource::NmeaUdp => "nmea_udp",
+            RequestSource::NmeaFile => "nmea_file",
+            RequestSource::Replay => "replay",
+            RequestSource::Reprocess => "reprocess",
+        }
+    }
+}
+
+/// A request against the API, or a batch read from another source, as it is recorded in
+/// log.requests.
+pub struct RequestLog {
+    pub source: RequestSource,
+    pub api_endpoint: String,
+    /// HTTP status of the request, `None` for sources that are not HTTP.
+    pub status_code: Option<i32>,
+    pub number_of_messages: i64,
+    /// Messages of a type this crate does not know, received but not stored.
+    pub unknown_messages: i64,
+    /// Reports of the messages that failed validation, written to log.validation_issues.
+    pub quality_reports: Vec<QualityReport>,
+}
+
+pub struct IngestionReport {
+    pub log_id: Uuid,
+    pub outcome: RequestOutcome,
+    pub error: Option<String>,
+    pub metrics: InsertMetrics,
+}
+
+#[derive(Default)]
+pub struct InsertMetrics {
+    pub static_rows: u64,
+    pub aton_rows: u64,
+    pub position_rows: u64,
+    /// Messages that were already stored by an earlier request.
+    pub duplicates_skipped: u64,
+    /// Messages written to log.rejected_messages instead, whatever the stage they failed at.
+    pub rejected_messages: u64,
+    pub vessels_upserted: u64,
+    pub latest_positions_updated: u64,
+    pub elapsed: Duration,
+}
+
+impl InsertMetrics {
+    pub fn total_rows(&self) -> u64 {
+        self.static_rows + self.aton_rows + self.position_rows
+    }
+
+    pub fn rows_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.total_rows() as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Writes all three message kinds and the rejected messages in a single transaction, `batch_size`
+/// rows per statement.
+///
+/// The columns are bound as arrays and expanded with `UNNEST`, which keeps the round trips down to
+/// one per chunk. The arrays bypass the macro type check (`as _`) because it does not accept
+/// arrays of nullable elements.
+pub async fn insert_ais_data(
     db_pool: PgPool,
-    aton_data: Vec<AISAtonData>,
+    messages: &SplitAISMessages,
     log_id: Uuid,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // let pool = PgPool::connect(&self.connection_string).await?;
-    let tx = db_pool.begin().await?;
-
-    for data in aton_data {
-        sqlx::query!(
-                "INSERT INTO ais.ais_aton_data (
-                    type_field, message_type, mmsi, msgtime, dimension_a, dimension_b, dimension_c, dimension_d,
-                    type_of_aids_to_navigation, latitude, longitude, name, type_of_electronic_fixing_device, log_id
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
-                data.type_field, data.message_type, data.mmsi, convert_to_datetime_option(data.msgtime), data.dimension_a, data.dimension_b,
-                data.dimension_c, data.dimension_d, data.type_of_aids_to_navigation, data.latitude,
-                data.longitude, data.name, data.type_of_electronic_fixing_device, log_id
-            ).execute(&db_pool).await?;
-    }
+    batch_size: usize,
+) -> Result<InsertMetrics, Error> {
+    let mut tx = db_pool.begin().await?;
+    let metrics = write_ais_data(&mut tx, messages, log_id, batch_size).await?;
+    record_duplicates_skipped(&mut tx, log_id, metrics.duplicates_skipped).await?;
     tx.commit().await?;
+    log_metrics(&metrics);
 
-    Ok(())
+    Ok(metrics)
 }
 
-pub async fn insert_position_data(
-    db_pool: PgPool,
-    position_data: Vec<AISPositionData>,
+async fn write_ais_data(
+    tx: &mut Transaction<'_, Postgres>,
+    messages: &SplitAISMessages,
     log_id: Uuid,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // let pool = PgPool::connect(&self.connection_string).await?;
-    let tx = db_pool.begin().await?;
-
-    for data in position_data {
-        sqlx::query!(
-                "INSERT INTO ais.ais_position_data (
-                    type_field, message_type, course_over_ground, ais_class, altitude, latitude, longitude,
-                    navigational_status, rate_of_turn, speed_over_ground, true_heading, mmsi, msgtime, log_id
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
-                data.type_field, data.message_type, data.course_over_ground, data.ais_class, data.altitude,
-                data.latitude, data.longitude, data.navigational_status, data.rate_of_turn, data.speed_over_ground,
-                data.true_heading, data.mmsi, convert_to_datetime_option(data.msgtime), log_id
-            ).execute(&db_pool).await?;
+    batch_size: usize,
+) -> Result<InsertMetrics, Error> {
+    let start = Instant::now();
+    let static_rows = insert_static_data(tx, &messages.static_data, log_id, batch_size).await?;
+    let vessels_upserted = upsert_vessels(tx, &static_rows.accepted(&messages.static_data), log_id).await?;
+    let aton_rows = insert_aton_data(tx, &messages.aton_data, log_id, batch_size).await?;
+    let position_rows = insert_position_data(tx, &messages.position_data, log_id, batch_size).await?;
+    let latest_positions_updated =
+        upsert_latest_positions(tx, &position_rows.accepted(&messages.position_data), log_id).await?;
+
+    let mut rejected_messages = insert_rejected_messages(&mut *tx, log_id, &messages.rejected).await?;
+    for inserted in [&static_rows, &aton_rows, &position_rows] {
+        rejected_messages += insert_rejected_messages(&mut *tx, log_id, &inserted.rejected).await?;
     }
-    tx.commit().await?;
+    let received = (messages.static_data.len() + messages.aton_data.len() + messages.position_data.len()) as u64;
+    let refused = (static_rows.rejected.len() + aton_rows.rejected.len() + position_rows.rejected.len()) as u64;
 
-    Ok(())
+    Ok(InsertMetrics {
+        static_rows: static_rows.rows,
+        aton_rows: aton_rows.rows,
+        position_rows: position_rows.rows,
+        duplicates_skipped: received - refused - (static_rows.rows + aton_rows.rows + position_rows.rows),
+        rejected_messages,
+        vessels_upserted,
+        latest_positions_updated,
+        elapsed: start.elapsed(),
+    })
 }
 
-pub async fn insert_static_data(
-    db_pool: PgPool,
-    static_data: Vec<AISStaticData>,
+pub(crate) fn log_metrics(metrics: &InsertMetrics) {
+    info!(
+        "Committed {} static, {} aton and {} position rows, skipped {} duplicates, rejected {} messages in {:.2?} ({:.0} rows/s)",
+        metrics.static_rows,
+        metrics.aton_rows,
+        metrics.position_rows,
+        metrics.duplicates_skipped,
+        metrics.rejected_messages,
+        metrics.elapsed,
+        metrics.rows_per_second()
+    );
+}
+
+/// What one of the `insert_*_data` functions wrote.
+#[derive(Default)]
+pub struct InsertedRows {
+    pub rows: u64,
+    /// Messages the database refused, they are not written to log.rejected_messages yet.
+    pub rejected: Vec<RejectedMessage>,
+    /// Rows of the table kept up to date from the messages, `ais.vessels` or `ais.latest_position`.
+    pub derived_rows: u64,
+    // Positions of the refused messages in the data that was written.
+    refused: Vec<usize>,
+}
+
+impl InsertedRows {
+    /// A write that refused nothing and stored `rows` messages.
+    pub fn new(rows: u64) -> Self {
+        InsertedRows {
+            rows,
+            ..InsertedRows::default()
+        }
+    }
+
+    /// The messages of `data` that were not refused, for the writes that follow the insert.
+    pub fn accepted<'a, T: Clone>(&self, data: &'a [T]) -> Cow<'a, [T]> {
+        if self.refused.is_empty() {
+            return Cow::Borrowed(data);
+        }
+        Cow::Owned(
+            data.iter()
+                .enumerate()
+                .filter(|(index, _)| !self.refused.contains(index))
+                .map(|(_, message)| message.clone())
+                .collect(),
+        )
+    }
+}
+
+// The statement that writes one chunk of a message kind.
+trait InsertChunk: Serialize + Sized + Sync {
+    fn insert_chunk<'a>(connection: &'a mut PgConnection, chunk: &'a [Self], log_id: Uuid) -> BoxFuture<'a, Result<u64, Error>>;
+}
+
+impl InsertChunk for AISStaticData {
+    fn insert_chunk<'a>(connection: &'a mut PgConnection, chunk: &'a [Self], log_id: Uuid) -> BoxFuture<'a, Result<u64, Error>> {
+        Box::pin(insert_static_chunk(connection, chunk, log_id))
+    }
+}
+
+impl InsertChunk for AISAtonData {
+    fn insert_chunk<'a>(connection: &'a mut PgConnection, chunk: &'a [Self], log_id: Uuid) -> BoxFuture<'a, Result<u64, Error>> {
+        Box::pin(insert_aton_chunk(connection, chunk, log_id))
+    }
+}
+
+impl InsertChunk for AISPositionData {
+    fn insert_chunk<'a>(connection: &'a mut PgConnection, chunk: &'a [Self], log_id: Uuid) -> BoxFuture<'a, Result<u64, Error>> {
+        Box::pin(insert_position_chunk(connection, chunk, log_id))
+    }
+}
+
+// Each chunk is written in a savepoint. When the database refuses the statement the chunk is rolled
+// back and written again row by row, so that only the offending messages are rejected instead of
+// the whole batch. Other errors, such as a lost connection, still fail the write.
+async fn insert_isolating<T: InsertChunk>(
+    tx: &mut Transaction<'_, Postgres>,
+    data: &[T],
     log_id: Uuid,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // let pool = PgPool::connect(&self.connection_string).await?;
-    let tx = db_pool.begin().await?;
-
-    for data in static_data {
-        sqlx::query!(
-            "INSERT INTO ais.ais_static_data (
-                type_field, message_type, mmsi, msgtime, imo_number, call_sign, destination, eta, name, draught,
-                ship_length, ship_width, ship_type, dimension_a, dimension_b, dimension_c, dimension_d,
-                position_fixing_device_type, report_class, log_id
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)",
-            data.type_field, data.message_type, data.mmsi, convert_to_datetime_option(data.msgtime), data.imo_number, data.call_sign,
-            data.destination, data.eta, data.name, data.draught, data.ship_length, data.ship_width,
-            data.ship_type, data.dimension_a, data.dimension_b, data.dimension_c, data.dimension_d,
-            data.position_fixing_device_type, data.report_class, log_id
-        ).execute(&db_pool).await.unwrap();
+    batch_size: usize,
+) -> Result<InsertedRows, Error> {
+    let mut inserted = InsertedRows::default();
+
+    let batch_size = batch_size.max(1);
+    for (chunk_index, chunk) in data.chunks(batch_size).enumerate() {
+        let mut savepoint = tx.begin().await?;
+        match T::insert_chunk(&mut savepoint, chunk, log_id).await {
+            Ok(rows) => {
+                savepoint.commit().await?;
+                inserted.rows += rows;
+                continue;
+            }
+            Err(Error::Database(error)) => {
+                savepoint.rollback().await?;
+                debug!("Chunk of {} rows refused ({}), inserting them one by one", chunk.len(), error);
+            }
+            Err(error) => return Err(error),
+        }
+
+        for (row_index, row) in chunk.iter().enumerate() {
+            let mut savepoint = tx.begin().await?;
+            match T::insert_chunk(&mut savepoint, std::slice::from_ref(row), log_id).await {
+                Ok(rows) => {
+                    savepoint.commit().await?;
+                    inserted.rows += rows;
+                }
+                Err(Error::Database(error)) => {
+                    savepoint.rollback().await?;
+                    inserted.refused.push(chunk_index * batch_size + row_index);
+                    inserted
+                        .rejected
+                        .push(RejectedMessage::new(RejectionStage::Insert, error.to_string(), row));
+                }
+                Err(error) => return Err(error),
+            }
+        }
     }
-    tx.commit().await?;
-    debug!("Committed static data.");
-    Ok(())
+    if !inserted.rejected.is_empty() {
+        warn!("The database refused {} messages", inserted.rejected.len());
+    }
+
+    Ok(inserted)
 }
 
-pub async fn insert_request_log(
-    db_pool: PgPool,
-    api_endpoint: &String,
-    status_code: &i32,
-    number_of_messages: &i64,
+pub async fn insert_aton_data(
+    tx: &mut Transaction<'_, Postgres>,
+    aton_data: &[AISAtonData],
+    log_id: Uuid,
+    batch_size: usize,
+) -> Result<InsertedRows, Error> {
+    insert_isolating(tx, aton_data, log_id, batch_size).await
+}
+
+async fn insert_aton_chunk(connection: &mut PgConnection, chunk: &[AISAtonData], log_id: Uuid) -> Result<u64, Error> {
+    let type_field: Vec<Option<String>> = chunk.iter().map(|data| data.type_field.clone()).collect();
+    let message_type: Vec<Option<i64>> = chunk.iter().map(|data| data.message_type).collect();
+    let mmsi: Vec<Option<i64>> = chunk.iter().map(|data| data.mmsi).collect();
+    let msgtime: Vec<Option<DateTime<Utc>>> = chunk.iter().map(|data| data.msgtime).collect();
+    let dimension_a: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_a).collect();
+    let dimension_b: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_b).collect();
+    let dimension_c: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_c).collect();
+    let dimension_d: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_d).collect();
+    let type_of_aids_to_navigation: Vec<Option<i64>> = chunk.iter().map(|data| data.type_of_aids_to_navigation.map(i64::from)).collect();
+    let latitude: Vec<Option<f64>> = chunk.iter().map(|data| data.latitude).collect();
+    let longitude: Vec<Option<f64>> = chunk.iter().map(|data| data.longitude).collect();
+    let name: Vec<Option<String>> = chunk.iter().map(|data| data.name.clone()).collect();
+    let type_of_electronic_fixing_device: Vec<Option<i64>> = chunk.iter().map(|data| data.type_of_electronic_fixing_device.map(i64::from)).collect();
+
+    let rows_affected = query!(
+            "INSERT INTO ais.ais_aton_data (
+                type_field, message_type, mmsi, msgtime, dimension_a, dimension_b, dimension_c, dimension_d,
+                type_of_aids_to_navigation, latitude, longitude, name, type_of_electronic_fixing_device, log_id
+            ) SELECT *, $14 FROM UNNEST(
+                $1::varchar[], $2::bigint[], $3::bigint[], $4::timestamptz[], $5::int[], $6::int[], $7::int[],
+                $8::int[], $9::bigint[], $10::float8[], $11::float8[], $12::varchar[], $13::bigint[]
+            )
+            ON CONFLICT (mmsi, msgtime, message_type) DO NOTHING",
+            &type_field as _, &message_type as _, &mmsi as _, &msgtime as _, &dimension_a as _,
+            &dimension_b as _, &dimension_c as _, &dimension_d as _, &type_of_aids_to_navigation as _, &latitude as _,
+            &longitude as _, &name as _, &type_of_electronic_fixing_device as _, log_id
+        ).execute(connection).await?.rows_affected();
+
+    Ok(rows_affected)
+}
+
+pub async fn insert_position_data(
+    tx: &mut Transaction<'_, Postgres>,
+    position_data: &[AISPositionData],
+    log_id: Uuid,
+    batch_size: usize,
+) -> Result<InsertedRows, Error> {
+    insert_isolating(tx, position_data, log_id, batch_size).await
+}
+
+async fn insert_position_chunk(connection: &mut PgConnection, chunk: &[AISPositionData], log_id: Uuid) -> Result<u64, Error> {
+    let type_field: Vec<Option<String>> = chunk.iter().map(|data| data.type_field.clone()).collect();
+    let message_type: Vec<Option<i64>> = chunk.iter().map(|data| data.message_type).collect();
+    let course_over_ground: Vec<Option<f64>> = chunk.iter().map(|data| data.course_over_ground).collect();
+    let ais_class: Vec<Option<String>> = chunk.iter().map(|data| data.ais_class.clone()).collect();
+    let altitude: Vec<Option<f64>> = chunk.iter().map(|data| data.altitude).collect();
+    let latitude: Vec<Option<f64>> = chunk.iter().map(|data| data.latitude).collect();
+    let longitude: Vec<Option<f64>> = chunk.iter().map(|data| data.longitude).collect();
+    let navigational_status: Vec<Option<i64>> = chunk.iter().map(|data| data.navigational_status.map(i64::from)).collect();
+    let rate_of_turn: Vec<Option<i64>> = chunk.iter().map(|data| data.rate_of_turn).collect();
+    let speed_over_ground: Vec<Option<f64>> = chunk.iter().map(|data| data.speed_over_ground).collect();
+    let true_heading: Vec<Option<i64>> = chunk.iter().map(|data| data.true_heading).collect();
+    let mmsi: Vec<Option<i64>> = chunk.iter().map(|data| data.mmsi).collect();
+    let msgtime: Vec<Option<DateTime<Utc>>> = chunk.iter().map(|data| data.msgtime).collect();
+
+    let rows_affected = query!(
+            "INSERT INTO ais.ais_position_data (
+                type_field, message_type, course_over_ground, ais_class, altitude, latitude, longitude,
+                navigational_status, rate_of_turn, speed_over_ground, true_heading, mmsi, msgtime, log_id
+            ) SELECT *, $14 FROM UNNEST(
+                $1::varchar[], $2::bigint[], $3::float8[], $4::varchar[], $5::float8[], $6::float8[], $7::float8[],
+                $8::bigint[], $9::bigint[], $10::float8[], $11::bigint[], $12::bigint[], $13::timestamptz[]
+            )
+            ON CONFLICT (mmsi, msgtime, message_type) DO NOTHING",
+            &type_field as _, &message_type as _, &course_over_ground as _, &ais_class as _, &altitude as _,
+            &latitude as _, &longitude as _, &navigational_status as _, &rate_of_turn as _, &speed_over_ground as _,
+            &true_heading as _, &mmsi as _, &msgtime as _, log_id
+        ).execute(connection).await?.rows_affected();
+
+    Ok(rows_affected)
+}
+
+pub async fn insert_static_data(
+    tx: &mut Transaction<'_, Postgres>,
+    static_data: &[AISStaticData],
+    log_id: Uuid,
+    batch_size: usize,
+) -> Result<InsertedRows, Error> {
+    let inserted = insert_isolating(tx, static_data, log_id, batch_size).await?;
+    debug!("Inserted {} static data rows.", inserted.rows);
+
+    Ok(inserted)
+}
+
+async fn insert_static_chunk(connection: &mut PgConnection, chunk: &[AISStaticData], log_id: Uuid) -> Result<u64, Error> {
+    let type_field: Vec<Option<String>> = chunk.iter().map(|data| data.type_field.clone()).collect();
+    let message_type: Vec<Option<i64>> = chunk.iter().map(|data| data.message_type).collect();
+    let mmsi: Vec<Option<i64>> = chunk.iter().map(|data| data.mmsi).collect();
+    let msgtime: Vec<Option<DateTime<Utc>>> = chunk.iter().map(|data| data.msgtime).collect();
+    let imo_number: Vec<Option<i64>> = chunk.iter().map(|data| data.imo_number).collect();
+    let call_sign: Vec<Option<String>> = chunk.iter().map(|data| data.call_sign.clone()).collect();
+    let destination: Vec<Option<String>> = chunk.iter().map(|data| data.destination.clone()).collect();
+    let eta: Vec<Option<DateTime<Utc>>> = chunk
+        .iter()
+        .map(|data| data.eta.as_ref().zip(data.msgtime).and_then(|(eta, msgtime)| eta.resolve(msgtime)))
+        .collect();
+    let name: Vec<Option<String>> = chunk.iter().map(|data| data.name.clone()).collect();
+    let draught: Vec<Option<i32>> = chunk.iter().map(|data| data.draught).collect();
+    let ship_length: Vec<Option<i32>> = chunk.iter().map(|data| data.ship_length).collect();
+    let ship_width: Vec<Option<i32>> = chunk.iter().map(|data| data.ship_width).collect();
+    let ship_type: Vec<Option<i32>> = chunk.iter().map(|data| data.ship_type.and_then(|ship_type| i32::try_from(ship_type).ok())).collect();
+    let dimension_a: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_a).collect();
+    let dimension_b: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_b).collect();
+    let dimension_c: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_c).collect();
+    let dimension_d: Vec<Option<i32>> = chunk.iter().map(|data| data.dimension_d).collect();
+    let position_fixing_device_type: Vec<Option<i64>> = chunk.iter().map(|data| data.position_fixing_device_type.map(i64::from)).collect();
+    let report_class: Vec<Option<String>> = chunk.iter().map(|data| data.report_class.clone()).collect();
+
+    let rows_affected = query!(
+        "INSERT INTO ais.ais_static_data (
+            type_field, message_type, mmsi, msgtime, imo_number, call_sign, destination, eta, name, draught,
+            ship_length, ship_width, ship_type, dimension_a, dimension_b, dimension_c, dimension_d,
+            position_fixing_device_type, report_class, log_id
+        ) SELECT *, $20 FROM UNNEST(
+            $1::varchar[], $2::bigint[], $3::bigint[], $4::timestamptz[], $5::bigint[], $6::varchar[], $7::varchar[],
+            $8::timestamptz[], $9::varchar[], $10::int[], $11::int[], $12::int[], $13::int[], $14::int[], $15::int[],
+            $16::int[], $17::int[], $18::bigint[], $19::varchar[]
+        )
+        ON CONFLICT (mmsi, msgtime, message_type) DO NOTHING",
+        &type_field as _, &message_type as _, &mmsi as _, &msgtime as _, &imo_number as _,
+        &call_sign as _, &destination as _, &eta as _, &name as _, &draught as _,
+        &ship_length as _, &ship_width as _, &ship_type as _, &dimension_a as _, &dimension_b as _,
+        &dimension_c as _, &dimension_d as _, &position_fixing_device_type as _, &report_class as _, log_id
+    ).execute(connection).await?.rows_affected();
+
+    Ok(rows_affected)
+}
+
+// log.requests.status_message is a VARCHAR(200).
+fn truncate_status_message(message: &str) -> String {
+    message.chars().take(200).collect()
+}
+
+pub(crate) async fn insert_request_log_with_outcome(
+    executor: impl PgExecutor<'_>,
+    request: &RequestLog,
+    outcome: Option<RequestOutcome>,
+    status_message: Option<&str>,
 ) -> Result<Uuid, Error> {
-    // let pool = PgPool::connect(&self.connection_string).await?;
-    let mut tx = db_pool.begin().await?;
+    let issues: Vec<(&QualityReport, &ValidationIssue)> = request
+        .quality_reports
+        .iter()
+        .flat_map(|report| report.out_of_range().map(move |issue| (report, issue)))
+        .collect();
+    let message_kind: Vec<&str> = issues.iter().map(|(report, _)| report.message_kind).collect();
+    let mmsi: Vec<Option<i64>> = issues.iter().map(|(report, _)| report.mmsi).collect();
+    let msgtime: Vec<Option<DateTime<Utc>>> = issues.iter().map(|(report, _)| report.msgtime).collect();
+    let field: Vec<&str> = issues.iter().map(|(_, issue)| issue.field).collect();
+    let value: Vec<String> = issues.iter().map(|(_, issue)| issue.value.chars().take(255).collect()).collect();
+    let rejected: Vec<bool> = issues.iter().map(|(report, _)| report.rejected).collect();
+
+    // One statement, so the issues are written with the request log row on whichever executor.
     let id = query!(
-        "INSERT INTO \
-      log.requests (api_endpoint, status_code, number_of_messages_received) \
-      VALUES ($1, $2, $3) RETURNING id;",
-        api_endpoint,
-        status_code,
-        number_of_messages
+        "WITH request AS (
+            INSERT INTO log.requests (
+                source, api_endpoint, status_code, number_of_messages_received, unknown_messages,
+                outcome, status_message
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id
+        ), issues AS (
+            INSERT INTO log.validation_issues (log_id, message_kind, mmsi, msgtime, field, value, rejected)
+            SELECT request.id, issue.* FROM request, UNNEST(
+                $8::varchar[], $9::bigint[], $10::timestamptz[], $11::varchar[], $12::varchar[], $13::bool[]
+            ) AS issue
+        )
+        SELECT id FROM request",
+        request.source.as_str(),
+        request.api_endpoint,
+        request.status_code,
+        request.number_of_messages,
+        request.unknown_messages,
+        outcome.map(|outcome| outcome.as_str()),
+        status_message.map(truncate_status_message),
+        &message_kind as _,
+        &mmsi as _,
+        &msgtime as _,
+        &field as _,
+        &value as _,
+        &rejected as _
     )
-    .fetch_one(&mut tx)
+    .fetch_one(executor)
     .await?
     .id;
-    tx.commit().await?;
 
     Ok(id)
 }
 
+pub(crate) async fn record_duplicates_skipped(
+    executor: impl PgExecutor<'_>,
+    log_id: Uuid,
+    duplicates_skipped: u64,
+) -> Result<(), Error> {
+    query!(
+        "UPDATE log.requests SET duplicates_skipped = $2 WHERE id = $1;",
+        log_id,
+        i64::try_from(duplicates_skipped).unwrap_or(i64::MAX)
+    )
+    .execute(executor)
+    .await?;
 
+    Ok(())
+}
 
+pub async fn update_request_outcome(
+    db_pool: PgPool,
+    log_id: Uuid,
+    outcome: RequestOutcome,
+    status_message: Option<&str>,
+) -> Result<(), Error> {
+    query!(
+        "UPDATE log.requests SET outcome = $2, status_message = $3 WHERE id = $1;",
+        log_id,
+        outcome.as_str(),
+        status_message.map(truncate_status_message)
+    )
+    .execute(&db_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records the request and writes all of its messages in one transaction. When the write fails
+/// nothing is kept except a `failed` request log row carrying the error.
+pub async fn ingest_atomically(
+    db_pool: PgPool,
+    request: &RequestLog,
+    messages: &SplitAISMessages,
+    batch_size: usize,
+) -> Result<IngestionReport, Error> {
+    let mut tx = db_pool.begin().await?;
+    let log_id = insert_request_log_with_outcome(&mut tx, request, Some(RequestOutcome::Success), None).await?;
+
+    let result = async {
+        let metrics = write_ais_data(&mut tx, messages, log_id, batch_size).await?;
+        record_duplicates_skipped(&mut tx, log_id, metrics.duplicates_skipped).await?;
+        Ok(metrics)
+    }
+    .await;
+
+    match finish(tx, result).await {
+        Ok(metrics) => {
+            log_metrics(&metrics);
+            Ok(IngestionReport {
+                log_id,
+                outcome: RequestOutcome::Success,
+                error: None,
+                metrics,
+            })
+        }
+        Err(error) => {
+            let message = error.to_string();
+            warn!("Rolled back the ingestion of {}: {}", request.api_endpoint, message);
+            let log_id = insert_request_log_with_outcome(&db_pool, request, Some(RequestOutcome::Failed), Some(&message)).await?;
+            Ok(IngestionReport {
+                log_id,
+                outcome: RequestOutcome::Failed,
+                error: Some(message),
+                metrics: InsertMetrics::default(),
+            })
+        }
+    }
+}
+
+// Commits the transaction when `result` is a success and rolls it back otherwise, either way its
+// connection goes back to the pool before anything else is written.
+pub(crate) async fn finish<T>(tx: Transaction<'_, Postgres>, result: Result<T, Error>) -> Result<T, Error> {
+    match result {
+        Ok(value) => tx.commit().await.map(|_| value),
+        Err(error) => {
+            if let Err(rollback_error) = tx.rollback().await {
+                warn!("Rolling back failed: {}", rollback_error);
+            }
+            Err(error)
+        }
+    }
+}
+
+pub struct IngestionCheckpoint {
+    pub last_msgtime: Option<DateTime<Utc>>,
+    pub last_request_time: DateTime<Utc>,
+}
+
+pub async fn get_checkpoint(
+    db_pool: PgPool,
+    source: &str,
+) -> Result<Option<IngestionCheckpoint>, Error> {
+    let checkpoint = query!(
+        "SELECT last_msgtime, last_request_time FROM log.checkpoints WHERE source = $1;",
+        source
+    )
+    .fetch_optional(&db_pool)
+    .await?
+    .map(|row| IngestionCheckpoint {
+        last_msgtime: row.last_msgtime,
+        last_request_time: row.last_request_time,
+    });
+
+    Ok(checkpoint)
+}
+
+pub async fn update_checkpoint(
+    db_pool: PgPool,
+    source: &str,
+    checkpoint: &IngestionCheckpoint,
+    log_id: Uuid,
+) -> Result<(), Error> {
+    query!(
+        "INSERT INTO log.checkpoints (source, last_msgtime, last_request_time, log_id, updated_at) \
+      VALUES ($1, $2, $3, $4, NOW()) \
+      ON CONFLICT (source) DO UPDATE SET \
+      last_msgtime = EXCLUDED.last_msgtime, last_request_time = EXCLUDED.last_request_time, \
+      log_id = EXCLUDED.log_id, updated_at = EXCLUDED.updated_at;",
+        source,
+        checkpoint.last_msgtime,
+        checkpoint.last_request_time,
+        log_id
+    )
+    .execute(&db_pool)
+    .await?;
+
+    Ok(())
+}
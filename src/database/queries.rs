@@ -0,0 +1,294 @@
+use crate::live_ais::ais_types::Eta;
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Serialize;
+use sqlx::{query, query_as, Error, PgPool};
+
+#[derive(Default, Clone)]
+pub struct ExportFilter {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub mmsi: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseStats {
+    pub requests: i64,
+    pub last_request_at: Option<DateTime<Utc>>,
+    pub static_rows: i64,
+    pub aton_rows: i64,
+    pub position_rows: i64,
+    pub distinct_vessels: i64,
+    pub checkpoints: Vec<CheckpointStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckpointStats {
+    pub source: String,
+    pub last_msgtime: Option<DateTime<Utc>>,
+    pub last_request_time: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct PositionRow {
+    type_field: Option<String>,
+    message_type: Option<i64>,
+    course_over_ground: Option<f64>,
+    ais_class: Option<String>,
+    altitude: Option<f64>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    navigational_status: Option<i64>,
+    rate_of_turn: Option<i64>,
+    speed_over_ground: Option<f64>,
+    true_heading: Option<i64>,
+    mmsi: Option<i64>,
+    msgtime: Option<DateTime<Utc>>,
+}
+
+impl From<PositionRow> for AISPositionData {
+    fn from(row: PositionRow) -> Self {
+        AISPositionData {
+            type_field: row.type_field,
+            message_type: row.message_type,
+            course_over_ground: row.course_over_ground,
+            ais_class: row.ais_class,
+            altitude: row.altitude,
+            latitude: row.latitude,
+            longitude: row.longitude,
+            navigational_status: row.navigational_status.map(Into::into),
+            rate_of_turn: row.rate_of_turn,
+            speed_over_ground: row.speed_over_ground,
+            true_heading: row.true_heading,
+            mmsi: row.mmsi,
+            msgtime: row.msgtime,
+        }
+    }
+}
+
+struct StaticRow {
+    type_field: Option<String>,
+    message_type: Option<i64>,
+    mmsi: Option<i64>,
+    msgtime: Option<DateTime<Utc>>,
+    imo_number: Option<i64>,
+    call_sign: Option<String>,
+    destination: Option<String>,
+    eta: Option<DateTime<Utc>>,
+    name: Option<String>,
+    draught: Option<i32>,
+    ship_length: Option<i32>,
+    ship_width: Option<i32>,
+    ship_type: Option<i32>,
+    dimension_a: Option<i32>,
+    dimension_b: Option<i32>,
+    dimension_c: Option<i32>,
+    dimension_d: Option<i32>,
+    position_fixing_device_type: Option<i64>,
+    report_class: Option<String>,
+}
+
+impl From<StaticRow> for AISStaticData {
+    fn from(row: StaticRow) -> Self {
+        AISStaticData {
+            type_field: row.type_field,
+            message_type: row.message_type,
+            mmsi: row.mmsi,
+            msgtime: row.msgtime,
+            imo_number: row.imo_number,
+            call_sign: row.call_sign,
+            destination: row.destination,
+            eta: row.eta.map(|eta| Eta::from(eta).into()),
+            name: row.name,
+            draught: row.draught,
+            ship_length: row.ship_length,
+            ship_width: row.ship_width,
+            ship_type: row.ship_type.map(Into::into),
+            dimension_a: row.dimension_a,
+            dimension_b: row.dimension_b,
+            dimension_c: row.dimension_c,
+            dimension_d: row.dimension_d,
+            position_fixing_device_type: row.position_fixing_device_type.map(Into::into),
+            report_class: row.report_class,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct AtonRow {
+    type_field: Option<String>,
+    message_type: Option<i64>,
+    mmsi: Option<i64>,
+    msgtime: Option<DateTime<Utc>>,
+    dimension_a: Option<i32>,
+    dimension_b: Option<i32>,
+    dimension_c: Option<i32>,
+    dimension_d: Option<i32>,
+    type_of_aids_to_navigation: Option<i64>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    name: Option<String>,
+    type_of_electronic_fixing_device: Option<i64>,
+}
+
+impl From<AtonRow> for AISAtonData {
+    fn from(row: AtonRow) -> Self {
+        AISAtonData {
+            type_field: row.type_field,
+            message_type: row.message_type,
+            mmsi: row.mmsi,
+            msgtime: row.msgtime,
+            dimension_a: row.dimension_a,
+            dimension_b: row.dimension_b,
+            dimension_c: row.dimension_c,
+            dimension_d: row.dimension_d,
+            type_of_aids_to_navigation: row.type_of_aids_to_navigation.map(Into::into),
+            latitude: row.latitude,
+            longitude: row.longitude,
+            name: row.name,
+            type_of_electronic_fixing_device: row.type_of_electronic_fixing_device.map(Into::into),
+        }
+    }
+}
+
+pub fn export_position_data<'a>(
+    db_pool: &'a PgPool,
+    filter: &ExportFilter,
+) -> BoxStream<'a, Result<AISPositionData, Error>> {
+    query_as!(
+        PositionRow,
+        "SELECT type_field, message_type, course_over_ground, ais_class, altitude, latitude, longitude,
+            navigational_status, rate_of_turn, speed_over_ground, true_heading, mmsi, msgtime
+        FROM ais.ais_position_data
+        WHERE ($1::timestamptz IS NULL OR msgtime >= $1)
+            AND ($2::timestamptz IS NULL OR msgtime < $2)
+            AND ($3::bigint IS NULL OR mmsi = $3)
+        ORDER BY msgtime",
+        filter.since,
+        filter.until,
+        filter.mmsi
+    )
+    .fetch(db_pool)
+    .map(|row| row.map(Into::into))
+    .boxed()
+}
+
+pub fn export_static_data<'a>(
+    db_pool: &'a PgPool,
+    filter: &ExportFilter,
+) -> BoxStream<'a, Result<AISStaticData, Error>> {
+    query_as!(
+        StaticRow,
+        "SELECT type_field, message_type, mmsi, msgtime, imo_number, call_sign, destination, eta, name, draught,
+            ship_length, ship_width, ship_type, dimension_a, dimension_b, dimension_c, dimension_d,
+            position_fixing_device_type, report_class
+        FROM ais.ais_static_data
+        WHERE ($1::timestamptz IS NULL OR msgtime >= $1)
+            AND ($2::timestamptz IS NULL OR msgtime < $2)
+            AND ($3::bigint IS NULL OR mmsi = $3)
+        ORDER BY msgtime",
+        filter.since,
+        filter.until,
+        filter.mmsi
+    )
+    .fetch(db_pool)
+    .map(|row| row.map(Into::into))
+    .boxed()
+}
+
+pub fn export_aton_data<'a>(
+    db_pool: &'a PgPool,
+    filter: &ExportFilter,
+) -> BoxStream<'a, Result<AISAtonData, Error>> {
+    query_as!(
+        AtonRow,
+        "SELECT type_field, message_type, mmsi, msgtime, dimension_a, dimension_b, dimension_c, dimension_d,
+            type_of_aids_to_navigation, latitude, longitude, name, type_of_electronic_fixing_device
+        FROM ais.ais_aton_data
+        WHERE ($1::timestamptz IS NULL OR msgtime >= $1)
+            AND ($2::timestamptz IS NULL OR msgtime < $2)
+            AND ($3::bigint IS NULL OR mmsi = $3)
+        ORDER BY msgtime",
+        filter.since,
+        filter.until,
+        filter.mmsi
+    )
+    .fetch(db_pool)
+    .map(|row| row.map(Into::into))
+    .boxed()
+}
+
+pub async fn get_latest_static_data(
+    db_pool: &PgPool,
+    mmsi: i64,
+) -> Result<Option<AISStaticData>, Error> {
+    let row = query_as!(
+        StaticRow,
+        "SELECT type_field, message_type, mmsi, msgtime, imo_number, call_sign, destination, eta, name, draught,
+            ship_length, ship_width, ship_type, dimension_a, dimension_b, dimension_c, dimension_d,
+            position_fixing_device_type, report_class
+        FROM ais.ais_static_data
+        WHERE mmsi = $1
+        ORDER BY msgtime DESC NULLS LAST
+        LIMIT 1",
+        mmsi
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(row.map(Into::into))
+}
+
+pub async fn get_database_stats(db_pool: &PgPool) -> Result<DatabaseStats, Error> {
+    let requests = query!(
+        "SELECT COUNT(*) AS \"count!\", MAX(created_at) AS last_request_at FROM log.requests"
+    )
+    .fetch_one(db_pool)
+    .await?;
+    let static_rows = query!("SELECT COUNT(*) AS \"count!\" FROM ais.ais_static_data")
+        .fetch_one(db_pool)
+        .await?
+        .count;
+    let aton_rows = query!("SELECT COUNT(*) AS \"count!\" FROM ais.ais_aton_data")
+        .fetch_one(db_pool)
+        .await?
+        .count;
+    let position_rows = query!("SELECT COUNT(*) AS \"count!\" FROM ais.ais_position_data")
+        .fetch_one(db_pool)
+        .await?
+        .count;
+    let distinct_vessels = query!(
+        "SELECT COUNT(DISTINCT mmsi) AS \"count!\" FROM (
+            SELECT mmsi FROM ais.ais_static_data UNION SELECT mmsi FROM ais.ais_position_data
+        ) AS vessels"
+    )
+    .fetch_one(db_pool)
+    .await?
+    .count;
+    let checkpoints = query!(
+        "SELECT source, last_msgtime, last_request_time FROM log.checkpoints ORDER BY source"
+    )
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(|row| CheckpointStats {
+        source: row.source,
+        last_msgtime: row.last_msgtime,
+        last_request_time: row.last_request_time,
+    })
+    .collect();
+
+    Ok(DatabaseStats {
+        requests: requests.count,
+        last_request_at: requests
+            .last_request_at
+            .map(|created_at| created_at.and_utc()),
+        static_rows,
+        aton_rows,
+        position_rows,
+        distinct_vessels,
+        checkpoints,
+    })
+}
@@ -0,0 +1,118 @@
+use crate::live_ais::response_structs::{RejectedMessage, RejectionStage};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Uuid;
+use sqlx::{query, Error, PgExecutor, PgPool};
+
+#[derive(Default, Clone)]
+pub struct RejectedMessageFilter {
+    pub stage: Option<RejectionStage>,
+    pub log_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    /// Also list messages that were already re-processed.
+    pub include_reprocessed: bool,
+    pub limit: Option<i64>,
+}
+
+/// A row of log.rejected_messages.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredRejectedMessage {
+    pub id: i64,
+    pub log_id: String,
+    pub created_at: DateTime<Utc>,
+    pub stage: String,
+    pub reason: String,
+    pub payload: serde_json::Value,
+    pub reprocessed_at: Option<DateTime<Utc>>,
+    pub reprocessed_log_id: Option<String>,
+}
+
+/// The payloads are bound as text and cast, sqlx is built without JSON support.
+pub async fn insert_rejected_messages(
+    executor: impl PgExecutor<'_>,
+    log_id: Uuid,
+    rejected: &[RejectedMessage],
+) -> Result<u64, Error> {
+    if rejected.is_empty() {
+        return Ok(0);
+    }
+
+    let stage: Vec<&str> = rejected.iter().map(|message| message.stage.as_str()).collect();
+    let reason: Vec<&str> = rejected.iter().map(|message| message.reason.as_str()).collect();
+    let payload: Vec<String> = rejected.iter().map(|message| message.payload.to_string()).collect();
+
+    let rows_affected = query!(
+        "INSERT INTO log.rejected_messages (log_id, stage, reason, payload)
+        SELECT $1, stage, reason, payload::jsonb FROM UNNEST($2::varchar[], $3::text[], $4::text[])
+            AS rejected (stage, reason, payload)",
+        log_id,
+        &stage as _,
+        &reason as _,
+        &payload as _
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected)
+}
+
+/// Oldest first.
+pub async fn list_rejected_messages(
+    db_pool: &PgPool,
+    filter: &RejectedMessageFilter,
+) -> Result<Vec<StoredRejectedMessage>, Error> {
+    let rows = query!(
+        "SELECT id, log_id, created_at, stage, reason, payload::text AS \"payload!\", reprocessed_at,
+            reprocessed_log_id
+        FROM log.rejected_messages
+        WHERE ($1::varchar IS NULL OR stage = $1)
+            AND ($2::uuid IS NULL OR log_id = $2)
+            AND ($3::timestamptz IS NULL OR created_at >= $3)
+            AND ($4 OR reprocessed_at IS NULL)
+        ORDER BY id
+        LIMIT $5",
+        filter.stage.map(|stage| stage.as_str()),
+        filter.log_id,
+        filter.since,
+        filter.include_reprocessed,
+        filter.limit
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| StoredRejectedMessage {
+            id: row.id,
+            log_id: row.log_id.to_string(),
+            created_at: row.created_at,
+            stage: row.stage,
+            reason: row.reason,
+            // The column is JSONB, so its text form always parses.
+            payload: serde_json::from_str(&row.payload).unwrap_or_default(),
+            reprocessed_at: row.reprocessed_at,
+            reprocessed_log_id: row.reprocessed_log_id.map(|log_id| log_id.to_string()),
+        })
+        .collect())
+}
+
+/// Records that the messages were ingested again by the request `log_id`.
+pub async fn mark_reprocessed(
+    executor: impl PgExecutor<'_>,
+    ids: &[i64],
+    log_id: Uuid,
+) -> Result<u64, Error> {
+    let rows_affected = query!(
+        "UPDATE log.rejected_messages SET reprocessed_at = NOW(), reprocessed_log_id = $2
+        WHERE id = ANY($1) AND reprocessed_at IS NULL",
+        ids,
+        log_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected)
+}
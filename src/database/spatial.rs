@@ -0,0 +1,217 @@
+//! Spatial lookups on positions and aids to navigation.
+//!
+//! When PostGIS was available at migration time the position, AtoN and latest position tables have
+//! a `geom` column. A trigger fills it from latitude/longitude on insert and update, so the insert
+//! functions never bind it and writes look the same with or without PostGIS.
+
+use crate::database::latest_position::FleetPosition;
+use crate::database::queries::{AtonRow, ExportFilter, PositionRow};
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData};
+use sqlx::{query_scalar, Error, PgPool, Postgres, QueryBuilder};
+use std::str::FromStr;
+
+/// Mean earth radius in meters, used by the haversine fallback when PostGIS is not installed.
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub min_longitude: f64,
+    pub max_latitude: f64,
+    pub max_longitude: f64,
+}
+
+/// Parses the corners as `min_longitude,min_latitude,max_longitude,max_latitude`, the order of a
+/// GeoJSON bounding box, such as `15,68,32,72`.
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let coordinates = value
+            .split(',')
+            .map(|coordinate| {
+                coordinate
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid coordinate \"{}\"", coordinate))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let [min_longitude, min_latitude, max_longitude, max_latitude] = coordinates[..] else {
+            return Err(format!(
+                "expected min_longitude,min_latitude,max_longitude,max_latitude, got \"{}\"",
+                value
+            ));
+        };
+        if min_latitude > max_latitude || min_longitude > max_longitude {
+            return Err(format!("the minimum corner of \"{}\" is not below the maximum", value));
+        }
+        Ok(BoundingBox {
+            min_latitude,
+            min_longitude,
+            max_latitude,
+            max_longitude,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SpatialFilter {
+    BoundingBox(BoundingBox),
+    Radius {
+        latitude: f64,
+        longitude: f64,
+        meters: f64,
+    },
+}
+
+/// Whether the PostGIS migration added the `geom` columns, i.e. PostGIS was available when the
+/// schema was migrated. Without it the lookups filter on the plain latitude/longitude columns.
+pub async fn postgis_enabled(db_pool: &PgPool) -> Result<bool, Error> {
+    query_scalar(
+        "SELECT EXISTS (
+            SELECT 1 FROM information_schema.columns
+            WHERE table_schema = 'ais' AND table_name = 'ais_position_data' AND column_name = 'geom'
+        )",
+    )
+    .fetch_one(db_pool)
+    .await
+}
+
+/// Appends the spatial condition for the table aliased as `table` to `builder`.
+fn push_spatial_condition(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    table: &str,
+    filter: &SpatialFilter,
+    postgis: bool,
+) {
+    match (*filter, postgis) {
+        (SpatialFilter::BoundingBox(bbox), true) => {
+            builder.push(format!("ST_Intersects({table}.geom, ST_MakeEnvelope("));
+            builder.push_bind(bbox.min_longitude);
+            builder.push(", ");
+            builder.push_bind(bbox.min_latitude);
+            builder.push(", ");
+            builder.push_bind(bbox.max_longitude);
+            builder.push(", ");
+            builder.push_bind(bbox.max_latitude);
+            builder.push(", 4326)::geography)");
+        }
+        (SpatialFilter::BoundingBox(bbox), false) => {
+            builder.push(format!("{table}.latitude BETWEEN "));
+            builder.push_bind(bbox.min_latitude);
+            builder.push(" AND ");
+            builder.push_bind(bbox.max_latitude);
+            builder.push(format!(" AND {table}.longitude BETWEEN "));
+            builder.push_bind(bbox.min_longitude);
+            builder.push(" AND ");
+            builder.push_bind(bbox.max_longitude);
+        }
+        (SpatialFilter::Radius { latitude, longitude, meters }, true) => {
+            builder.push(format!("ST_DWithin({table}.geom, ST_SetSRID(ST_MakePoint("));
+            builder.push_bind(longitude);
+            builder.push(", ");
+            builder.push_bind(latitude);
+            builder.push("), 4326)::geography, ");
+            builder.push_bind(meters);
+            builder.push(")");
+        }
+        (SpatialFilter::Radius { latitude, longitude, meters }, false) => {
+            // Cheap latitude band first, then the great-circle distance.
+            let degrees = (meters / EARTH_RADIUS_METERS).to_degrees();
+            builder.push(format!("{table}.latitude BETWEEN "));
+            builder.push_bind(latitude - degrees);
+            builder.push(" AND ");
+            builder.push_bind(latitude + degrees);
+            builder.push(format!(" AND 2 * {EARTH_RADIUS_METERS} * asin(sqrt(power(sin(radians({table}.latitude - "));
+            builder.push_bind(latitude);
+            builder.push(format!(") / 2), 2) + cos(radians({table}.latitude)) * cos(radians("));
+            builder.push_bind(latitude);
+            builder.push(format!(")) * power(sin(radians({table}.longitude - "));
+            builder.push_bind(longitude);
+            builder.push(") / 2), 2))) <= ");
+            builder.push_bind(meters);
+        }
+    }
+}
+
+fn push_time_conditions(builder: &mut QueryBuilder<'_, Postgres>, table: &str, filter: &ExportFilter) {
+    if let Some(since) = filter.since {
+        builder.push(format!(" AND {table}.msgtime >= "));
+        builder.push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        builder.push(format!(" AND {table}.msgtime < "));
+        builder.push_bind(until);
+    }
+    if let Some(mmsi) = filter.mmsi {
+        builder.push(format!(" AND {table}.mmsi = "));
+        builder.push_bind(mmsi);
+    }
+}
+
+/// Position reports inside `area`, ordered by msgtime.
+pub async fn find_positions(
+    db_pool: &PgPool,
+    area: &SpatialFilter,
+    filter: &ExportFilter,
+) -> Result<Vec<AISPositionData>, Error> {
+    let postgis = postgis_enabled(db_pool).await?;
+    let mut builder = QueryBuilder::new(
+        "SELECT p.type_field, p.message_type, p.course_over_ground, p.ais_class, p.altitude, p.latitude,
+            p.longitude, p.navigational_status, p.rate_of_turn, p.speed_over_ground, p.true_heading, p.mmsi,
+            p.msgtime
+        FROM ais.ais_position_data p
+        WHERE ",
+    );
+    push_spatial_condition(&mut builder, "p", area, postgis);
+    push_time_conditions(&mut builder, "p", filter);
+    builder.push(" ORDER BY p.msgtime");
+
+    let rows: Vec<PositionRow> = builder.build_query_as().fetch_all(db_pool).await?;
+    Ok(rows.into_iter().map(Into::into).collect())
+}
+
+/// AtoN reports inside `area`, ordered by msgtime.
+pub async fn find_atons(
+    db_pool: &PgPool,
+    area: &SpatialFilter,
+    filter: &ExportFilter,
+) -> Result<Vec<AISAtonData>, Error> {
+    let postgis = postgis_enabled(db_pool).await?;
+    let mut builder = QueryBuilder::new(
+        "SELECT a.type_field, a.message_type, a.mmsi, a.msgtime, a.dimension_a, a.dimension_b, a.dimension_c,
+            a.dimension_d, a.type_of_aids_to_navigation, a.latitude, a.longitude, a.name,
+            a.type_of_electronic_fixing_device
+        FROM ais.ais_aton_data a
+        WHERE ",
+    );
+    push_spatial_condition(&mut builder, "a", area, postgis);
+    push_time_conditions(&mut builder, "a", filter);
+    builder.push(" ORDER BY a.msgtime");
+
+    let rows: Vec<AtonRow> = builder.build_query_as().fetch_all(db_pool).await?;
+    Ok(rows.into_iter().map(Into::into).collect())
+}
+
+/// Vessels whose last known position is inside `area` and was reported within the times of
+/// `filter`.
+pub async fn find_fleet_positions(
+    db_pool: &PgPool,
+    area: &SpatialFilter,
+    filter: &ExportFilter,
+) -> Result<Vec<FleetPosition>, Error> {
+    let postgis = postgis_enabled(db_pool).await?;
+    let mut builder = QueryBuilder::new(
+        "SELECT p.mmsi, p.msgtime, p.latitude, p.longitude, p.course_over_ground, p.speed_over_ground,
+            p.true_heading, p.navigational_status, v.name, v.call_sign, v.imo_number, v.ship_type,
+            v.ship_length, v.ship_width, v.destination
+        FROM ais.latest_position p
+        LEFT JOIN ais.vessels v ON v.mmsi = p.mmsi
+        WHERE ",
+    );
+    push_spatial_condition(&mut builder, "p", area, postgis);
+    push_time_conditions(&mut builder, "p", filter);
+    builder.push(" ORDER BY p.mmsi");
+
+    builder.build_query_as().fetch_all(db_pool).await
+}
@@ -0,0 +1,153 @@
+use crate::live_ais::response_structs::AISStaticData;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Uuid;
+use sqlx::{query, query_as, Error, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub struct Vessel {
+    pub mmsi: i64,
+    pub name: Option<String>,
+    pub call_sign: Option<String>,
+    pub imo_number: Option<i64>,
+    pub ship_type: Option<i32>,
+    pub ship_length: Option<i32>,
+    pub ship_width: Option<i32>,
+    pub dimension_a: Option<i32>,
+    pub dimension_b: Option<i32>,
+    pub dimension_c: Option<i32>,
+    pub dimension_d: Option<i32>,
+    pub draught: Option<i32>,
+    pub destination: Option<String>,
+    pub eta: Option<DateTime<Utc>>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VesselChange {
+    pub changed_at: DateTime<Utc>,
+    pub change_type: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub related_mmsi: Option<i64>,
+}
+
+/// Brings `ais.vessels` up to date with a batch of static messages.
+///
+/// Only the newest message per MMSI in the batch is applied, and only when it is newer than what
+/// the registry already holds. Fields missing from the message (class B vessels send their static
+/// data in two parts) keep their stored value. Changes are written to `ais.vessel_history` by a
+/// trigger on the table.
+pub async fn upsert_vessels(
+    tx: &mut Transaction<'_, Postgres>,
+    static_data: &[AISStaticData],
+    log_id: Uuid,
+) -> Result<u64, Error> {
+    let mut latest: HashMap<i64, (DateTime<Utc>, &AISStaticData)> = HashMap::new();
+    for data in static_data {
+        let (Some(mmsi), Some(msgtime)) = (data.mmsi, data.msgtime) else {
+            continue;
+        };
+        match latest.get(&mmsi) {
+            Some((seen, _)) if *seen >= msgtime => {}
+            _ => {
+                latest.insert(mmsi, (msgtime, data));
+            }
+        }
+    }
+    if latest.is_empty() {
+        return Ok(0);
+    }
+
+    let vessels: Vec<(i64, DateTime<Utc>, &AISStaticData)> = latest
+        .into_iter()
+        .map(|(mmsi, (msgtime, data))| (mmsi, msgtime, data))
+        .collect();
+    let mmsi: Vec<i64> = vessels.iter().map(|(mmsi, _, _)| *mmsi).collect();
+    let msgtime: Vec<DateTime<Utc>> = vessels.iter().map(|(_, msgtime, _)| *msgtime).collect();
+    let name: Vec<Option<String>> = vessels.iter().map(|(_, _, data)| data.name.clone()).collect();
+    let call_sign: Vec<Option<String>> = vessels.iter().map(|(_, _, data)| data.call_sign.clone()).collect();
+    let imo_number: Vec<Option<i64>> = vessels.iter().map(|(_, _, data)| data.imo_number).collect();
+    let ship_type: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.ship_type.and_then(|ship_type| i32::try_from(ship_type).ok())).collect();
+    let ship_length: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.ship_length).collect();
+    let ship_width: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.ship_width).collect();
+    let dimension_a: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.dimension_a).collect();
+    let dimension_b: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.dimension_b).collect();
+    let dimension_c: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.dimension_c).collect();
+    let dimension_d: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.dimension_d).collect();
+    let draught: Vec<Option<i32>> = vessels.iter().map(|(_, _, data)| data.draught).collect();
+    let destination: Vec<Option<String>> = vessels.iter().map(|(_, _, data)| data.destination.clone()).collect();
+    let eta: Vec<Option<DateTime<Utc>>> = vessels
+        .iter()
+        .map(|(_, msgtime, data)| data.eta.as_ref().and_then(|eta| eta.resolve(*msgtime)))
+        .collect();
+
+    let rows_affected = query!(
+        "INSERT INTO ais.vessels (
+            mmsi, first_seen, last_seen, name, call_sign, imo_number, ship_type, ship_length, ship_width,
+            dimension_a, dimension_b, dimension_c, dimension_d, draught, destination, eta, log_id
+        ) SELECT mmsi, msgtime, msgtime, name, call_sign, imo_number, ship_type, ship_length, ship_width,
+            dimension_a, dimension_b, dimension_c, dimension_d, draught, destination, eta, $16
+        FROM UNNEST(
+            $1::bigint[], $2::timestamptz[], $3::varchar[], $4::varchar[], $5::bigint[], $6::int[], $7::int[],
+            $8::int[], $9::int[], $10::int[], $11::int[], $12::int[], $13::int[], $14::varchar[], $15::timestamptz[]
+        ) AS incoming (
+            mmsi, msgtime, name, call_sign, imo_number, ship_type, ship_length, ship_width,
+            dimension_a, dimension_b, dimension_c, dimension_d, draught, destination, eta
+        )
+        ON CONFLICT (mmsi) DO UPDATE SET
+            name = COALESCE(EXCLUDED.name, vessels.name),
+            call_sign = COALESCE(EXCLUDED.call_sign, vessels.call_sign),
+            imo_number = COALESCE(EXCLUDED.imo_number, vessels.imo_number),
+            ship_type = COALESCE(EXCLUDED.ship_type, vessels.ship_type),
+            ship_length = COALESCE(EXCLUDED.ship_length, vessels.ship_length),
+            ship_width = COALESCE(EXCLUDED.ship_width, vessels.ship_width),
+            dimension_a = COALESCE(EXCLUDED.dimension_a, vessels.dimension_a),
+            dimension_b = COALESCE(EXCLUDED.dimension_b, vessels.dimension_b),
+            dimension_c = COALESCE(EXCLUDED.dimension_c, vessels.dimension_c),
+            dimension_d = COALESCE(EXCLUDED.dimension_d, vessels.dimension_d),
+            draught = COALESCE(EXCLUDED.draught, vessels.draught),
+            destination = COALESCE(EXCLUDED.destination, vessels.destination),
+            eta = COALESCE(EXCLUDED.eta, vessels.eta),
+            last_seen = EXCLUDED.last_seen,
+            log_id = EXCLUDED.log_id
+        WHERE EXCLUDED.last_seen > vessels.last_seen",
+        &mmsi as _, &msgtime as _, &name as _, &call_sign as _, &imo_number as _,
+        &ship_type as _, &ship_length as _, &ship_width as _, &dimension_a as _, &dimension_b as _,
+        &dimension_c as _, &dimension_d as _, &draught as _, &destination as _, &eta as _,
+        log_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected)
+}
+
+pub async fn get_vessel(db_pool: &PgPool, mmsi: i64) -> Result<Option<Vessel>, Error> {
+    query_as!(
+        Vessel,
+        "SELECT mmsi, name, call_sign, imo_number, ship_type, ship_length, ship_width, dimension_a,
+            dimension_b, dimension_c, dimension_d, draught, destination, eta, first_seen, last_seen
+        FROM ais.vessels
+        WHERE mmsi = $1",
+        mmsi
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+pub async fn get_vessel_history(db_pool: &PgPool, mmsi: i64) -> Result<Vec<VesselChange>, Error> {
+    query_as!(
+        VesselChange,
+        "SELECT changed_at, change_type, old_value, new_value, related_mmsi
+        FROM ais.vessel_history
+        WHERE mmsi = $1
+        ORDER BY changed_at, id",
+        mmsi
+    )
+    .fetch_all(db_pool)
+    .await
+}
@@ -1,2 +1,5 @@
 pub mod database;
 pub mod live_ais;
+pub mod nmea;
+pub mod sinks;
+pub mod sources;
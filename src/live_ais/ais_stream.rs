@@ -1,14 +1,25 @@
-use crate::live_ais::response_structs::{AISLatestResponses, GetAISLatestResponse};
+use crate::database::configuration::{ApiSettings, RetrySettings, TokenSettings};
+use crate::live_ais::query::{messages_from_geojson, AisQuery, FilterInput, ModelFormat};
+use crate::live_ais::response_structs::{
+    AISLatestResponses, AisMessage, GetAISLatestResponse,
+};
+use crate::live_ais::retry::{classify, Attempt, Backoff, CircuitBreaker, FailedRequests};
+use crate::live_ais::token::{Credentials, TokenManager};
+pub use crate::live_ais::token::ScopeType;
 
-use super::response_structs::TokenResponse;
+use async_stream::stream;
 use chrono::prelude::*;
-use reqwest::{self, Client, StatusCode};
-use std::collections::HashMap;
+use futures::{Stream, StreamExt};
+use reqwest::{self, Client, Method, Response, StatusCode, Url};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 use thiserror::Error;
 
-use log::{debug, error, info};
+use log::{debug, info, warn};
 
-static BASE_URL: &'static str = "https://live.ais.barentswatch.no";
+// Delay before re-opening the live stream after it was dropped by the server.
+const STREAM_RECONNECT_DELAY: Duration = Duration::from_secs(5);
 
 #[derive(Error, Debug)]
 pub enum ResponseErrorMessages {
@@ -24,33 +35,31 @@ pub enum ResponseErrorMessages {
     #[error("deserialization error: {0}")]
     DeserializationError(reqwest::Error),
 
-    #[error("no token available")]
-    NoToken,
-}
+    #[error("invalid stream message: {0}")]
+    InvalidStreamMessage(serde_json::Error),
 
-pub enum ScopeType {
-    Ais,
-    Api,
-}
+    #[error("invalid GeoJSON response: {0}")]
+    InvalidGeoJson(serde_json::Error),
 
-impl ScopeType {
-    fn as_str(&self) -> &str {
-        match self {
-            ScopeType::Ais => "ais",
-            ScopeType::Api => "api",
-        }
-    }
+    #[error("requests are paused after repeated failures until {until}")]
+    CircuitOpen { until: DateTime<Utc> },
 }
 
+/// A client of the BarentsWatch API.
+///
+/// Clones share the token, the circuit breaker and the failed requests, so one client can be
+/// used from several tasks at once.
+#[derive(Clone)]
 pub struct AisLiveAPI {
-    grant_type: String,
-    client_id: String,
-    client_secret: String,
-    scope: ScopeType,
     client: Client,
-    token: Option<String>,
-    token_expires_in: Option<i64>,
-    token_fetched_time: Option<DateTime<Utc>>,
+    api: ApiSettings,
+    tokens: Arc<TokenManager>,
+    token_settings: TokenSettings,
+    retry: RetrySettings,
+    circuit_breaker: Arc<Mutex<CircuitBreaker>>,
+    failed_requests: FailedRequests,
+    // Cleared once the server turned out not to filter, so the filters are applied here.
+    server_filters: Arc<AtomicBool>,
 }
 
 impl AisLiveAPI {
@@ -61,127 +70,353 @@ impl AisLiveAPI {
         scope: ScopeType,
     ) -> Self {
         let client = reqwest::Client::new();
-
-        return AisLiveAPI {
+        let api = ApiSettings::default();
+        let retry = RetrySettings::default();
+        let token_settings = TokenSettings::default();
+        let failed_requests = FailedRequests::default();
+        let credentials = Credentials {
             grant_type,
             client_id,
             client_secret,
             scope,
-            client,
-            token: None,
-            token_expires_in: None,
-            token_fetched_time: None,
         };
-    }
 
-    pub async fn fetch_token(&mut self) -> Result<(), ResponseErrorMessages> {
-        let scope = &String::from(self.scope.as_str());
+        AisLiveAPI {
+            tokens: Arc::new(TokenManager::new(
+                client.clone(),
+                api.token_url.clone(),
+                credentials,
+                token_settings.clone(),
+                retry.clone(),
+                failed_requests.clone(),
+            )),
+            client,
+            server_filters: Arc::new(AtomicBool::new(api.server_filters)),
+            api,
+            token_settings,
+            circuit_breaker: Arc::new(Mutex::new(CircuitBreaker::new(&retry))),
+            retry,
+            failed_requests,
+        }
+    }
 
-        let mut form = HashMap::new();
-        form.insert("grant_type", &self.grant_type);
-        form.insert("client_id", &self.client_id);
-        form.insert("client_secret", &self.client_secret);
-        form.insert("scope", scope);
+    /// Replaces the default retry and circuit breaker settings.
+    pub fn with_retry(mut self, retry: RetrySettings) -> Self {
+        self.circuit_breaker = Arc::new(Mutex::new(CircuitBreaker::new(&retry)));
+        self.retry = retry;
+        self.rebuild_token_manager();
+        self
+    }
 
-        let url = reqwest::Url::parse("https://id.barentswatch.no/connect/token")
-            .map_err(ResponseErrorMessages::InvalidUrl)?;
+    /// Replaces the default refresh skew, and keeps the token in a file when it has a cache path.
+    pub fn with_token_settings(mut self, token_settings: TokenSettings) -> Self {
+        self.token_settings = token_settings;
+        self.rebuild_token_manager();
+        self
+    }
 
-        debug!("fetch_token method - Value of URL: {}", url);
+    /// Sends the requests to other endpoints than the BarentsWatch ones.
+    pub fn with_endpoints(mut self, api: ApiSettings) -> Self {
+        self.server_filters = Arc::new(AtomicBool::new(api.server_filters));
+        self.api = api;
+        self.rebuild_token_manager();
+        self
+    }
 
-        let res = self
-            .client
-            .post(url)
-            .form(&form)
-            .send()
-            .await
-            .map_err(ResponseErrorMessages::NetworkError)?;
-        match res.status() {
-            StatusCode::OK => {
-                let token_response: TokenResponse = res
-                    .json::<TokenResponse>()
-                    .await
-                    .map_err(ResponseErrorMessages::DeserializationError)?;
-                self.token = Some(String::from(token_response.access_token));
-                self.token_expires_in = Some(token_response.expires_in);
-                self.token_fetched_time = Some(Utc::now());
+    // The builders run before the client is shared, so the new manager starts without a token.
+    fn rebuild_token_manager(&mut self) {
+        self.tokens = Arc::new(TokenManager::new(
+            self.client.clone(),
+            self.api.token_url.clone(),
+            self.tokens.credentials().clone(),
+            self.token_settings.clone(),
+            self.retry.clone(),
+            self.failed_requests.clone(),
+        ));
+    }
 
-                info!(
-                    "Successfully fetched token. Expiry time: {:?}.",
-                    self.token_expires_in
-                );
+    /// The token manager of this client and its clones.
+    pub fn tokens(&self) -> Arc<TokenManager> {
+        Arc::clone(&self.tokens)
+    }
 
-                Ok(())
-            }
-            status_code => Err(ResponseErrorMessages::UnexpectedStatusCode(status_code)),
-        }
+    /// The attempts of this client that failed, including the ones that were retried.
+    pub fn failed_requests(&self) -> FailedRequests {
+        self.failed_requests.clone()
     }
 
-    async fn refresh_token(&mut self) -> Result<(), ResponseErrorMessages> {
-        if let Some(token_fetched_time) = self.token_fetched_time {
-            let duration_since_fetch = Utc::now()
-                .signed_duration_since(token_fetched_time)
-                .num_seconds();
-            debug!("{}", duration_since_fetch);
+    fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.retry.request_timeout_seconds)
+    }
 
-            if duration_since_fetch < 3300 {
-                debug!("Token is still valid for a long enough time, no need to refresh it.");
-                return Ok(());
-            }
-        }
+    /// Fetches a new token, even when the current one is still valid.
+    pub async fn fetch_token(&self) -> Result<(), ResponseErrorMessages> {
+        self.tokens.refresh().await.map(|_| ())
+    }
 
-        self.fetch_token().await?;
-        Ok(())
+    fn circuit_breaker(&self) -> MutexGuard<'_, CircuitBreaker> {
+        self.circuit_breaker
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
     pub async fn get_latest_ais(
-        &mut self,
+        &self,
         since: DateTime<Utc>,
     ) -> Result<GetAISLatestResponse, ResponseErrorMessages> {
         let url = reqwest::Url::parse(&format!(
             "{}/v1/latest/ais?since={}",
-            BASE_URL,
-            since.format("%Y-%m-%dT%H:%M:%S").to_string()
+            self.base_url(),
+            since.format("%Y-%m-%dT%H:%M:%S")
         ))
         .map_err(ResponseErrorMessages::InvalidUrl)?;
         debug!("Method get_latest_ais - Value of URL: {}", url);
 
-        self.refresh_token().await?;
-        let token = self
-            .token
-            .as_deref()
-            .ok_or(ResponseErrorMessages::NoToken)?;
+        let res = self
+            .send_authenticated(Method::GET, url, None, Some(self.request_timeout()))
+            .await?;
+        read_latest_response(res, ModelFormat::Json).await
+    }
 
+    /// The latest messages newer than the `since` of `query` that match its filters.
+    ///
+    /// The filters are sent to `/v1/latest/combined`. When the server does not have that endpoint,
+    /// or `server_filters` is off, everything is fetched from `/v1/latest/ais` and filtered here.
+    pub async fn query_latest_ais(&self, query: &AisQuery) -> Result<GetAISLatestResponse, ResponseErrorMessages> {
+        if query.has_filters() && self.server_filters.load(Ordering::Relaxed) {
+            match self.post_latest_combined(query).await {
+                Err(ResponseErrorMessages::UnexpectedStatusCode(status_code)) if filters_unsupported(status_code) => {
+                    warn!(
+                        "The API does not filter the latest messages ({}), filtering them here instead.",
+                        status_code
+                    );
+                    self.server_filters.store(false, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+
+        let mut latest_response = self.get_latest_ais(query.since()).await?;
+        if let Some(messages) = latest_response.ais_latest_responses.take() {
+            let messages = query.apply(messages);
+            debug!("{} messages match the filters", messages.len());
+            latest_response.content_length = Some(messages.len());
+            latest_response.ais_latest_responses = Some(messages);
+        }
+        Ok(latest_response)
+    }
+
+    async fn post_latest_combined(&self, query: &AisQuery) -> Result<GetAISLatestResponse, ResponseErrorMessages> {
+        let url = reqwest::Url::parse(&format!("{}/v1/latest/combined", self.base_url()))
+            .map_err(ResponseErrorMessages::InvalidUrl)?;
+        debug!("Method query_latest_ais - Value of URL: {}", url);
+
+        let filter = query.filter_input();
         let res = self
-            .client
-            .get(url)
-            .bearer_auth(token)
-            .send()
-            .await
-            .map_err(ResponseErrorMessages::NetworkError)?;
-        match res.status() {
-            StatusCode::OK => {
-                debug!("Content length: {:#?}", &res.content_length());
-                let mut latest_response = GetAISLatestResponse {
-                    api_endpoint: res.url().to_string(),
-                    status_code: res.status().as_u16(),
-                    content_length: None,
-                    ais_latest_responses: None,
-                };
-
-                let latest_ais_response: AISLatestResponses = res
+            .send_authenticated(Method::POST, url, Some(&filter), Some(self.request_timeout()))
+            .await?;
+        read_latest_response(res, query.model_format()).await
+    }
+
+    /// Opens the `/v1/ais` live stream and yields every message as it arrives.
+    ///
+    /// The stream never ends on its own: when the server drops the connection, or it cannot be
+    /// opened, the error is yielded and the connection is re-established after a short delay.
+    pub fn stream_ais(
+        &self,
+    ) -> impl Stream<Item = Result<AisMessage, ResponseErrorMessages>> + '_ {
+        stream! {
+            loop {
+                match self.open_ais_stream().await {
+                    Ok(res) => {
+                        info!("Connected to the live AIS stream at {}", res.url());
+                        let mut body = res.bytes_stream();
+                        let mut buffer: Vec<u8> = Vec::new();
+
+                        while let Some(chunk) = body.next().await {
+                            match chunk {
+                                Ok(bytes) => {
+                                    buffer.extend_from_slice(&bytes);
+                                    while let Some(position) = buffer.iter().position(|b| *b == b'\n') {
+                                        let line: Vec<u8> = buffer.drain(..=position).collect();
+                                        if let Some(item) = parse_stream_line(&line) {
+                                            yield item;
+                                        }
+                                    }
+                                }
+                                Err(error) => {
+                                    yield Err(ResponseErrorMessages::NetworkError(error));
+                                    break;
+                                }
+                            }
+                        }
+                        warn!("Live AIS stream was closed, reconnecting.");
+                    }
+                    Err(ResponseErrorMessages::CircuitOpen { until }) => {
+                        // Wait for the breaker instead of being refused every few seconds.
+                        yield Err(ResponseErrorMessages::CircuitOpen { until });
+                        tokio::time::sleep((until - Utc::now()).to_std().unwrap_or_default()).await;
+                        continue;
+                    }
+                    Err(error) => yield Err(error),
+                }
+
+                tokio::time::sleep(STREAM_RECONNECT_DELAY).await;
+            }
+        }
+    }
+
+    pub fn stream_endpoint(&self) -> String {
+        format!("{}/v1/ais", self.base_url())
+    }
+
+    fn base_url(&self) -> &str {
+        self.api.base_url.trim_end_matches('/')
+    }
+
+    async fn open_ais_stream(&self) -> Result<Response, ResponseErrorMessages> {
+        let url = reqwest::Url::parse(&self.stream_endpoint())
+            .map_err(ResponseErrorMessages::InvalidUrl)?;
+        debug!("Method open_ais_stream - Value of URL: {}", url);
+
+        // No timeout, it would end the stream.
+        self.send_authenticated(Method::GET, url, None, None).await
+    }
+
+    // Sends a request with the token through the circuit breaker, which counts the request as one
+    // failure however many attempts it took.
+    async fn send_authenticated(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<&FilterInput>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, ResponseErrorMessages> {
+        self.circuit_breaker().check()?;
+        let result = self.send_with_retries(method, url, body, timeout).await;
+        match &result {
+            Ok(_) => self.circuit_breaker().record_success(),
+            Err(_) => self.circuit_breaker().record_failure(),
+        }
+        result
+    }
+
+    // Retries transient failures and fetches a new token once when the current one is refused,
+    // such as after it was revoked before it expired.
+    async fn send_with_retries(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<&FilterInput>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, ResponseErrorMessages> {
+        let mut backoff = Backoff::new(&self.retry);
+        let mut reauthenticated = false;
+        loop {
+            let token = self.tokens.access_token().await?;
+
+            let mut request = token.authorize(self.client.request(method.clone(), url.clone()));
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout);
+            }
+            match classify(request.send().await) {
+                Attempt::Success(res) => return Ok(res),
+                Attempt::Unauthorized(error) => {
+                    self.failed_requests.push(url.as_str(), Some(StatusCode::UNAUTHORIZED), &error);
+                    if reauthenticated {
+                        return Err(error);
+                    }
+                    info!("The token was refused, fetching a new one.");
+                    reauthenticated = true;
+                    self.tokens.invalidate(&token).await;
+                }
+                Attempt::Transient {
+                    error,
+                    status_code,
+                    retry_after,
+                } => {
+                    self.failed_requests.push(url.as_str(), status_code, &error);
+                    let Some(delay) = backoff.next_delay(retry_after) else {
+                        return Err(error);
+                    };
+                    warn!("Request to {} failed ({}), attempt {} in {:.1?}", url, error, backoff.attempt(), delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Attempt::Fatal { error, status_code } => {
+                    self.failed_requests.push(url.as_str(), Some(status_code), &error);
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+// Reads the messages of a latest response, in whichever format they were asked for.
+async fn read_latest_response(
+    res: Response,
+    model_format: ModelFormat,
+) -> Result<GetAISLatestResponse, ResponseErrorMessages> {
+    match res.status() {
+        StatusCode::OK => {
+            debug!("Content length: {:#?}", &res.content_length());
+            let mut latest_response = GetAISLatestResponse {
+                api_endpoint: res.url().to_string(),
+                status_code: res.status().as_u16(),
+                content_length: None,
+                ais_latest_responses: None,
+            };
+
+            let latest_ais_response: AISLatestResponses = match model_format {
+                ModelFormat::Json => res
                     .json::<AISLatestResponses>()
                     .await
-                    .map_err(ResponseErrorMessages::DeserializationError)?;
-                info!(
-                    "Successfully fetched and deserialized GetAISLatestResponse. Number of messages received: {}",
-                    &latest_ais_response.len()
-                );
-                latest_response.content_length = Some(latest_ais_response.len());
-                latest_response.ais_latest_responses = Some(latest_ais_response);
-
-                Ok(latest_response)
-            }
-            status_code => Err(ResponseErrorMessages::UnexpectedStatusCode(status_code)),
+                    .map_err(ResponseErrorMessages::DeserializationError)?,
+                ModelFormat::Geojson => {
+                    let collection = res
+                        .json::<serde_json::Value>()
+                        .await
+                        .map_err(ResponseErrorMessages::DeserializationError)?;
+                    messages_from_geojson(collection).map_err(ResponseErrorMessages::InvalidGeoJson)?
+                }
+            };
+            info!(
+                "Successfully fetched and deserialized GetAISLatestResponse. Number of messages received: {}",
+                &latest_ais_response.len()
+            );
+            latest_response.content_length = Some(latest_ais_response.len());
+            latest_response.ais_latest_responses = Some(latest_ais_response);
+
+            Ok(latest_response)
         }
+        status_code => Err(ResponseErrorMessages::UnexpectedStatusCode(status_code)),
+    }
+}
+
+// Servers without `/v1/latest/combined` answer with one of these.
+fn filters_unsupported(status_code: StatusCode) -> bool {
+    matches!(
+        status_code,
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    )
+}
+
+// The stream is newline delimited JSON. Server-sent event framing (`data:` prefixes, comments and
+// keep-alive blank lines) is tolerated so the same parser works for the SSE flavour of the endpoint.
+fn parse_stream_line(
+    line: &[u8],
+) -> Option<Result<AisMessage, ResponseErrorMessages>> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    let payload = line.strip_prefix("data:").unwrap_or(line).trim();
+    if payload.is_empty() || payload.starts_with(':') || payload.starts_with("event:") {
+        return None;
     }
+
+    Some(
+        serde_json::from_str::<AisMessage>(payload)
+            .map_err(ResponseErrorMessages::InvalidStreamMessage),
+    )
 }
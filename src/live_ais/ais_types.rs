@@ -0,0 +1,924 @@
+//! Typed versions of the coded fields in AIS messages, following ITU-R M.1371-5.
+//!
+//! Every type converts losslessly from and to the raw code: codes the recommendation leaves
+//! reserved or that are out of range end up in `Unknown` and convert back to the same number.
+//! They serialize as the raw code so the JSON representation is unchanged.
+//! [`Eta`] is the voyage ETA, which comes without a year and is stored with the year resolved
+//! from the time of the message.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum NavigationalStatus {
+    UnderWayUsingEngine,
+    AtAnchor,
+    NotUnderCommand,
+    RestrictedManoeuvrability,
+    ConstrainedByDraught,
+    Moored,
+    Aground,
+    EngagedInFishing,
+    UnderWaySailing,
+    /// Reserved for high speed craft.
+    ReservedHsc,
+    /// Reserved for wing in ground craft.
+    ReservedWig,
+    PowerDrivenVesselTowingAstern,
+    PowerDrivenVesselPushingAheadOrTowingAlongside,
+    /// AIS-SART, MOB-AIS or EPIRB-AIS transmitting.
+    AisSartActive,
+    NotDefined,
+    Unknown(i64),
+}
+
+impl From<i64> for NavigationalStatus {
+    fn from(code: i64) -> Self {
+        match code {
+            0 => NavigationalStatus::UnderWayUsingEngine,
+            1 => NavigationalStatus::AtAnchor,
+            2 => NavigationalStatus::NotUnderCommand,
+            3 => NavigationalStatus::RestrictedManoeuvrability,
+            4 => NavigationalStatus::ConstrainedByDraught,
+            5 => NavigationalStatus::Moored,
+            6 => NavigationalStatus::Aground,
+            7 => NavigationalStatus::EngagedInFishing,
+            8 => NavigationalStatus::UnderWaySailing,
+            9 => NavigationalStatus::ReservedHsc,
+            10 => NavigationalStatus::ReservedWig,
+            11 => NavigationalStatus::PowerDrivenVesselTowingAstern,
+            12 => NavigationalStatus::PowerDrivenVesselPushingAheadOrTowingAlongside,
+            14 => NavigationalStatus::AisSartActive,
+            15 => NavigationalStatus::NotDefined,
+            code => NavigationalStatus::Unknown(code),
+        }
+    }
+}
+
+impl From<NavigationalStatus> for i64 {
+    fn from(status: NavigationalStatus) -> Self {
+        match status {
+            NavigationalStatus::UnderWayUsingEngine => 0,
+            NavigationalStatus::AtAnchor => 1,
+            NavigationalStatus::NotUnderCommand => 2,
+            NavigationalStatus::RestrictedManoeuvrability => 3,
+            NavigationalStatus::ConstrainedByDraught => 4,
+            NavigationalStatus::Moored => 5,
+            NavigationalStatus::Aground => 6,
+            NavigationalStatus::EngagedInFishing => 7,
+            NavigationalStatus::UnderWaySailing => 8,
+            NavigationalStatus::ReservedHsc => 9,
+            NavigationalStatus::ReservedWig => 10,
+            NavigationalStatus::PowerDrivenVesselTowingAstern => 11,
+            NavigationalStatus::PowerDrivenVesselPushingAheadOrTowingAlongside => 12,
+            NavigationalStatus::AisSartActive => 14,
+            NavigationalStatus::NotDefined => 15,
+            NavigationalStatus::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for NavigationalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavigationalStatus::UnderWayUsingEngine => write!(f, "Under way using engine"),
+            NavigationalStatus::AtAnchor => write!(f, "At anchor"),
+            NavigationalStatus::NotUnderCommand => write!(f, "Not under command"),
+            NavigationalStatus::RestrictedManoeuvrability => write!(f, "Restricted manoeuvrability"),
+            NavigationalStatus::ConstrainedByDraught => write!(f, "Constrained by her draught"),
+            NavigationalStatus::Moored => write!(f, "Moored"),
+            NavigationalStatus::Aground => write!(f, "Aground"),
+            NavigationalStatus::EngagedInFishing => write!(f, "Engaged in fishing"),
+            NavigationalStatus::UnderWaySailing => write!(f, "Under way sailing"),
+            NavigationalStatus::ReservedHsc => write!(f, "Reserved for high speed craft"),
+            NavigationalStatus::ReservedWig => write!(f, "Reserved for wing in ground craft"),
+            NavigationalStatus::PowerDrivenVesselTowingAstern => {
+                write!(f, "Power-driven vessel towing astern")
+            }
+            NavigationalStatus::PowerDrivenVesselPushingAheadOrTowingAlongside => {
+                write!(f, "Power-driven vessel pushing ahead or towing alongside")
+            }
+            NavigationalStatus::AisSartActive => write!(f, "AIS-SART active"),
+            NavigationalStatus::NotDefined => write!(f, "Not defined"),
+            NavigationalStatus::Unknown(code) => write!(f, "Unknown navigational status {}", code),
+        }
+    }
+}
+
+/// Second digit of the ship type codes that carry one, the category of dangerous goods, harmful
+/// substances or marine pollutants on board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HazardCategory {
+    /// All ships of this type.
+    All,
+    /// Category X.
+    A,
+    /// Category Y.
+    B,
+    /// Category Z.
+    C,
+    /// Category OS.
+    D,
+    Reserved(u8),
+    NoAdditionalInformation,
+}
+
+impl HazardCategory {
+    fn from_digit(digit: u8) -> Self {
+        match digit {
+            0 => HazardCategory::All,
+            1 => HazardCategory::A,
+            2 => HazardCategory::B,
+            3 => HazardCategory::C,
+            4 => HazardCategory::D,
+            9 => HazardCategory::NoAdditionalInformation,
+            digit => HazardCategory::Reserved(digit),
+        }
+    }
+
+    fn digit(self) -> i64 {
+        match self {
+            HazardCategory::All => 0,
+            HazardCategory::A => 1,
+            HazardCategory::B => 2,
+            HazardCategory::C => 3,
+            HazardCategory::D => 4,
+            HazardCategory::Reserved(digit) => i64::from(digit),
+            HazardCategory::NoAdditionalInformation => 9,
+        }
+    }
+}
+
+impl fmt::Display for HazardCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HazardCategory::All => write!(f, "all ships of this type"),
+            HazardCategory::A => write!(f, "hazardous category A"),
+            HazardCategory::B => write!(f, "hazardous category B"),
+            HazardCategory::C => write!(f, "hazardous category C"),
+            HazardCategory::D => write!(f, "hazardous category D"),
+            HazardCategory::Reserved(digit) => write!(f, "reserved category {}", digit),
+            HazardCategory::NoAdditionalInformation => write!(f, "no additional information"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum ShipType {
+    NotAvailable,
+    WingInGround { hazard: HazardCategory },
+    Fishing,
+    Towing,
+    /// Towing where the length of the tow exceeds 200 m or its breadth exceeds 25 m.
+    TowingLarge,
+    DredgingOrUnderwaterOperations,
+    DivingOperations,
+    MilitaryOperations,
+    Sailing,
+    PleasureCraft,
+    HighSpeedCraft { hazard: HazardCategory },
+    PilotVessel,
+    SearchAndRescue,
+    Tug,
+    PortTender,
+    AntiPollution,
+    LawEnforcement,
+    MedicalTransport,
+    /// Noncombatant ship according to RR Resolution No. 18.
+    Noncombatant,
+    Passenger { hazard: HazardCategory },
+    Cargo { hazard: HazardCategory },
+    Tanker { hazard: HazardCategory },
+    Other { hazard: HazardCategory },
+    Unknown(i64),
+}
+
+impl From<i64> for ShipType {
+    fn from(code: i64) -> Self {
+        let hazard = HazardCategory::from_digit((code.rem_euclid(10)) as u8);
+        match code {
+            0 => ShipType::NotAvailable,
+            20..=29 => ShipType::WingInGround { hazard },
+            30 => ShipType::Fishing,
+            31 => ShipType::Towing,
+            32 => ShipType::TowingLarge,
+            33 => ShipType::DredgingOrUnderwaterOperations,
+            34 => ShipType::DivingOperations,
+            35 => ShipType::MilitaryOperations,
+            36 => ShipType::Sailing,
+            37 => ShipType::PleasureCraft,
+            40..=49 => ShipType::HighSpeedCraft { hazard },
+            50 => ShipType::PilotVessel,
+            51 => ShipType::SearchAndRescue,
+            52 => ShipType::Tug,
+            53 => ShipType::PortTender,
+            54 => ShipType::AntiPollution,
+            55 => ShipType::LawEnforcement,
+            58 => ShipType::MedicalTransport,
+            59 => ShipType::Noncombatant,
+            60..=69 => ShipType::Passenger { hazard },
+            70..=79 => ShipType::Cargo { hazard },
+            80..=89 => ShipType::Tanker { hazard },
+            90..=99 => ShipType::Other { hazard },
+            code => ShipType::Unknown(code),
+        }
+    }
+}
+
+impl From<ShipType> for i64 {
+    fn from(ship_type: ShipType) -> Self {
+        match ship_type {
+            ShipType::NotAvailable => 0,
+            ShipType::WingInGround { hazard } => 20 + hazard.digit(),
+            ShipType::Fishing => 30,
+            ShipType::Towing => 31,
+            ShipType::TowingLarge => 32,
+            ShipType::DredgingOrUnderwaterOperations => 33,
+            ShipType::DivingOperations => 34,
+            ShipType::MilitaryOperations => 35,
+            ShipType::Sailing => 36,
+            ShipType::PleasureCraft => 37,
+            ShipType::HighSpeedCraft { hazard } => 40 + hazard.digit(),
+            ShipType::PilotVessel => 50,
+            ShipType::SearchAndRescue => 51,
+            ShipType::Tug => 52,
+            ShipType::PortTender => 53,
+            ShipType::AntiPollution => 54,
+            ShipType::LawEnforcement => 55,
+            ShipType::MedicalTransport => 58,
+            ShipType::Noncombatant => 59,
+            ShipType::Passenger { hazard } => 60 + hazard.digit(),
+            ShipType::Cargo { hazard } => 70 + hazard.digit(),
+            ShipType::Tanker { hazard } => 80 + hazard.digit(),
+            ShipType::Other { hazard } => 90 + hazard.digit(),
+            ShipType::Unknown(code) => code,
+        }
+    }
+}
+
+// The ship type is stored as an INT, unlike the other codes.
+impl From<i32> for ShipType {
+    fn from(code: i32) -> Self {
+        ShipType::from(i64::from(code))
+    }
+}
+
+/// Fails for an `Unknown` code read from JSON that does not fit the column, AIS itself only has
+/// eight bits for it.
+impl TryFrom<ShipType> for i32 {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(ship_type: ShipType) -> Result<Self, Self::Error> {
+        i32::try_from(i64::from(ship_type))
+    }
+}
+
+impl fmt::Display for ShipType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShipType::NotAvailable => write!(f, "Not available"),
+            ShipType::WingInGround { hazard } => write!(f, "Wing in ground, {}", hazard),
+            ShipType::Fishing => write!(f, "Fishing"),
+            ShipType::Towing => write!(f, "Towing"),
+            ShipType::TowingLarge => write!(f, "Towing, length exceeds 200 m or breadth exceeds 25 m"),
+            ShipType::DredgingOrUnderwaterOperations => write!(f, "Dredging or underwater operations"),
+            ShipType::DivingOperations => write!(f, "Diving operations"),
+            ShipType::MilitaryOperations => write!(f, "Military operations"),
+            ShipType::Sailing => write!(f, "Sailing"),
+            ShipType::PleasureCraft => write!(f, "Pleasure craft"),
+            ShipType::HighSpeedCraft { hazard } => write!(f, "High speed craft, {}", hazard),
+            ShipType::PilotVessel => write!(f, "Pilot vessel"),
+            ShipType::SearchAndRescue => write!(f, "Search and rescue vessel"),
+            ShipType::Tug => write!(f, "Tug"),
+            ShipType::PortTender => write!(f, "Port tender"),
+            ShipType::AntiPollution => write!(f, "Anti-pollution equipment"),
+            ShipType::LawEnforcement => write!(f, "Law enforcement"),
+            ShipType::MedicalTransport => write!(f, "Medical transport"),
+            ShipType::Noncombatant => write!(f, "Noncombatant ship"),
+            ShipType::Passenger { hazard } => write!(f, "Passenger, {}", hazard),
+            ShipType::Cargo { hazard } => write!(f, "Cargo, {}", hazard),
+            ShipType::Tanker { hazard } => write!(f, "Tanker, {}", hazard),
+            ShipType::Other { hazard } => write!(f, "Other type, {}", hazard),
+            ShipType::Unknown(code) => write!(f, "Unknown ship type {}", code),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum AtonType {
+    NotSpecified,
+    ReferencePoint,
+    Racon,
+    /// Fixed structure off shore, such as an oil platform or wind farm.
+    FixedStructure,
+    LightWithoutSectors,
+    LightWithSectors,
+    LeadingLightFront,
+    LeadingLightRear,
+    BeaconCardinalNorth,
+    BeaconCardinalEast,
+    BeaconCardinalSouth,
+    BeaconCardinalWest,
+    BeaconPortHand,
+    BeaconStarboardHand,
+    BeaconPreferredChannelPortHand,
+    BeaconPreferredChannelStarboardHand,
+    BeaconIsolatedDanger,
+    BeaconSafeWater,
+    BeaconSpecialMark,
+    CardinalMarkNorth,
+    CardinalMarkEast,
+    CardinalMarkSouth,
+    CardinalMarkWest,
+    PortHandMark,
+    StarboardHandMark,
+    PreferredChannelPortHand,
+    PreferredChannelStarboardHand,
+    IsolatedDanger,
+    SafeWater,
+    SpecialMark,
+    /// Light vessel, LANBY or rig.
+    LightVessel,
+    Unknown(i64),
+}
+
+impl From<i64> for AtonType {
+    fn from(code: i64) -> Self {
+        match code {
+            0 => AtonType::NotSpecified,
+            1 => AtonType::ReferencePoint,
+            2 => AtonType::Racon,
+            3 => AtonType::FixedStructure,
+            5 => AtonType::LightWithoutSectors,
+            6 => AtonType::LightWithSectors,
+            7 => AtonType::LeadingLightFront,
+            8 => AtonType::LeadingLightRear,
+            9 => AtonType::BeaconCardinalNorth,
+            10 => AtonType::BeaconCardinalEast,
+            11 => AtonType::BeaconCardinalSouth,
+            12 => AtonType::BeaconCardinalWest,
+            13 => AtonType::BeaconPortHand,
+            14 => AtonType::BeaconStarboardHand,
+            15 => AtonType::BeaconPreferredChannelPortHand,
+            16 => AtonType::BeaconPreferredChannelStarboardHand,
+            17 => AtonType::BeaconIsolatedDanger,
+            18 => AtonType::BeaconSafeWater,
+            19 => AtonType::BeaconSpecialMark,
+            20 => AtonType::CardinalMarkNorth,
+            21 => AtonType::CardinalMarkEast,
+            22 => AtonType::CardinalMarkSouth,
+            23 => AtonType::CardinalMarkWest,
+            24 => AtonType::PortHandMark,
+            25 => AtonType::StarboardHandMark,
+            26 => AtonType::PreferredChannelPortHand,
+            27 => AtonType::PreferredChannelStarboardHand,
+            28 => AtonType::IsolatedDanger,
+            29 => AtonType::SafeWater,
+            30 => AtonType::SpecialMark,
+            31 => AtonType::LightVessel,
+            code => AtonType::Unknown(code),
+        }
+    }
+}
+
+impl From<AtonType> for i64 {
+    fn from(aton_type: AtonType) -> Self {
+        match aton_type {
+            AtonType::NotSpecified => 0,
+            AtonType::ReferencePoint => 1,
+            AtonType::Racon => 2,
+            AtonType::FixedStructure => 3,
+            AtonType::LightWithoutSectors => 5,
+            AtonType::LightWithSectors => 6,
+            AtonType::LeadingLightFront => 7,
+            AtonType::LeadingLightRear => 8,
+            AtonType::BeaconCardinalNorth => 9,
+            AtonType::BeaconCardinalEast => 10,
+            AtonType::BeaconCardinalSouth => 11,
+            AtonType::BeaconCardinalWest => 12,
+            AtonType::BeaconPortHand => 13,
+            AtonType::BeaconStarboardHand => 14,
+            AtonType::BeaconPreferredChannelPortHand => 15,
+            AtonType::BeaconPreferredChannelStarboardHand => 16,
+            AtonType::BeaconIsolatedDanger => 17,
+            AtonType::BeaconSafeWater => 18,
+            AtonType::BeaconSpecialMark => 19,
+            AtonType::CardinalMarkNorth => 20,
+            AtonType::CardinalMarkEast => 21,
+            AtonType::CardinalMarkSouth => 22,
+            AtonType::CardinalMarkWest => 23,
+            AtonType::PortHandMark => 24,
+            AtonType::StarboardHandMark => 25,
+            AtonType::PreferredChannelPortHand => 26,
+            AtonType::PreferredChannelStarboardHand => 27,
+            AtonType::IsolatedDanger => 28,
+            AtonType::SafeWater => 29,
+            AtonType::SpecialMark => 30,
+            AtonType::LightVessel => 31,
+            AtonType::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for AtonType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtonType::NotSpecified => write!(f, "Type of AtoN not specified"),
+            AtonType::ReferencePoint => write!(f, "Reference point"),
+            AtonType::Racon => write!(f, "RACON"),
+            AtonType::FixedStructure => write!(f, "Fixed structure off shore"),
+            AtonType::LightWithoutSectors => write!(f, "Light, without sectors"),
+            AtonType::LightWithSectors => write!(f, "Light, with sectors"),
+            AtonType::LeadingLightFront => write!(f, "Leading light front"),
+            AtonType::LeadingLightRear => write!(f, "Leading light rear"),
+            AtonType::BeaconCardinalNorth => write!(f, "Beacon, cardinal N"),
+            AtonType::BeaconCardinalEast => write!(f, "Beacon, cardinal E"),
+            AtonType::BeaconCardinalSouth => write!(f, "Beacon, cardinal S"),
+            AtonType::BeaconCardinalWest => write!(f, "Beacon, cardinal W"),
+            AtonType::BeaconPortHand => write!(f, "Beacon, port hand"),
+            AtonType::BeaconStarboardHand => write!(f, "Beacon, starboard hand"),
+            AtonType::BeaconPreferredChannelPortHand => write!(f, "Beacon, preferred channel port hand"),
+            AtonType::BeaconPreferredChannelStarboardHand => {
+                write!(f, "Beacon, preferred channel starboard hand")
+            }
+            AtonType::BeaconIsolatedDanger => write!(f, "Beacon, isolated danger"),
+            AtonType::BeaconSafeWater => write!(f, "Beacon, safe water"),
+            AtonType::BeaconSpecialMark => write!(f, "Beacon, special mark"),
+            AtonType::CardinalMarkNorth => write!(f, "Cardinal mark N"),
+            AtonType::CardinalMarkEast => write!(f, "Cardinal mark E"),
+            AtonType::CardinalMarkSouth => write!(f, "Cardinal mark S"),
+            AtonType::CardinalMarkWest => write!(f, "Cardinal mark W"),
+            AtonType::PortHandMark => write!(f, "Port hand mark"),
+            AtonType::StarboardHandMark => write!(f, "Starboard hand mark"),
+            AtonType::PreferredChannelPortHand => write!(f, "Preferred channel port hand"),
+            AtonType::PreferredChannelStarboardHand => write!(f, "Preferred channel starboard hand"),
+            AtonType::IsolatedDanger => write!(f, "Isolated danger"),
+            AtonType::SafeWater => write!(f, "Safe water"),
+            AtonType::SpecialMark => write!(f, "Special mark"),
+            AtonType::LightVessel => write!(f, "Light vessel, LANBY or rig"),
+            AtonType::Unknown(code) => write!(f, "Unknown AtoN type {}", code),
+        }
+    }
+}
+
+/// Type of electronic position fixing device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum EpfdType {
+    Undefined,
+    Gps,
+    Glonass,
+    CombinedGpsGlonass,
+    LoranC,
+    Chayka,
+    IntegratedNavigationSystem,
+    Surveyed,
+    Galileo,
+    InternalGnss,
+    Unknown(i64),
+}
+
+impl From<i64> for EpfdType {
+    fn from(code: i64) -> Self {
+        match code {
+            0 => EpfdType::Undefined,
+            1 => EpfdType::Gps,
+            2 => EpfdType::Glonass,
+            3 => EpfdType::CombinedGpsGlonass,
+            4 => EpfdType::LoranC,
+            5 => EpfdType::Chayka,
+            6 => EpfdType::IntegratedNavigationSystem,
+            7 => EpfdType::Surveyed,
+            8 => EpfdType::Galileo,
+            15 => EpfdType::InternalGnss,
+            code => EpfdType::Unknown(code),
+        }
+    }
+}
+
+impl From<EpfdType> for i64 {
+    fn from(epfd_type: EpfdType) -> Self {
+        match epfd_type {
+            EpfdType::Undefined => 0,
+            EpfdType::Gps => 1,
+            EpfdType::Glonass => 2,
+            EpfdType::CombinedGpsGlonass => 3,
+            EpfdType::LoranC => 4,
+            EpfdType::Chayka => 5,
+            EpfdType::IntegratedNavigationSystem => 6,
+            EpfdType::Surveyed => 7,
+            EpfdType::Galileo => 8,
+            EpfdType::InternalGnss => 15,
+            EpfdType::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for EpfdType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpfdType::Undefined => write!(f, "Undefined"),
+            EpfdType::Gps => write!(f, "GPS"),
+            EpfdType::Glonass => write!(f, "GLONASS"),
+            EpfdType::CombinedGpsGlonass => write!(f, "Combined GPS/GLONASS"),
+            EpfdType::LoranC => write!(f, "Loran-C"),
+            EpfdType::Chayka => write!(f, "Chayka"),
+            EpfdType::IntegratedNavigationSystem => write!(f, "Integrated navigation system"),
+            EpfdType::Surveyed => write!(f, "Surveyed"),
+            EpfdType::Galileo => write!(f, "Galileo"),
+            EpfdType::InternalGnss => write!(f, "Internal GNSS"),
+            EpfdType::Unknown(code) => write!(f, "Unknown EPFD type {}", code),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("invalid ETA {0:?}")]
+pub struct EtaParseError(pub String);
+
+/// Estimated time of arrival as broadcast in static voyage data: a month, day, hour and minute in
+/// UTC without a year, each of which may be "not available".
+///
+/// Parses the AIS `MMDDHHMM` form, `MM-DD HH:MM`, and full timestamps (whose year is dropped, see
+/// [`Eta::resolve`]). Displays as `MMDDHHMM` using the AIS not-available values 0, 0, 24 and 60.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Eta {
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    pub hour: Option<u32>,
+    pub minute: Option<u32>,
+}
+
+impl Eta {
+    /// Builds an ETA from the fields as they are encoded in the message, where 0, 0, 24 and 60 mean
+    /// not available. Returns `None` when a field is out of range.
+    pub fn from_raw(month: u32, day: u32, hour: u32, minute: u32) -> Option<Self> {
+        if month > 12 || day > 31 || hour > 24 || minute > 60 {
+            return None;
+        }
+        Some(Eta {
+            month: (month != 0).then_some(month),
+            day: (day != 0).then_some(day),
+            hour: (hour != 24).then_some(hour),
+            minute: (minute != 60).then_some(minute),
+        })
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.month.is_some() && self.day.is_some()
+    }
+
+    /// The ETA as a timestamp, taking the year from `msgtime`: the year is chosen so the ETA lies
+    /// within six months of the message, which handles voyages across new year in both
+    /// directions. Returns `None` when the month or day is not available or the date does not
+    /// exist; a missing hour or minute counts as zero.
+    pub fn resolve(&self, msgtime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let (month, day) = (self.month?, self.day?);
+        let at_year = |year: i32| {
+            Utc.with_ymd_and_hms(year, month, day, self.hour.unwrap_or(0), self.minute.unwrap_or(0), 0)
+                .single()
+        };
+
+        // A leap day may only exist in the year before or after the message.
+        [msgtime.year(), msgtime.year() + 1, msgtime.year() - 1]
+            .into_iter()
+            .filter_map(at_year)
+            .find(|eta| (msgtime - Duration::days(183)..=msgtime + Duration::days(183)).contains(eta))
+    }
+}
+
+impl FromStr for Eta {
+    type Err = EtaParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = || EtaParseError(input.to_owned());
+        let trimmed = input.trim();
+        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("n/a") || trimmed.eq_ignore_ascii_case("na") {
+            return Ok(Eta::default());
+        }
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(trimmed) {
+            return Ok(Eta::from(timestamp.with_timezone(&Utc)));
+        }
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%dT%H:%M:%S") {
+            return Eta::from_raw(timestamp.month(), timestamp.day(), timestamp.hour(), timestamp.minute())
+                .ok_or_else(error);
+        }
+
+        let digits: String = trimmed.chars().filter(char::is_ascii_digit).collect();
+        let separators_only = trimmed
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '-' | ' ' | ':' | 'T'));
+        if digits.len() != 8 || !separators_only {
+            return Err(error());
+        }
+        let field = |range: std::ops::Range<usize>| digits[range].parse::<u32>().map_err(|_| error());
+        Eta::from_raw(field(0..2)?, field(2..4)?, field(4..6)?, field(6..8)?).ok_or_else(error)
+    }
+}
+
+impl fmt::Display for Eta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}{:02}{:02}{:02}",
+            self.month.unwrap_or(0),
+            self.day.unwrap_or(0),
+            self.hour.unwrap_or(24),
+            self.minute.unwrap_or(60)
+        )
+    }
+}
+
+impl Serialize for Eta {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Eta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        input.parse().map_err(de::Error::custom)
+    }
+}
+
+// Every part of a timestamp is in range, so this cannot fail.
+impl From<DateTime<Utc>> for Eta {
+    fn from(timestamp: DateTime<Utc>) -> Self {
+        Eta {
+            month: Some(timestamp.month()),
+            day: Some(timestamp.day()),
+            hour: Some(timestamp.hour()),
+            minute: Some(timestamp.minute()),
+        }
+    }
+}
+
+/// The ETA of a static message as it was received.
+///
+/// Text that is not an ETA is kept as it is, so validation can report it instead of the whole
+/// message failing to parse. Serializes as the ETA or the text that was received.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReceivedEta {
+    Eta(Eta),
+    Invalid(String),
+}
+
+impl ReceivedEta {
+    pub fn eta(&self) -> Option<Eta> {
+        match self {
+            ReceivedEta::Eta(eta) => Some(*eta),
+            ReceivedEta::Invalid(_) => None,
+        }
+    }
+
+    /// See [`Eta::resolve`], `None` for an invalid ETA.
+    pub fn resolve(&self, msgtime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.eta()?.resolve(msgtime)
+    }
+}
+
+impl From<Eta> for ReceivedEta {
+    fn from(eta: Eta) -> Self {
+        ReceivedEta::Eta(eta)
+    }
+}
+
+impl Serialize for ReceivedEta {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ReceivedEta::Eta(eta) => eta.serialize(serializer),
+            ReceivedEta::Invalid(input) => serializer.serialize_str(input),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ReceivedEta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        Ok(match input.parse() {
+            Ok(eta) => ReceivedEta::Eta(eta),
+            Err(_) => ReceivedEta::Invalid(input),
+        })
+    }
+}
+
+/// Deserializes an optional ETA, treating an ETA with every part not available as no ETA.
+pub fn deserialize_eta<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<ReceivedEta>, D::Error> {
+    let eta = Option::<ReceivedEta>::deserialize(deserializer)?;
+    Ok(eta.filter(|eta| *eta != ReceivedEta::Eta(Eta::default())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    // Every eight bit code converts to the type and back, and serializes as the code.
+    fn assert_round_trips<T>()
+    where
+        T: From<i64> + Into<i64> + Serialize + DeserializeOwned + PartialEq + fmt::Debug + Copy,
+    {
+        for code in 0..=255 {
+            let value = T::from(code);
+            assert_eq!(value.into(), code, "{:?}", value);
+            assert_eq!(serde_json::to_value(value).unwrap(), json!(code));
+            assert_eq!(serde_json::from_value::<T>(json!(code)).unwrap(), value);
+        }
+    }
+
+    // The codes that are not assigned, which display as unknown with their code.
+    fn unknown_codes<T: From<i64> + fmt::Display>(name: &str) -> Vec<i64> {
+        (0..=255)
+            .filter(|code| {
+                let text = T::from(*code).to_string();
+                let unknown = text.starts_with("Unknown");
+                if unknown {
+                    assert_eq!(text, format!("Unknown {} {}", name, code));
+                }
+                unknown
+            })
+            .collect()
+    }
+
+    #[test]
+    fn converts_every_code_back_and_forth() {
+        assert_round_trips::<NavigationalStatus>();
+        assert_round_trips::<ShipType>();
+        assert_round_trips::<AtonType>();
+        assert_round_trips::<EpfdType>();
+        assert_eq!(i64::from(ShipType::from(-1)), -1);
+        assert_eq!(i64::from(NavigationalStatus::from(4096)), 4096);
+    }
+
+    #[test]
+    fn displays_navigational_status() {
+        assert_eq!(NavigationalStatus::from(0).to_string(), "Under way using engine");
+        assert_eq!(NavigationalStatus::from(9).to_string(), "Reserved for high speed craft");
+        assert_eq!(NavigationalStatus::from(10).to_string(), "Reserved for wing in ground craft");
+        assert_eq!(NavigationalStatus::from(14).to_string(), "AIS-SART active");
+        assert_eq!(NavigationalStatus::from(15).to_string(), "Not defined");
+        assert_eq!(
+            unknown_codes::<NavigationalStatus>("navigational status"),
+            std::iter::once(13).chain(16..=255).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn displays_ship_type() {
+        assert_eq!(ShipType::from(0).to_string(), "Not available");
+        assert_eq!(ShipType::from(20).to_string(), "Wing in ground, all ships of this type");
+        assert_eq!(ShipType::from(25).to_string(), "Wing in ground, reserved category 5");
+        assert_eq!(ShipType::from(49).to_string(), "High speed craft, no additional information");
+        assert_eq!(ShipType::from(52).to_string(), "Tug");
+        assert_eq!(ShipType::from(71).to_string(), "Cargo, hazardous category A");
+        assert_eq!(ShipType::from(84).to_string(), "Tanker, hazardous category D");
+        assert_eq!(ShipType::from(98).to_string(), "Other type, reserved category 8");
+        assert_eq!(
+            unknown_codes::<ShipType>("ship type"),
+            (1..=19).chain(38..=39).chain(56..=57).chain(100..=255).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn displays_aton_type() {
+        assert_eq!(AtonType::from(0).to_string(), "Type of AtoN not specified");
+        assert_eq!(AtonType::from(3).to_string(), "Fixed structure off shore");
+        assert_eq!(AtonType::from(31).to_string(), "Light vessel, LANBY or rig");
+        assert_eq!(
+            unknown_codes::<AtonType>("AtoN type"),
+            std::iter::once(4).chain(32..=255).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn displays_epfd_type() {
+        assert_eq!(EpfdType::from(3).to_string(), "Combined GPS/GLONASS");
+        assert_eq!(EpfdType::from(15).to_string(), "Internal GNSS");
+        assert_eq!(unknown_codes::<EpfdType>("EPFD type"), (9..=14).chain(16..=255).collect::<Vec<_>>());
+    }
+
+    fn eta(month: u32, day: u32, hour: u32, minute: u32) -> Eta {
+        Eta {
+            month: Some(month),
+            day: Some(day),
+            hour: Some(hour),
+            minute: Some(minute),
+        }
+    }
+
+    fn time(input: &str) -> DateTime<Utc> {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_the_eta_forms() {
+        assert_eq!("12310800".parse(), Ok(eta(12, 31, 8, 0)));
+        assert_eq!("12-31 08:00".parse(), Ok(eta(12, 31, 8, 0)));
+        assert_eq!("2026-12-31T08:00:00Z".parse(), Ok(eta(12, 31, 8, 0)));
+        assert_eq!("2026-12-31T08:00:00".parse(), Ok(eta(12, 31, 8, 0)));
+        assert_eq!("02290800".parse(), Ok(eta(2, 29, 8, 0)));
+        assert_eq!("n/a".parse(), Ok(Eta::default()));
+        assert_eq!("".parse(), Ok(Eta::default()));
+    }
+
+    #[test]
+    fn parses_the_not_available_values() {
+        let parsed: Eta = "00002460".parse().unwrap();
+        assert_eq!(parsed, Eta::default());
+        assert!(!parsed.is_available());
+        assert_eq!(parsed.to_string(), "00002460");
+
+        let parsed: Eta = "03152460".parse().unwrap();
+        assert_eq!((parsed.hour, parsed.minute), (None, None));
+        assert_eq!(parsed.to_string(), "03152460");
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_eta() {
+        for input in ["13010800", "01320800", "01012561", "0101080", "soon", "12/31 08:00"] {
+            assert_eq!(input.parse::<Eta>(), Err(EtaParseError(input.to_owned())), "{}", input);
+        }
+    }
+
+    #[test]
+    fn resolves_in_the_year_of_the_message() {
+        assert_eq!(
+            eta(6, 1, 8, 30).resolve(time("2026-05-20T12:00:00Z")),
+            Some(time("2026-06-01T08:30:00Z"))
+        );
+        // A missing hour or minute counts as zero.
+        let without_time = Eta { hour: None, minute: None, ..eta(6, 1, 0, 0) };
+        assert_eq!(without_time.resolve(time("2026-05-20T12:00:00Z")), Some(time("2026-06-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn resolves_voyages_across_new_year() {
+        assert_eq!(
+            eta(1, 5, 12, 0).resolve(time("2026-12-20T12:00:00Z")),
+            Some(time("2027-01-05T12:00:00Z"))
+        );
+        assert_eq!(
+            eta(12, 28, 12, 0).resolve(time("2027-01-03T12:00:00Z")),
+            Some(time("2026-12-28T12:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn resolves_a_leap_day_in_the_year_it_exists() {
+        assert_eq!(
+            eta(2, 29, 8, 0).resolve(time("2027-12-15T12:00:00Z")),
+            Some(time("2028-02-29T08:00:00Z"))
+        );
+        assert_eq!(
+            eta(2, 29, 8, 0).resolve(time("2028-02-01T12:00:00Z")),
+            Some(time("2028-02-29T08:00:00Z"))
+        );
+        // No leap day within six months of the message.
+        assert_eq!(eta(2, 29, 8, 0).resolve(time("2029-01-10T12:00:00Z")), None);
+    }
+
+    #[test]
+    fn does_not_resolve_incomplete_or_impossible_dates() {
+        let msgtime = time("2026-05-20T12:00:00Z");
+        assert_eq!(Eta { month: None, ..eta(6, 1, 8, 0) }.resolve(msgtime), None);
+        assert_eq!(Eta { day: None, ..eta(6, 1, 8, 0) }.resolve(msgtime), None);
+        assert_eq!(eta(2, 30, 8, 0).resolve(msgtime), None);
+        assert_eq!(eta(4, 31, 8, 0).resolve(msgtime), None);
+    }
+
+    #[test]
+    fn keeps_received_text_that_is_not_an_eta() {
+        let received: ReceivedEta = serde_json::from_str("\"12310800\"").unwrap();
+        assert_eq!(received, ReceivedEta::Eta(eta(12, 31, 8, 0)));
+        assert_eq!(serde_json::to_string(&received).unwrap(), "\"12310800\"");
+        assert_eq!(received.resolve(time("2026-12-01T00:00:00Z")), Some(time("2026-12-31T08:00:00Z")));
+
+        let received: ReceivedEta = serde_json::from_str("\"next week\"").unwrap();
+        assert_eq!(received, ReceivedEta::Invalid("next week".to_owned()));
+        assert_eq!(serde_json::to_string(&received).unwrap(), "\"next week\"");
+        assert_eq!(received.eta(), None);
+        assert_eq!(received.resolve(time("2026-12-01T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn treats_an_eta_without_any_part_as_missing() {
+        #[derive(Deserialize)]
+        struct Voyage {
+            #[serde(deserialize_with = "deserialize_eta")]
+            eta: Option<ReceivedEta>,
+        }
+        let voyage = |json: &str| serde_json::from_str::<Voyage>(json).unwrap().eta;
+
+        assert_eq!(voyage(r#"{"eta": "00002460"}"#), None);
+        assert_eq!(voyage(r#"{"eta": null}"#), None);
+        assert_eq!(voyage(r#"{"eta": "03152460"}"#).and_then(|eta| eta.eta()).and_then(|eta| eta.month), Some(3));
+    }
+}
@@ -0,0 +1,267 @@
+//! Maritime Identification Digits, the part of an MMSI that tells the flag of the station.
+//!
+//! Countries are given as ISO 3166-1 alpha-3 codes. Territories with a MID of their own have
+//! their own code, except the Azores and Madeira which are Portugal.
+
+/// The MID of an MMSI, after the prefixes of coast stations, aids to navigation, search and rescue
+/// aircraft and handheld radios. `None` for MMSIs without a MID, such as AIS-SART and MOB devices.
+pub fn mid(mmsi: i64) -> Option<u32> {
+    if !(0..1_000_000_000).contains(&mmsi) {
+        return None;
+    }
+    let digits = format!("{:09}", mmsi);
+    let mid = match &digits[..3] {
+        "970" | "972" | "974" => return None,
+        // Search and rescue aircraft.
+        "111" => &digits[3..6],
+        // Aids to navigation and craft associated with a parent ship.
+        prefix if prefix.starts_with("99") || prefix.starts_with("98") => &digits[2..5],
+        // Coast stations and groups of ships.
+        prefix if prefix.starts_with("00") => &digits[2..5],
+        prefix if prefix.starts_with('0') || prefix.starts_with('8') => &digits[1..4],
+        prefix => prefix,
+    };
+    mid.parse().ok()
+}
+
+/// The flag country of an MMSI.
+pub fn country_code(mmsi: i64) -> Option<&'static str> {
+    mid(mmsi).and_then(mid_country_code)
+}
+
+/// The country a MID is allocated to.
+pub fn mid_country_code(mid: u32) -> Option<&'static str> {
+    let country = match mid {
+        201 => "ALB",
+        202 => "AND",
+        203 => "AUT",
+        204 | 255 | 263 => "PRT",
+        205 => "BEL",
+        206 => "BLR",
+        207 => "BGR",
+        208 => "VAT",
+        209 | 210 | 212 => "CYP",
+        211 | 218 => "DEU",
+        213 => "GEO",
+        214 => "MDA",
+        215 | 229 | 248 | 249 | 256 => "MLT",
+        216 => "ARM",
+        219 | 220 => "DNK",
+        224 | 225 => "ESP",
+        226..=228 => "FRA",
+        230 => "FIN",
+        231 => "FRO",
+        232..=235 => "GBR",
+        236 => "GIB",
+        237 | 239..=241 => "GRC",
+        238 => "HRV",
+        242 => "MAR",
+        243 => "HUN",
+        244..=246 => "NLD",
+        247 => "ITA",
+        250 => "IRL",
+        251 => "ISL",
+        252 => "LIE",
+        253 => "LUX",
+        254 => "MCO",
+        257..=259 => "NOR",
+        261 => "POL",
+        262 => "MNE",
+        264 => "ROU",
+        265 | 266 => "SWE",
+        267 => "SVK",
+        268 => "SMR",
+        269 => "CHE",
+        270 => "CZE",
+        271 => "TUR",
+        272 => "UKR",
+        273 => "RUS",
+        274 => "MKD",
+        275 => "LVA",
+        276 => "EST",
+        277 => "LTU",
+        278 => "SVN",
+        279 => "SRB",
+        301 => "AIA",
+        303 | 338 | 366..=369 => "USA",
+        304 | 305 => "ATG",
+        306 => "CUW",
+        307 => "ABW",
+        308 | 309 | 311 => "BHS",
+        310 => "BMU",
+        312 => "BLZ",
+        314 => "BRB",
+        316 => "CAN",
+        319 => "CYM",
+        321 => "CRI",
+        323 => "CUB",
+        325 => "DMA",
+        327 => "DOM",
+        329 => "GLP",
+        330 => "GRD",
+        331 => "GRL",
+        332 => "GTM",
+        334 => "HND",
+        336 => "HTI",
+        339 => "JAM",
+        341 => "KNA",
+        343 => "LCA",
+        345 => "MEX",
+        347 => "MTQ",
+        348 => "MSR",
+        350 => "NIC",
+        351..=357 | 370..=374 => "PAN",
+        358 => "PRI",
+        359 => "SLV",
+        361 => "SPM",
+        362 => "TTO",
+        364 => "TCA",
+        375..=377 => "VCT",
+        378 => "VGB",
+        379 => "VIR",
+        401 => "AFG",
+        403 => "SAU",
+        405 => "BGD",
+        408 => "BHR",
+        410 => "BTN",
+        412..=414 => "CHN",
+        416 => "TWN",
+        417 => "LKA",
+        419 => "IND",
+        422 => "IRN",
+        423 => "AZE",
+        425 => "IRQ",
+        428 => "ISR",
+        431 | 432 => "JPN",
+        434 => "TKM",
+        436 => "KAZ",
+        437 => "UZB",
+        438 => "JOR",
+        440 | 441 => "KOR",
+        443 => "PSE",
+        445 => "PRK",
+        447 => "KWT",
+        450 => "LBN",
+        451 => "KGZ",
+        453 => "MAC",
+        455 => "MDV",
+        457 => "MNG",
+        459 => "NPL",
+        461 => "OMN",
+        463 => "PAK",
+        466 => "QAT",
+        468 => "SYR",
+        470 | 471 => "ARE",
+        472 => "TJK",
+        473 | 475 => "YEM",
+        477 => "HKG",
+        478 => "BIH",
+        501 | 607 | 618 | 635 => "ATF",
+        503 => "AUS",
+        506 => "MMR",
+        508 => "BRN",
+        510 => "FSM",
+        511 => "PLW",
+        512 => "NZL",
+        514 | 515 => "KHM",
+        516 => "CXR",
+        518 => "COK",
+        520 => "FJI",
+        523 => "CCK",
+        525 => "IDN",
+        529 => "KIR",
+        531 => "LAO",
+        533 => "MYS",
+        536 => "MNP",
+        538 => "MHL",
+        540 => "NCL",
+        542 => "NIU",
+        544 => "NRU",
+        546 => "PYF",
+        548 => "PHL",
+        550 => "TLS",
+        553 => "PNG",
+        555 => "PCN",
+        557 => "SLB",
+        559 => "ASM",
+        561 => "WSM",
+        563..=566 => "SGP",
+        567 => "THA",
+        570 => "TON",
+        572 => "TUV",
+        574 => "VNM",
+        576 | 577 => "VUT",
+        578 => "WLF",
+        601 => "ZAF",
+        603 => "AGO",
+        605 => "DZA",
+        608 | 665 => "SHN",
+        609 => "BDI",
+        610 => "BEN",
+        611 => "BWA",
+        612 => "CAF",
+        613 => "CMR",
+        615 => "COG",
+        616 | 620 => "COM",
+        617 => "CPV",
+        619 => "CIV",
+        621 => "DJI",
+        622 => "EGY",
+        624 => "ETH",
+        625 => "ERI",
+        626 => "GAB",
+        627 => "GHA",
+        629 => "GMB",
+        630 => "GNB",
+        631 => "GNQ",
+        632 => "GIN",
+        633 => "BFA",
+        634 => "KEN",
+        636 | 637 => "LBR",
+        638 => "SSD",
+        642 => "LBY",
+        644 => "LSO",
+        645 => "MUS",
+        647 => "MDG",
+        649 => "MLI",
+        650 => "MOZ",
+        654 => "MRT",
+        655 => "MWI",
+        656 => "NER",
+        657 => "NGA",
+        659 => "NAM",
+        660 => "REU",
+        661 => "RWA",
+        662 => "SDN",
+        663 => "SEN",
+        664 => "SYC",
+        666 => "SOM",
+        667 => "SLE",
+        668 => "STP",
+        669 => "SWZ",
+        670 => "TCD",
+        671 => "TGO",
+        672 => "TUN",
+        674 | 677 => "TZA",
+        675 => "UGA",
+        676 => "COD",
+        678 => "ZMB",
+        679 => "ZWE",
+        701 => "ARG",
+        710 => "BRA",
+        720 => "BOL",
+        725 => "CHL",
+        730 => "COL",
+        735 => "ECU",
+        740 => "FLK",
+        745 => "GUF",
+        750 => "GUY",
+        755 => "PRY",
+        760 => "PER",
+        765 => "SUR",
+        770 => "URY",
+        775 => "VEN",
+        _ => return None,
+    };
+    Some(country)
+}
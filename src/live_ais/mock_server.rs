@@ -0,0 +1,433 @@
+//! A stand-in for the BarentsWatch API, to run the client and the ingestion without network access.
+//!
+//! [`MockServer`] serves the token endpoint, `/v1/latest/ais`, the filtered `/v1/latest/combined`
+//! and the `/v1/ais` stream on a local port, from the bundled fixtures unless a [`MockScript`]
+//! gives other messages. The API endpoints
+//! only accept tokens the server issued, and tokens can be revoked to make the client
+//! re-authenticate. Failures are scripted per endpoint and served in order before the endpoint
+//! answers normally again, to exercise the retries and the circuit breaker.
+
+use crate::database::configuration::ApiSettings;
+use crate::live_ais::query::{messages_to_geojson, AisQuery, FilterInput, ModelFormat};
+use crate::live_ais::response_structs::AisMessage;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, info};
+use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::oneshot;
+
+static FIXTURE_MESSAGES: &str = include_str!("../../fixtures/ais_messages.jsonl");
+
+/// The bundled messages: positions, static data and an aid to navigation.
+pub fn fixture_messages() -> Vec<Value> {
+    parse_json_lines(FIXTURE_MESSAGES).expect("the bundled fixtures are valid JSON lines")
+}
+
+/// Parses one JSON value per line, skipping blank lines.
+pub fn parse_json_lines(contents: &str) -> Result<Vec<Value>, serde_json::Error> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockEndpoint {
+    Token,
+    Latest,
+    Combined,
+    Stream,
+}
+
+impl MockEndpoint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MockEndpoint::Token => "token",
+            MockEndpoint::Latest => "latest",
+            MockEndpoint::Combined => "combined",
+            MockEndpoint::Stream => "stream",
+        }
+    }
+}
+
+impl fmt::Display for MockEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A response with `status` instead of the normal answer of `endpoint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptedFailure {
+    pub endpoint: MockEndpoint,
+    pub status: StatusCode,
+    /// Sent as `Retry-After` in seconds.
+    pub retry_after: Option<u64>,
+}
+
+impl ScriptedFailure {
+    pub fn new(endpoint: MockEndpoint, status: StatusCode) -> Self {
+        ScriptedFailure {
+            endpoint,
+            status,
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+/// Parses `endpoint:status` or `endpoint:status:retry_after`, such as `latest:429:2`.
+impl FromStr for ScriptedFailure {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.split(':');
+        let endpoint = match parts.next() {
+            Some("token") => MockEndpoint::Token,
+            Some("latest") => MockEndpoint::Latest,
+            Some("combined") => MockEndpoint::Combined,
+            Some("stream") => MockEndpoint::Stream,
+            _ => {
+                return Err(format!(
+                    "unknown endpoint in \"{}\", expected token, latest, combined or stream",
+                    value
+                ))
+            }
+        };
+        let status = parts
+            .next()
+            .and_then(|status| status.parse::<u16>().ok())
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .ok_or_else(|| format!("invalid status code in \"{}\"", value))?;
+        let retry_after = parts
+            .next()
+            .map(|seconds| {
+                seconds
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid retry after in \"{}\"", value))
+            })
+            .transpose()?;
+        if parts.next().is_some() {
+            return Err(format!("expected endpoint:status[:retry_after], got \"{}\"", value));
+        }
+
+        Ok(ScriptedFailure {
+            endpoint,
+            status,
+            retry_after,
+        })
+    }
+}
+
+/// What a [`MockServer`] answers with.
+#[derive(Debug, Clone)]
+pub struct MockScript {
+    /// Served by `/v1/latest/ais`, filtered by `since` on their `msgtime`, and by
+    /// `/v1/latest/combined` with the other filters too.
+    pub latest: Vec<Value>,
+    /// Sent on every connection to `/v1/ais`, which is closed afterwards.
+    pub stream: Vec<Value>,
+    pub token_expires_in: i64,
+    /// Whether `/v1/latest/combined` exists, it answers 404 otherwise like an older server.
+    pub server_filters: bool,
+    pub failures: Vec<ScriptedFailure>,
+}
+
+impl Default for MockScript {
+    fn default() -> Self {
+        MockScript {
+            latest: fixture_messages(),
+            stream: fixture_messages(),
+            token_expires_in: 3600,
+            server_filters: true,
+            failures: Vec::new(),
+        }
+    }
+}
+
+/// A request the server answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub endpoint: MockEndpoint,
+    pub status: StatusCode,
+}
+
+struct MockState {
+    script: MockScript,
+    failures: VecDeque<ScriptedFailure>,
+    tokens: HashSet<String>,
+    issued_tokens: usize,
+    requests: Vec<MockRequest>,
+}
+
+impl MockState {
+    fn take_failure(&mut self, endpoint: MockEndpoint) -> Option<ScriptedFailure> {
+        let position = self.failures.iter().position(|failure| failure.endpoint == endpoint)?;
+        self.failures.remove(position)
+    }
+}
+
+/// The mock API, running until it is dropped.
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// Starts serving on `address`, port 0 picks a free one.
+    pub async fn start(script: MockScript, address: SocketAddr) -> Result<Self, hyper::Error> {
+        let state = Arc::new(Mutex::new(MockState {
+            failures: script.failures.iter().cloned().collect(),
+            script,
+            tokens: HashSet::new(),
+            issued_tokens: 0,
+            requests: Vec::new(),
+        }));
+
+        let service_state = Arc::clone(&state);
+        let make_service = make_service_fn(move |_| {
+            let state = Arc::clone(&service_state);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(Arc::clone(&state), request)))
+            }
+        });
+        let server = Server::try_bind(&address)?.serve(make_service);
+        let address = server.local_addr();
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+        info!("Mock BarentsWatch API listening on http://{}", address);
+
+        Ok(MockServer {
+            address,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The settings that point a client at this server.
+    pub fn api_settings(&self) -> ApiSettings {
+        ApiSettings {
+            base_url: format!("http://{}", self.address),
+            token_url: format!("http://{}/connect/token", self.address),
+            server_filters: true,
+        }
+    }
+
+    /// The requests answered so far, in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Scripts another failure, after the ones already pending.
+    pub fn fail(&self, failure: ScriptedFailure) {
+        self.state().failures.push_back(failure);
+    }
+
+    /// Refuses every token issued so far, as when they were revoked before they expired.
+    pub fn revoke_tokens(&self) {
+        self.state().tokens.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+fn lock(state: &Mutex<MockState>) -> MutexGuard<'_, MockState> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+async fn handle(state: Arc<Mutex<MockState>>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let endpoint = match (request.method(), request.uri().path()) {
+        (&Method::POST, "/connect/token") => MockEndpoint::Token,
+        (&Method::GET, "/v1/latest/ais") => MockEndpoint::Latest,
+        (&Method::POST, "/v1/latest/combined") => MockEndpoint::Combined,
+        (&Method::GET, "/v1/ais") => MockEndpoint::Stream,
+        _ => return Ok(respond(StatusCode::NOT_FOUND, Body::empty())),
+    };
+    debug!("Mock API received {} {}", request.method(), request.uri());
+
+    let failure = lock(&state).take_failure(endpoint);
+    let response = match failure {
+        Some(failure) => {
+            let mut response = respond(failure.status, Body::empty());
+            if let Some(seconds) = failure.retry_after {
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+            }
+            response
+        }
+        None => match endpoint {
+            MockEndpoint::Token => issue_token(&state, request).await,
+            MockEndpoint::Latest if authorized(&state, &request) => latest(&state, &request),
+            MockEndpoint::Combined if !lock(&state).script.server_filters => respond(StatusCode::NOT_FOUND, Body::empty()),
+            MockEndpoint::Combined if authorized(&state, &request) => combined(&state, request).await,
+            MockEndpoint::Stream if authorized(&state, &request) => stream(&state),
+            _ => respond(StatusCode::UNAUTHORIZED, Body::empty()),
+        },
+    };
+
+    lock(&state).requests.push(MockRequest {
+        endpoint,
+        status: response.status(),
+    });
+    Ok(response)
+}
+
+async fn issue_token(state: &Mutex<MockState>, request: Request<Body>) -> Response<Body> {
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return respond(StatusCode::BAD_REQUEST, Body::empty()),
+    };
+    let form: Vec<(String, String)> = url::form_urlencoded::parse(&body).into_owned().collect();
+    let field = |name: &str| {
+        form.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    };
+
+    if field("grant_type") != Some("client_credentials") {
+        return json(StatusCode::BAD_REQUEST, &json!({ "error": "unsupported_grant_type" }));
+    }
+    if field("client_id").is_none() || field("client_secret").is_none() {
+        return json(StatusCode::UNAUTHORIZED, &json!({ "error": "invalid_client" }));
+    }
+
+    let mut state = lock(state);
+    state.issued_tokens += 1;
+    let access_token = format!("mock-token-{}", state.issued_tokens);
+    state.tokens.insert(access_token.clone());
+    json(
+        StatusCode::OK,
+        &json!({
+            "access_token": access_token,
+            "expires_in": state.script.token_expires_in,
+            "token_type": "Bearer",
+            "scope": field("scope").unwrap_or("ais"),
+        }),
+    )
+}
+
+fn authorized(state: &Mutex<MockState>, request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| lock(state).tokens.contains(token))
+}
+
+fn latest(state: &Mutex<MockState>, request: &Request<Body>) -> Response<Body> {
+    let since = request.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "since")
+            .and_then(|(_, since)| NaiveDateTime::parse_from_str(&since, "%Y-%m-%dT%H:%M:%S").ok())
+            .map(|since| since.and_utc())
+    });
+
+    let state = lock(state);
+    let messages: Vec<&Value> = state
+        .script
+        .latest
+        .iter()
+        .filter(|message| match (since, msgtime(message)) {
+            (Some(since), Some(msgtime)) => msgtime > since,
+            _ => true,
+        })
+        .collect();
+    json(StatusCode::OK, &messages)
+}
+
+async fn combined(state: &Mutex<MockState>, request: Request<Body>) -> Response<Body> {
+    let filter = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => serde_json::from_slice::<FilterInput>(&body),
+        Err(_) => return respond(StatusCode::BAD_REQUEST, Body::empty()),
+    };
+    let query = match filter {
+        Ok(filter) => AisQuery::from(filter),
+        Err(error) => return json(StatusCode::BAD_REQUEST, &json!({ "error": error.to_string() })),
+    };
+
+    let messages: Vec<AisMessage> = lock(state)
+        .script
+        .latest
+        .iter()
+        .filter_map(|message| serde_json::from_value(message.clone()).ok())
+        .filter(|message: &AisMessage| message.msgtime().is_none_or(|msgtime| msgtime > query.since()))
+        .collect();
+    let messages: Vec<Value> = query
+        .apply(messages)
+        .iter()
+        .filter_map(|message| serde_json::to_value(message).ok())
+        .collect();
+
+    match query.model_format() {
+        ModelFormat::Json => json(StatusCode::OK, &messages),
+        ModelFormat::Geojson => json(StatusCode::OK, &messages_to_geojson(&messages)),
+    }
+}
+
+fn stream(state: &Mutex<MockState>) -> Response<Body> {
+    let body: String = lock(state)
+        .script
+        .stream
+        .iter()
+        .map(|message| format!("{}\n", message))
+        .collect();
+    let mut response = respond(StatusCode::OK, Body::from(body));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+    response
+}
+
+fn msgtime(message: &Value) -> Option<DateTime<Utc>> {
+    message
+        .get("msgtime")
+        .and_then(Value::as_str)
+        .and_then(|msgtime| DateTime::parse_from_rfc3339(msgtime).ok())
+        .map(|msgtime| msgtime.with_timezone(&Utc))
+}
+
+fn json(status: StatusCode, body: &impl serde::Serialize) -> Response<Body> {
+    let mut response = respond(status, Body::from(serde_json::to_vec(body).unwrap_or_default()));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn respond(status: StatusCode, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+}
@@ -1,2 +1,9 @@
 pub mod ais_stream;
+pub mod ais_types;
+pub mod mid;
+pub mod mock_server;
+pub mod query;
 pub mod response_structs;
+pub mod retry;
+pub mod token;
+pub mod validation;
@@ -0,0 +1,482 @@
+//! Filters for the latest AIS request.
+//!
+//! An [`AisQuery`] is sent to `/v1/latest/combined`, which filters on the server so that only the
+//! vessels of interest are transferred. Against a server without that endpoint only `since` is
+//! sent to `/v1/latest/ais`, and [`AisQuery::apply`] filters the full response here instead.
+
+use crate::database::configuration::FilterSettings;
+use crate::live_ais::mid::country_code;
+use crate::live_ais::response_structs::AisMessage;
+use chrono::{DateTime, Utc};
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    Position,
+    Static,
+    Aton,
+}
+
+impl MessageKind {
+    fn of(message: &AisMessage) -> Option<Self> {
+        match message {
+            AisMessage::Position(_) => Some(MessageKind::Position),
+            AisMessage::StaticData(_) => Some(MessageKind::Static),
+            AisMessage::Aton(_) => Some(MessageKind::Aton),
+            AisMessage::Unknown(_) | AisMessage::Invalid(_) => None,
+        }
+    }
+}
+
+/// The format the server sends the messages in, they are read into the same messages either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelFormat {
+    #[default]
+    Json,
+    /// A feature collection with the position as the geometry of each message.
+    Geojson,
+}
+
+// The API spells the formats `Json` and `Geojson`.
+mod api_model_format {
+    use super::ModelFormat;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(model_format: &ModelFormat, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match model_format {
+            ModelFormat::Json => "Json",
+            ModelFormat::Geojson => "Geojson",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ModelFormat, D::Error> {
+        let model_format = String::deserialize(deserializer)?;
+        match model_format.to_ascii_lowercase().as_str() {
+            "json" => Ok(ModelFormat::Json),
+            "geojson" => Ok(ModelFormat::Geojson),
+            _ => Err(de::Error::unknown_variant(&model_format, &["Json", "Geojson"])),
+        }
+    }
+}
+
+/// An area given by its corners as `[longitude, latitude]`, sent as a GeoJSON polygon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "GeoJsonPolygon", into = "GeoJsonPolygon")]
+pub struct Polygon {
+    corners: Vec<[f64; 2]>,
+}
+
+impl Polygon {
+    /// A polygon with fewer than three corners contains nothing.
+    pub fn new(mut corners: Vec<[f64; 2]>) -> Self {
+        // GeoJSON repeats the first corner at the end, the ring is closed here either way.
+        if corners.len() > 1 && corners.first() == corners.last() {
+            corners.pop();
+        }
+        Polygon { corners }
+    }
+
+    pub fn bounding_box(min_longitude: f64, min_latitude: f64, max_longitude: f64, max_latitude: f64) -> Self {
+        Polygon::new(vec![
+            [min_longitude, min_latitude],
+            [max_longitude, min_latitude],
+            [max_longitude, max_latitude],
+            [min_longitude, max_latitude],
+        ])
+    }
+
+    pub fn corners(&self) -> &[[f64; 2]] {
+        &self.corners
+    }
+
+    /// Whether the point is inside, by counting how often a ray from it crosses the edges.
+    pub fn contains(&self, longitude: f64, latitude: f64) -> bool {
+        if self.corners.len() < 3 {
+            return false;
+        }
+        let mut inside = false;
+        let mut previous = self.corners[self.corners.len() - 1];
+        for &corner in &self.corners {
+            let ([x1, y1], [x2, y2]) = (previous, corner);
+            if (y1 > latitude) != (y2 > latitude) && longitude < (x2 - x1) * (latitude - y1) / (y2 - y1) + x1 {
+                inside = !inside;
+            }
+            previous = corner;
+        }
+        inside
+    }
+}
+
+/// Parses corners separated by spaces, each as `longitude,latitude`, such as
+/// `15,68 32,68 32,72 15,72`.
+impl FromStr for Polygon {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let corners = value
+            .split_whitespace()
+            .map(|corner| {
+                let (longitude, latitude) = corner
+                    .split_once(',')
+                    .ok_or_else(|| format!("expected longitude,latitude, got \"{}\"", corner))?;
+                let coordinate = |value: &str| {
+                    value
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid coordinate in \"{}\"", corner))
+                };
+                Ok([coordinate(longitude)?, coordinate(latitude)?])
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let polygon = Polygon::new(corners);
+        if polygon.corners.len() < 3 {
+            return Err("an area needs at least three corners".to_owned());
+        }
+        Ok(polygon)
+    }
+}
+
+// Only the outer ring is used, holes are ignored.
+#[derive(Serialize, Deserialize)]
+struct GeoJsonPolygon {
+    #[serde(rename = "type")]
+    kind: String,
+    coordinates: Vec<Vec<[f64; 2]>>,
+}
+
+impl TryFrom<GeoJsonPolygon> for Polygon {
+    type Error = String;
+
+    fn try_from(geometry: GeoJsonPolygon) -> Result<Self, Self::Error> {
+        if geometry.kind != "Polygon" {
+            return Err(format!("expected a Polygon geometry, got {}", geometry.kind));
+        }
+        let ring = geometry.coordinates.into_iter().next().unwrap_or_default();
+        Ok(Polygon::new(ring))
+    }
+}
+
+impl From<Polygon> for GeoJsonPolygon {
+    fn from(polygon: Polygon) -> Self {
+        let mut ring = polygon.corners;
+        if let Some(&first) = ring.first() {
+            ring.push(first);
+        }
+        GeoJsonPolygon {
+            kind: "Polygon".to_owned(),
+            coordinates: vec![ring],
+        }
+    }
+}
+
+/// The body of `/v1/latest/combined`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterInput {
+    pub since: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mmsi: Vec<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ship_types: Vec<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub country_code: Vec<String>,
+    #[serde(default = "included")]
+    pub include_position: bool,
+    #[serde(default = "included")]
+    pub include_static: bool,
+    #[serde(default = "included")]
+    pub include_aton: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Polygon>,
+    #[serde(default = "full_model")]
+    pub model_type: String,
+    #[serde(default, with = "api_model_format")]
+    pub model_format: ModelFormat,
+}
+
+fn included() -> bool {
+    true
+}
+
+fn full_model() -> String {
+    "Full".to_owned()
+}
+
+/// Which of the latest messages to fetch.
+///
+/// Every filter that is set must match. Positions carry no ship type and static data no position,
+/// so a position matches the ship types when a static message of the same vessel in the response
+/// does, and static data matches the area when a position of the same vessel does. Messages that
+/// did not parse are only filtered by MMSI and country, so they still end up as rejected messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AisQuery {
+    since: DateTime<Utc>,
+    mmsi: Vec<i64>,
+    ship_types: Vec<i64>,
+    country_codes: Vec<String>,
+    message_types: Vec<MessageKind>,
+    area: Option<Polygon>,
+    model_format: ModelFormat,
+}
+
+impl AisQuery {
+    /// Every message newer than `since`.
+    pub fn new(since: DateTime<Utc>) -> Self {
+        AisQuery {
+            since,
+            mmsi: Vec::new(),
+            ship_types: Vec::new(),
+            country_codes: Vec::new(),
+            message_types: Vec::new(),
+            area: None,
+            model_format: ModelFormat::Json,
+        }
+    }
+
+    /// The messages newer than `since` that match the configured filters.
+    pub fn from_settings(since: DateTime<Utc>, settings: &FilterSettings) -> Self {
+        let query = AisQuery::new(since)
+            .with_mmsi(settings.mmsi.iter().copied())
+            .with_ship_types(settings.ship_types.iter().copied())
+            .with_country_codes(settings.country_codes.iter().cloned())
+            .with_message_types(settings.message_types.iter().copied())
+            .with_model_format(settings.model_format);
+        match &settings.area {
+            Some(corners) => query.with_area(Polygon::new(corners.clone())),
+            None => query,
+        }
+    }
+
+    pub fn with_mmsi(mut self, mmsi: impl IntoIterator<Item = i64>) -> Self {
+        self.mmsi.extend(mmsi);
+        self
+    }
+
+    /// AIS ship type codes, such as 30 for fishing.
+    pub fn with_ship_types(mut self, ship_types: impl IntoIterator<Item = i64>) -> Self {
+        self.ship_types.extend(ship_types);
+        self
+    }
+
+    /// ISO 3166-1 alpha-3 codes of the flag, told from the MID of the MMSI.
+    pub fn with_country_codes(mut self, country_codes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.country_codes
+            .extend(country_codes.into_iter().map(|code| code.into().to_ascii_uppercase()));
+        self
+    }
+
+    pub fn with_message_types(mut self, message_types: impl IntoIterator<Item = MessageKind>) -> Self {
+        self.message_types.extend(message_types);
+        self
+    }
+
+    pub fn with_area(mut self, area: Polygon) -> Self {
+        self.area = Some(area);
+        self
+    }
+
+    pub fn with_model_format(mut self, model_format: ModelFormat) -> Self {
+        self.model_format = model_format;
+        self
+    }
+
+    pub fn since(&self) -> DateTime<Utc> {
+        self.since
+    }
+
+    pub fn model_format(&self) -> ModelFormat {
+        self.model_format
+    }
+
+    /// Whether anything is filtered besides `since`.
+    pub fn has_filters(&self) -> bool {
+        !self.mmsi.is_empty()
+            || !self.ship_types.is_empty()
+            || !self.country_codes.is_empty()
+            || !self.message_types.is_empty()
+            || self.area.is_some()
+    }
+
+    /// The query as the body of `/v1/latest/combined`.
+    pub fn filter_input(&self) -> FilterInput {
+        let includes = |kind| self.message_types.is_empty() || self.message_types.contains(&kind);
+        FilterInput {
+            since: self.since,
+            mmsi: self.mmsi.clone(),
+            ship_types: self.ship_types.clone(),
+            country_code: self.country_codes.clone(),
+            include_position: includes(MessageKind::Position),
+            include_static: includes(MessageKind::Static),
+            include_aton: includes(MessageKind::Aton),
+            geometry: self.area.clone(),
+            model_type: full_model(),
+            model_format: self.model_format,
+        }
+    }
+
+    /// Keeps the messages that match, for when the server did not filter them.
+    pub fn apply(&self, messages: Vec<AisMessage>) -> Vec<AisMessage> {
+        if !self.has_filters() {
+            return messages;
+        }
+
+        let positioned_in_area: HashSet<i64> = match &self.area {
+            Some(area) => messages
+                .iter()
+                .filter_map(|message| match message {
+                    AisMessage::Position(data) if contains(area, data.longitude, data.latitude) => data.mmsi,
+                    _ => None,
+                })
+                .collect(),
+            None => HashSet::new(),
+        };
+        let of_ship_types: HashSet<i64> = messages
+            .iter()
+            .filter_map(|message| match message {
+                AisMessage::StaticData(data) if self.is_ship_type(data.ship_type.map(i64::from)) => data.mmsi,
+                _ => None,
+            })
+            .collect();
+
+        messages
+            .into_iter()
+            .filter(|message| self.matches(message, &positioned_in_area, &of_ship_types))
+            .collect()
+    }
+
+    fn matches(&self, message: &AisMessage, positioned_in_area: &HashSet<i64>, of_ship_types: &HashSet<i64>) -> bool {
+        let mmsi = message.mmsi();
+        if !self.mmsi.is_empty() && !mmsi.is_some_and(|mmsi| self.mmsi.contains(&mmsi)) {
+            return false;
+        }
+        if !self.country_codes.is_empty()
+            && !mmsi
+                .and_then(country_code)
+                .is_some_and(|country| self.country_codes.iter().any(|code| code == country))
+        {
+            return false;
+        }
+
+        let Some(kind) = MessageKind::of(message) else {
+            return true;
+        };
+        if !self.message_types.is_empty() && !self.message_types.contains(&kind) {
+            return false;
+        }
+        let of_vessels = |vessels: &HashSet<i64>| mmsi.is_some_and(|mmsi| vessels.contains(&mmsi));
+        if let Some(area) = &self.area {
+            let inside = match message {
+                AisMessage::Position(data) => contains(area, data.longitude, data.latitude),
+                AisMessage::Aton(data) => contains(area, data.longitude, data.latitude),
+                _ => of_vessels(positioned_in_area),
+            };
+            if !inside {
+                return false;
+            }
+        }
+        if !self.ship_types.is_empty() {
+            return match message {
+                AisMessage::StaticData(data) => self.is_ship_type(data.ship_type.map(i64::from)),
+                AisMessage::Position(_) => of_vessels(of_ship_types),
+                // Aids to navigation are not ships.
+                _ => false,
+            };
+        }
+        true
+    }
+
+    fn is_ship_type(&self, ship_type: Option<i64>) -> bool {
+        ship_type.is_some_and(|ship_type| self.ship_types.contains(&ship_type))
+    }
+}
+
+impl From<FilterInput> for AisQuery {
+    fn from(input: FilterInput) -> Self {
+        let message_types = [
+            (MessageKind::Position, input.include_position),
+            (MessageKind::Static, input.include_static),
+            (MessageKind::Aton, input.include_aton),
+        ];
+        let mut query = AisQuery::new(input.since)
+            .with_mmsi(input.mmsi)
+            .with_ship_types(input.ship_types)
+            .with_country_codes(input.country_code)
+            .with_model_format(input.model_format);
+        if message_types.iter().any(|(_, included)| !included) {
+            query = query.with_message_types(
+                message_types
+                    .into_iter()
+                    .filter(|(_, included)| *included)
+                    .map(|(kind, _)| kind),
+            );
+        }
+        if let Some(area) = input.geometry {
+            query = query.with_area(area);
+        }
+        query
+    }
+}
+
+fn contains(area: &Polygon, longitude: Option<f64>, latitude: Option<f64>) -> bool {
+    match (longitude, latitude) {
+        (Some(longitude), Some(latitude)) => area.contains(longitude, latitude),
+        _ => false,
+    }
+}
+
+/// Reads the messages of a GeoJSON feature collection, taking the position from the geometry
+/// when the properties do not have it.
+pub fn messages_from_geojson(collection: Value) -> Result<Vec<AisMessage>, serde_json::Error> {
+    let Value::Object(mut collection) = collection else {
+        return Err(serde_json::Error::custom("expected a GeoJSON FeatureCollection"));
+    };
+    let Some(Value::Array(features)) = collection.remove("features") else {
+        return Err(serde_json::Error::custom("a FeatureCollection needs features"));
+    };
+
+    features
+        .into_iter()
+        .map(|feature| {
+            let mut properties = match feature.get("properties") {
+                Some(Value::Object(properties)) => properties.clone(),
+                _ => Map::new(),
+            };
+            let point = feature
+                .get("geometry")
+                .filter(|geometry| geometry.get("type").and_then(Value::as_str) == Some("Point"))
+                .and_then(|geometry| geometry.get("coordinates"))
+                .and_then(Value::as_array);
+            if let Some([longitude, latitude, ..]) = point.map(Vec::as_slice) {
+                properties.entry("longitude").or_insert_with(|| longitude.clone());
+                properties.entry("latitude").or_insert_with(|| latitude.clone());
+            }
+            serde_json::from_value(Value::Object(properties))
+        })
+        .collect()
+}
+
+/// Writes messages as a GeoJSON feature collection, the inverse of [`messages_from_geojson`].
+pub fn messages_to_geojson(messages: &[Value]) -> Value {
+    let features: Vec<Value> = messages
+        .iter()
+        .map(|message| {
+            let longitude = message.get("longitude").and_then(Value::as_f64);
+            let latitude = message.get("latitude").and_then(Value::as_f64);
+            let geometry = match (longitude, latitude) {
+                (Some(longitude), Some(latitude)) => {
+                    serde_json::json!({ "type": "Point", "coordinates": [longitude, latitude] })
+                }
+                _ => Value::Null,
+            };
+            serde_json::json!({ "type": "Feature", "geometry": geometry, "properties": message })
+        })
+        .collect();
+    serde_json::json!({ "type": "FeatureCollection", "features": features })
+}
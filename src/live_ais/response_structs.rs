@@ -1,5 +1,6 @@
-use serde::Deserialize;
-use serde::Serialize;
+use crate::live_ais::ais_types::{deserialize_eta, AtonType, EpfdType, NavigationalStatus, ReceivedEta, ShipType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -20,21 +21,22 @@ pub struct AISStaticData {
     pub type_field: Option<String>,
     pub message_type: Option<i64>,
     pub mmsi: Option<i64>,
-    pub msgtime: Option<String>,
+    pub msgtime: Option<DateTime<Utc>>,
     pub imo_number: Option<i64>,
     pub call_sign: Option<String>,
     pub destination: Option<String>,
-    pub eta: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_eta")]
+    pub eta: Option<ReceivedEta>,
     pub name: Option<String>,
     pub draught: Option<i32>,
     pub ship_length: Option<i32>,
     pub ship_width: Option<i32>,
-    pub ship_type: Option<i32>,
+    pub ship_type: Option<ShipType>,
     pub dimension_a: Option<i32>,
     pub dimension_b: Option<i32>,
     pub dimension_c: Option<i32>,
     pub dimension_d: Option<i32>,
-    pub position_fixing_device_type: Option<i64>,
+    pub position_fixing_device_type: Option<EpfdType>,
     pub report_class: Option<String>,
 }
 
@@ -45,16 +47,16 @@ pub struct AISAtonData {
     pub type_field: Option<String>,
     pub message_type: Option<i64>,
     pub mmsi: Option<i64>,
-    pub msgtime: Option<String>,
+    pub msgtime: Option<DateTime<Utc>>,
     pub dimension_a: Option<i32>,
     pub dimension_b: Option<i32>,
     pub dimension_c: Option<i32>,
     pub dimension_d: Option<i32>,
-    pub type_of_aids_to_navigation: Option<i64>,
+    pub type_of_aids_to_navigation: Option<AtonType>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     pub name: Option<String>,
-    pub type_of_electronic_fixing_device: Option<i64>,
+    pub type_of_electronic_fixing_device: Option<EpfdType>,
 }
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -67,128 +69,172 @@ pub struct AISPositionData {
     pub altitude: Option<f64>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
-    pub navigational_status: Option<i64>,
+    pub navigational_status: Option<NavigationalStatus>,
     pub rate_of_turn: Option<i64>,
     pub speed_over_ground: Option<f64>,
     pub true_heading: Option<i64>,
     pub mmsi: Option<i64>,
-    pub msgtime: Option<String>,
+    pub msgtime: Option<DateTime<Utc>>,
 }
 
-pub type AISLatestResponses = Vec<GetAISLatestResponseItem>;
-#[derive(Default)]
-pub struct GetAISLatestResponse {
-    pub api_endpoint: String,
-    pub status_code: u16,
-    pub content_length: Option<usize>,
-    pub ais_latest_responses: Option<AISLatestResponses>,
+/// A message from the API, told apart by its `type` field like serde's internally tagged enums, but
+/// matched case-insensitively and leaving the tag on the inner struct so it is stored with the
+/// message. Messages of a type this crate does not know are kept as the raw JSON, and so are
+/// messages of a known type that do not parse, so that one bad message does not fail the response.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum AisMessage {
+    Position(AISPositionData),
+    StaticData(AISStaticData),
+    Aton(AISAtonData),
+    Unknown(serde_json::Value),
+    Invalid(InvalidMessage),
 }
 
-//noinspection ALL
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct GetAISLatestResponseItem {
-    #[serde(rename = "type")]
-    pub type_field: Option<String>,
-    pub message_type: Option<i64>,
-    pub mmsi: Option<i64>,
-    pub msgtime: Option<String>,
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvalidMessage {
+    pub payload: serde_json::Value,
+    pub error: String,
+}
 
-    // Fields from AISStaticData
-    pub imo_number: Option<i64>,
-    pub call_sign: Option<String>,
-    pub destination: Option<String>,
-    pub eta: Option<String>,
-    pub name: Option<String>,
-    pub draught: Option<i32>,
-    pub ship_length: Option<i32>,
-    pub ship_width: Option<i32>,
-    pub ship_type: Option<i32>,
-    pub dimension_a: Option<i32>,
-    pub dimension_b: Option<i32>,
-    pub dimension_c: Option<i32>,
-    pub dimension_d: Option<i32>,
-    pub position_fixing_device_type: Option<i64>,
-    pub report_class: Option<String>,
+impl AisMessage {
+    pub fn mmsi(&self) -> Option<i64> {
+        match self {
+            AisMessage::Position(data) => data.mmsi,
+            AisMessage::StaticData(data) => data.mmsi,
+            AisMessage::Aton(data) => data.mmsi,
+            AisMessage::Unknown(value) | AisMessage::Invalid(InvalidMessage { payload: value, .. }) => {
+                value.get("mmsi").and_then(serde_json::Value::as_i64)
+            }
+        }
+    }
 
-    // Fields from AISAtonData
-    pub type_of_aids_to_navigation: Option<i64>,
-    pub latitude: Option<f64>,
-    pub longitude: Option<f64>,
-    pub type_of_electronic_fixing_device: Option<i64>,
+    pub fn msgtime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            AisMessage::Position(data) => data.msgtime,
+            AisMessage::StaticData(data) => data.msgtime,
+            AisMessage::Aton(data) => data.msgtime,
+            AisMessage::Unknown(value) | AisMessage::Invalid(InvalidMessage { payload: value, .. }) => value
+                .get("msgtime")
+                .and_then(serde_json::Value::as_str)
+                .and_then(|msgtime| DateTime::parse_from_rfc3339(msgtime).ok())
+                .map(|msgtime| msgtime.with_timezone(&Utc)),
+        }
+    }
+}
 
-    // Fields from AISPositionData
-    pub course_over_ground: Option<f64>,
-    pub ais_class: Option<String>,
-    pub altitude: Option<f64>,
-    pub navigational_status: Option<i64>,
-    pub rate_of_turn: Option<i64>,
-    pub speed_over_ground: Option<f64>,
-    pub true_heading: Option<i64>,
+impl<'de> Deserialize<'de> for AisMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let message_type = value
+            .get("type")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_ascii_lowercase);
+
+        // Parsed from a clone so that the payload can be kept when it does not parse.
+        let parsed = match message_type.as_deref() {
+            Some("position") => serde_json::from_value(value.clone()).map(AisMessage::Position),
+            Some("staticdata") => serde_json::from_value(value.clone()).map(AisMessage::StaticData),
+            Some("aton") => serde_json::from_value(value.clone()).map(AisMessage::Aton),
+            _ => return Ok(AisMessage::Unknown(value)),
+        };
+        Ok(parsed.unwrap_or_else(|error| {
+            AisMessage::Invalid(InvalidMessage {
+                payload: value,
+                error: error.to_string(),
+            })
+        }))
+    }
+}
+
+/// The step of the ingestion a message was rejected at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RejectionStage {
+    /// The message has no or an unknown `type`, or does not parse as its type.
+    Parse,
+    /// The message has impossible values and validation runs in `reject` mode.
+    Validation,
+    /// The database refused the row.
+    Insert,
 }
 
-impl From<&GetAISLatestResponseItem> for AISPositionData {
-    fn from(item: &GetAISLatestResponseItem) -> Self {
-        AISPositionData {
-            type_field: item.type_field.clone(),
-            message_type: item.message_type,
-            course_over_ground: item.course_over_ground,
-            ais_class: item.ais_class.clone(),
-            altitude: item.altitude,
-            latitude: item.latitude,
-            longitude: item.longitude,
-            navigational_status: item.navigational_status,
-            rate_of_turn: item.rate_of_turn,
-            speed_over_ground: item.speed_over_ground,
-            true_heading: item.true_heading,
-            mmsi: item.mmsi,
-            msgtime: item.msgtime.clone(),
+impl RejectionStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectionStage::Parse => "parse",
+            RejectionStage::Validation => "validation",
+            RejectionStage::Insert => "insert",
         }
     }
 }
 
-impl From<&GetAISLatestResponseItem> for AISStaticData {
-    fn from(item: &GetAISLatestResponseItem) -> Self {
-        AISStaticData {
-            type_field: item.type_field.clone(),
-            message_type: item.message_type,
-            mmsi: item.mmsi,
-            msgtime: item.msgtime.clone(),
-            imo_number: item.imo_number,
-            call_sign: item.call_sign.clone(),
-            destination: item.destination.clone(),
-            eta: item.eta.clone(),
-            name: item.name.clone(),
-            draught: item.draught,
-            ship_length: item.ship_length,
-            ship_width: item.ship_width,
-            ship_type: item.ship_type,
-            dimension_a: item.dimension_a,
-            dimension_b: item.dimension_b,
-            dimension_c: item.dimension_c,
-            dimension_d: item.dimension_d,
-            position_fixing_device_type: item.position_fixing_device_type,
-            report_class: item.report_class.clone(),
+/// A message left out of the ingestion, kept with the reason so it can be inspected and re-processed
+/// once the cause is fixed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedMessage {
+    pub stage: RejectionStage,
+    pub reason: String,
+    pub payload: serde_json::Value,
+}
+
+impl RejectedMessage {
+    pub fn new(stage: RejectionStage, reason: impl Into<String>, message: &impl Serialize) -> Self {
+        RejectedMessage {
+            stage,
+            reason: reason.into(),
+            payload: serde_json::to_value(message).unwrap_or_default(),
         }
     }
 }
 
-impl From<&GetAISLatestResponseItem> for AISAtonData {
-    fn from(item: &GetAISLatestResponseItem) -> Self {
-        AISAtonData {
-            type_field: item.type_field.clone(),
-            message_type: item.message_type,
-            mmsi: item.mmsi,
-            msgtime: item.msgtime.clone(),
-            dimension_a: item.dimension_a,
-            dimension_b: item.dimension_b,
-            dimension_c: item.dimension_c,
-            dimension_d: item.dimension_d,
-            type_of_aids_to_navigation: item.type_of_aids_to_navigation,
-            latitude: item.latitude,
-            longitude: item.longitude,
-            name: item.name.clone(),
-            type_of_electronic_fixing_device: item.type_of_electronic_fixing_device,
+/// Messages sorted by kind, the shape they are written to the database in.
+#[derive(Default)]
+pub struct SplitAISMessages {
+    pub static_data: Vec<AISStaticData>,
+    pub aton_data: Vec<AISAtonData>,
+    pub position_data: Vec<AISPositionData>,
+    /// How many of the messages were of an unknown type, these are also in `rejected`.
+    pub unknown: usize,
+    pub rejected: Vec<RejectedMessage>,
+}
+
+impl FromIterator<AisMessage> for SplitAISMessages {
+    fn from_iter<I: IntoIterator<Item = AisMessage>>(messages: I) -> Self {
+        let mut split = SplitAISMessages::default();
+        for message in messages {
+            match message {
+                AisMessage::Position(data) => split.position_data.push(data),
+                AisMessage::StaticData(data) => split.static_data.push(data),
+                AisMessage::Aton(data) => split.aton_data.push(data),
+                AisMessage::Unknown(value) => {
+                    let reason = match value.get("type") {
+                        Some(message_type) => format!("unknown message type {}", message_type),
+                        None => "missing message type".to_owned(),
+                    };
+                    split.unknown += 1;
+                    split.rejected.push(RejectedMessage {
+                        stage: RejectionStage::Parse,
+                        reason,
+                        payload: value,
+                    });
+                }
+                AisMessage::Invalid(invalid) => split.rejected.push(RejectedMessage {
+                    stage: RejectionStage::Parse,
+                    reason: invalid.error,
+                    payload: invalid.payload,
+                }),
+            }
         }
+        split
     }
 }
+
+pub type AISLatestResponses = Vec<AisMessage>;
+#[derive(Default)]
+pub struct GetAISLatestResponse {
+    pub api_endpoint: String,
+    pub status_code: u16,
+    pub content_length: Option<usize>,
+    pub ais_latest_responses: Option<AISLatestResponses>,
+}
@@ -0,0 +1,351 @@
+//! Retries of the requests to the BarentsWatch API.
+//!
+//! Network errors, timeouts, 5xx and 429 responses are retried with exponential backoff and
+//! jitter, or after the `Retry-After` the server asked for. Every failed attempt is kept in
+//! [`FailedRequests`] so the ingestion can record it in log.requests. After repeated failures the
+//! [`CircuitBreaker`] stops sending requests for a while, so an API that is down is not hammered
+//! by every poll.
+
+use crate::database::configuration::RetrySettings;
+use crate::live_ais::ais_stream::ResponseErrorMessages;
+use chrono::{DateTime, Utc};
+use log::warn;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A request attempt that did not succeed.
+#[derive(Debug, Clone)]
+pub struct FailedRequest {
+    pub endpoint: String,
+    /// `None` when no response was received.
+    pub status_code: Option<i32>,
+    pub error: String,
+}
+
+/// The failed attempts of a client, shared so they can be collected while a stream of the client
+/// is being read.
+#[derive(Clone, Default)]
+pub struct FailedRequests(Arc<Mutex<Vec<FailedRequest>>>);
+
+impl FailedRequests {
+    /// Returns the attempts that failed since the last call.
+    pub fn take(&self) -> Vec<FailedRequest> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    pub(crate) fn push(&self, endpoint: &str, status_code: Option<StatusCode>, error: &ResponseErrorMessages) {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(FailedRequest {
+                endpoint: endpoint.to_owned(),
+                status_code: status_code.map(|status_code| i32::from(status_code.as_u16())),
+                error: error.to_string(),
+            });
+    }
+}
+
+/// How an attempt ended.
+pub(crate) enum Attempt {
+    Success(Response),
+    /// Worth another try, after `retry_after` when the server said so.
+    Transient {
+        error: ResponseErrorMessages,
+        status_code: Option<StatusCode>,
+        retry_after: Option<Duration>,
+    },
+    /// The token was refused, a new one may help.
+    Unauthorized(ResponseErrorMessages),
+    Fatal {
+        error: ResponseErrorMessages,
+        status_code: StatusCode,
+    },
+}
+
+pub(crate) fn classify(result: Result<Response, reqwest::Error>) -> Attempt {
+    let response = match result {
+        Ok(response) => response,
+        Err(error) => {
+            return Attempt::Transient {
+                status_code: error.status(),
+                error: ResponseErrorMessages::NetworkError(error),
+                retry_after: None,
+            }
+        }
+    };
+
+    let status_code = response.status();
+    let error = ResponseErrorMessages::UnexpectedStatusCode(status_code);
+    match status_code {
+        status_code if status_code.is_success() => Attempt::Success(response),
+        StatusCode::UNAUTHORIZED => Attempt::Unauthorized(error),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::REQUEST_TIMEOUT => Attempt::Transient {
+            retry_after: retry_after(&response),
+            status_code: Some(status_code),
+            error,
+        },
+        status_code if status_code.is_server_error() => Attempt::Transient {
+            retry_after: retry_after(&response),
+            status_code: Some(status_code),
+            error,
+        },
+        status_code => Attempt::Fatal { error, status_code },
+    }
+}
+
+// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// The delays between the attempts of one request.
+pub(crate) struct Backoff {
+    attempt: u32,
+    max_attempts: u32,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(settings: &RetrySettings) -> Self {
+        Backoff {
+            attempt: 1,
+            max_attempts: settings.max_attempts.max(1),
+            initial: Duration::from_millis(settings.initial_backoff_ms),
+            max: Duration::from_secs(settings.max_backoff_seconds),
+        }
+    }
+
+    /// The delay before the next attempt, `None` once all attempts were used.
+    ///
+    /// The exponential delay is jittered between half and all of it, so that clients that failed
+    /// together do not retry together. A `Retry-After` from the server is used instead, up to the
+    /// maximum backoff.
+    pub(crate) fn next_delay(&mut self, retry_after: Option<Duration>) -> Option<Duration> {
+        if self.attempt >= self.max_attempts {
+            return None;
+        }
+        let exponential = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.attempt - 1))
+            .min(self.max);
+        self.attempt += 1;
+
+        Some(retry_after.map(|retry_after| retry_after.min(self.max)).unwrap_or_else(|| {
+            let half = exponential / 2;
+            half + half.mul_f64(rand::thread_rng().gen::<f64>())
+        }))
+    }
+
+    pub(crate) fn attempt(&self) -> u32 {
+        self.attempt
+    }
+}
+
+/// Pauses requests after `threshold` of them failed in a row.
+///
+/// Once the cooldown has passed one request is let through; when it fails too the breaker opens
+/// again straight away, when it succeeds the count starts over.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    open_until: Option<DateTime<Utc>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: &RetrySettings) -> Self {
+        CircuitBreaker {
+            threshold: settings.circuit_breaker_threshold.max(1),
+            cooldown: Duration::from_secs(settings.circuit_breaker_cooldown_seconds),
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// Fails with [`ResponseErrorMessages::CircuitOpen`] while requests are paused.
+    pub fn check(&self) -> Result<(), ResponseErrorMessages> {
+        match self.open_until {
+            Some(until) if until > Utc::now() => Err(ResponseErrorMessages::CircuitOpen { until }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= self.threshold {
+            let until = Utc::now() + chrono::Duration::from_std(self.cooldown).unwrap_or_else(|_| chrono::Duration::zero());
+            warn!(
+                "{} requests to the API failed in a row, pausing requests until {}",
+                self.consecutive_failures, until
+            );
+            self.open_until = Some(until);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn settings() -> RetrySettings {
+        RetrySettings {
+            max_attempts: 6,
+            initial_backoff_ms: 1000,
+            max_backoff_seconds: 5,
+            circuit_breaker_threshold: 2,
+            circuit_breaker_cooldown_seconds: 600,
+            ..RetrySettings::default()
+        }
+    }
+
+    fn response(status_code: u16, retry_after: Option<&'static str>) -> Response {
+        let mut response = hyper::Response::builder().status(status_code);
+        if let Some(retry_after) = retry_after {
+            response = response.header(RETRY_AFTER, HeaderValue::from_static(retry_after));
+        }
+        Response::from(response.body("").unwrap())
+    }
+
+    #[test]
+    fn doubles_the_delay_up_to_the_maximum() {
+        let mut backoff = Backoff::new(&settings());
+
+        for expected in [1, 2, 4, 5, 5] {
+            let maximum = Duration::from_secs(expected);
+            let delay = backoff.next_delay(None).unwrap();
+            assert!(maximum / 2 <= delay && delay <= maximum, "{:?} not within {:?}", delay, maximum);
+        }
+        assert_eq!(backoff.attempt(), 6);
+        assert_eq!(backoff.next_delay(None), None);
+    }
+
+    #[test]
+    fn jitters_between_half_and_all_of_the_delay() {
+        let delays: Vec<_> = (0..100)
+            .map(|_| Backoff::new(&settings()).next_delay(None).unwrap())
+            .collect();
+
+        assert!(delays
+            .iter()
+            .all(|delay| (Duration::from_millis(500)..=Duration::from_secs(1)).contains(delay)));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[test]
+    fn waits_as_long_as_the_server_asks_up_to_the_maximum() {
+        let mut backoff = Backoff::new(&settings());
+
+        assert_eq!(backoff.next_delay(Some(Duration::from_secs(3))), Some(Duration::from_secs(3)));
+        assert_eq!(backoff.next_delay(Some(Duration::from_secs(3600))), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn makes_at_least_one_attempt() {
+        let mut backoff = Backoff::new(&RetrySettings { max_attempts: 0, ..settings() });
+        assert_eq!(backoff.next_delay(None), None);
+    }
+
+    #[test]
+    fn classifies_responses() {
+        assert!(matches!(classify(Ok(response(200, None))), Attempt::Success(_)));
+        assert!(matches!(classify(Ok(response(401, None))), Attempt::Unauthorized(_)));
+        assert!(matches!(
+            classify(Ok(response(404, None))),
+            Attempt::Fatal { status_code: StatusCode::NOT_FOUND, .. }
+        ));
+        assert!(matches!(
+            classify(Ok(response(503, None))),
+            Attempt::Transient { status_code: Some(StatusCode::SERVICE_UNAVAILABLE), retry_after: None, .. }
+        ));
+        assert!(matches!(
+            classify(Ok(response(408, None))),
+            Attempt::Transient { status_code: Some(StatusCode::REQUEST_TIMEOUT), .. }
+        ));
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds_and_as_a_date() {
+        let Attempt::Transient { retry_after, .. } = classify(Ok(response(429, Some("7")))) else {
+            panic!("429 is transient");
+        };
+        assert_eq!(retry_after, Some(Duration::from_secs(7)));
+
+        let Attempt::Transient { retry_after, .. } = classify(Ok(response(503, Some("Wed, 21 Oct 2015 07:28:00 GMT"))))
+        else {
+            panic!("503 is transient");
+        };
+        assert_eq!(retry_after, Some(Duration::ZERO));
+
+        let Attempt::Transient { retry_after, .. } = classify(Ok(response(503, Some("soon")))) else {
+            panic!("503 is transient");
+        };
+        assert_eq!(retry_after, None);
+    }
+
+    #[tokio::test]
+    async fn retries_network_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let result = reqwest::get(format!("http://{}", address)).await;
+
+        assert!(matches!(
+            classify(result),
+            Attempt::Transient {
+                error: ResponseErrorMessages::NetworkError(_),
+                status_code: None,
+                retry_after: None,
+            }
+        ));
+    }
+
+    #[test]
+    fn opens_after_the_threshold_of_failures_in_a_row() {
+        let mut breaker = CircuitBreaker::new(&settings());
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure();
+        assert!(matches!(breaker.check(), Err(ResponseErrorMessages::CircuitOpen { .. })));
+    }
+
+    #[test]
+    fn lets_one_request_through_after_the_cooldown() {
+        let mut breaker = CircuitBreaker::new(&settings());
+        breaker.record_failure();
+        breaker.record_failure();
+
+        // The cooldown has passed: half open.
+        breaker.open_until = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert!(breaker.check().is_ok());
+        // The one request fails, the breaker opens again straight away.
+        breaker.record_failure();
+        assert!(breaker.check().is_err());
+
+        breaker.open_until = Some(Utc::now() - chrono::Duration::seconds(1));
+        breaker.record_success();
+        assert!(breaker.check().is_ok());
+        // After a success it takes the whole threshold again.
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+    }
+}
@@ -0,0 +1,318 @@
+//! Access tokens for the BarentsWatch API.
+//!
+//! [`TokenManager`] fetches a token with the client credentials and hands out the cached one until
+//! it is about to expire, going by the `expires_in` of the token response. The manager is shared
+//! by every clone of a client: the token is behind an async lock, so concurrent requests wait for
+//! one fetch instead of each fetching their own. With a cache file the token also outlives the
+//! process, for commands such as `fetch` that run for a few seconds.
+
+use crate::database::configuration::{RetrySettings, TokenSettings};
+use crate::live_ais::ais_stream::ResponseErrorMessages;
+use crate::live_ais::response_structs::TokenResponse;
+use crate::live_ais::retry::{classify, Attempt, Backoff, FailedRequests};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info, warn};
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScopeType {
+    Ais,
+    Api,
+}
+
+impl ScopeType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ScopeType::Ais => "ais",
+            ScopeType::Api => "api",
+        }
+    }
+}
+
+/// The client credentials a token is requested with.
+#[derive(Clone)]
+pub struct Credentials {
+    pub grant_type: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: ScopeType,
+}
+
+/// A token as granted by the identity server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessToken {
+    pub access_token: String,
+    pub token_type: String,
+    /// The scopes that were granted, separated by spaces.
+    pub scope: String,
+    pub fetched_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl AccessToken {
+    fn from_response(response: TokenResponse, fetched_at: DateTime<Utc>) -> Self {
+        AccessToken {
+            access_token: response.access_token,
+            token_type: response.token_type,
+            scope: response.scope,
+            fetched_at,
+            expires_at: fetched_at + Duration::seconds(response.expires_in),
+        }
+    }
+
+    /// Whether the token should be replaced before it is used.
+    ///
+    /// The skew is capped at half the lifetime of the token, otherwise a token that lives shorter
+    /// than the skew would be replaced on every request.
+    pub fn expires_soon(&self, skew: Duration) -> bool {
+        let lifetime = self.expires_at - self.fetched_at;
+        let skew = skew.min(lifetime / 2);
+        Utc::now() + skew >= self.expires_at
+    }
+
+    /// Adds the `Authorization` header for the token type the server granted.
+    pub fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.token_type.eq_ignore_ascii_case("bearer") {
+            request.bearer_auth(&self.access_token)
+        } else {
+            request.header(
+                reqwest::header::AUTHORIZATION,
+                format!("{} {}", self.token_type, self.access_token),
+            )
+        }
+    }
+
+    fn grants(&self, scope: ScopeType) -> bool {
+        self.scope.split_whitespace().any(|granted| granted == scope.as_str())
+    }
+}
+
+// What the cache file holds. The identity server, client and scope are kept so that a file left
+// behind by other credentials is not used.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedToken {
+    token_url: String,
+    client_id: String,
+    scope: ScopeType,
+    token: AccessToken,
+}
+
+#[derive(Default)]
+struct TokenState {
+    token: Option<AccessToken>,
+    cache_loaded: bool,
+}
+
+/// Fetches, caches and refreshes the token of a client.
+pub struct TokenManager {
+    client: Client,
+    token_url: String,
+    credentials: Credentials,
+    settings: TokenSettings,
+    retry: RetrySettings,
+    failed_requests: FailedRequests,
+    state: Mutex<TokenState>,
+}
+
+impl TokenManager {
+    pub fn new(
+        client: Client,
+        token_url: String,
+        credentials: Credentials,
+        settings: TokenSettings,
+        retry: RetrySettings,
+        failed_requests: FailedRequests,
+    ) -> Self {
+        TokenManager {
+            client,
+            token_url,
+            credentials,
+            settings,
+            retry,
+            failed_requests,
+            state: Mutex::new(TokenState::default()),
+        }
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// A token that is valid for at least the refresh skew, fetching one when needed.
+    pub async fn access_token(&self) -> Result<AccessToken, ResponseErrorMessages> {
+        let mut state = self.state.lock().await;
+        if !state.cache_loaded {
+            state.cache_loaded = true;
+            state.token = self.load_cached().await;
+        }
+
+        match &state.token {
+            Some(token) if !token.expires_soon(self.skew()) => {
+                debug!("Token is valid until {}, no need to refresh it.", token.expires_at);
+                Ok(token.clone())
+            }
+            _ => self.fetch_into(&mut state).await,
+        }
+    }
+
+    /// Fetches a new token even when the current one is still valid.
+    pub async fn refresh(&self) -> Result<AccessToken, ResponseErrorMessages> {
+        let mut state = self.state.lock().await;
+        state.cache_loaded = true;
+        self.fetch_into(&mut state).await
+    }
+
+    /// Drops `token` after the API refused it, so the next request fetches a new one.
+    ///
+    /// Nothing happens when another task already replaced it.
+    pub async fn invalidate(&self, token: &AccessToken) {
+        let mut state = self.state.lock().await;
+        if state
+            .token
+            .as_ref()
+            .is_some_and(|current| current.access_token == token.access_token)
+        {
+            state.token = None;
+        }
+    }
+
+    fn skew(&self) -> Duration {
+        Duration::seconds(i64::try_from(self.settings.refresh_skew_seconds).unwrap_or(i64::MAX / 1000))
+    }
+
+    async fn fetch_into(&self, state: &mut TokenState) -> Result<AccessToken, ResponseErrorMessages> {
+        let token = self.fetch().await?;
+        if let Some(path) = &self.settings.cache_path {
+            if let Err(error) = self.store_cached(path, &token).await {
+                warn!("Could not write the token cache {}: {}", path.display(), error);
+            }
+        }
+        state.token = Some(token.clone());
+        Ok(token)
+    }
+
+    async fn fetch(&self) -> Result<AccessToken, ResponseErrorMessages> {
+        let mut form = HashMap::new();
+        form.insert("grant_type", self.credentials.grant_type.as_str());
+        form.insert("client_id", self.credentials.client_id.as_str());
+        form.insert("client_secret", self.credentials.client_secret.as_str());
+        form.insert("scope", self.credentials.scope.as_str());
+
+        let url = Url::parse(&self.token_url).map_err(ResponseErrorMessages::InvalidUrl)?;
+        debug!("fetch_token method - Value of URL: {}", url);
+
+        let mut backoff = Backoff::new(&self.retry);
+        let res = loop {
+            let result = self
+                .client
+                .post(url.clone())
+                .form(&form)
+                .timeout(std::time::Duration::from_secs(self.retry.request_timeout_seconds))
+                .send()
+                .await;
+            match classify(result) {
+                Attempt::Success(res) => break res,
+                Attempt::Transient {
+                    error,
+                    status_code,
+                    retry_after,
+                } => {
+                    self.failed_requests.push(url.as_str(), status_code, &error);
+                    let Some(delay) = backoff.next_delay(retry_after) else {
+                        return Err(error);
+                    };
+                    warn!("Fetching a token failed ({}), attempt {} in {:.1?}", error, backoff.attempt(), delay);
+                    tokio::time::sleep(delay).await;
+                }
+                // The credentials were refused, trying again will not help.
+                Attempt::Unauthorized(error) => {
+                    self.failed_requests.push(url.as_str(), Some(StatusCode::UNAUTHORIZED), &error);
+                    return Err(error);
+                }
+                Attempt::Fatal { error, status_code } => {
+                    self.failed_requests.push(url.as_str(), Some(status_code), &error);
+                    return Err(error);
+                }
+            }
+        };
+
+        let fetched_at = Utc::now();
+        let token_response: TokenResponse = res
+            .json::<TokenResponse>()
+            .await
+            .map_err(ResponseErrorMessages::DeserializationError)?;
+        let token = AccessToken::from_response(token_response, fetched_at);
+        if !token.grants(self.credentials.scope) {
+            warn!(
+                "Requested scope \"{}\" but was granted \"{}\"",
+                self.credentials.scope.as_str(),
+                token.scope
+            );
+        }
+        info!("Successfully fetched token. Expires at {}.", token.expires_at);
+
+        Ok(token)
+    }
+
+    // A missing, unreadable or foreign cache is not an error, a new token is fetched instead.
+    async fn load_cached(&self) -> Option<AccessToken> {
+        let path = self.settings.cache_path.as_ref()?;
+        let contents = match tokio::fs::read(path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(error) => {
+                warn!("Could not read the token cache {}: {}", path.display(), error);
+                return None;
+            }
+        };
+        let cached: CachedToken = match serde_json::from_slice(&contents) {
+            Ok(cached) => cached,
+            Err(error) => {
+                warn!("Ignoring the token cache {}: {}", path.display(), error);
+                return None;
+            }
+        };
+
+        if cached.token_url != self.token_url
+            || cached.client_id != self.credentials.client_id
+            || cached.scope != self.credentials.scope
+        {
+            debug!("The token cache {} belongs to other credentials.", path.display());
+            return None;
+        }
+        debug!("Loaded a token valid until {} from {}", cached.token.expires_at, path.display());
+        Some(cached.token)
+    }
+
+    async fn store_cached(&self, path: &Path, token: &AccessToken) -> std::io::Result<()> {
+        let cached = CachedToken {
+            token_url: self.token_url.clone(),
+            client_id: self.credentials.client_id.clone(),
+            scope: self.credentials.scope,
+            token: token.clone(),
+        };
+        let contents = serde_json::to_vec_pretty(&cached)?;
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The token grants access to the API, keep it from other users. The mode only applies when
+        // the file is created, a file that already exists is restricted before the token is written.
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(path).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600)).await?;
+        }
+        tokio::io::AsyncWriteExt::write_all(&mut file, &contents).await?;
+        Ok(())
+    }
+}
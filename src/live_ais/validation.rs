@@ -0,0 +1,502 @@
+//! Clean-up of the "not available" sentinels and impossible values AIS messages carry.
+//!
+//! AIS encodes missing data as out-of-band values (latitude 91, heading 511, ...). Validating a
+//! message replaces those with `None` and clears values no real report can have, returning a
+//! [`QualityReport`] that lists what was changed so the ingestion can record or reject it.
+//! A message whose MMSI is impossible cannot be attributed to a station and is rejected in every
+//! mode that validates.
+
+use crate::live_ais::ais_types::{ReceivedEta, ShipType};
+use crate::live_ais::response_structs::{
+    AISAtonData, AISPositionData, AISStaticData, RejectedMessage, RejectionStage, SplitAISMessages,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+const MMSI_RANGE: RangeInclusive<i64> = 1..=999_999_999;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationMode {
+    /// Store messages exactly as received.
+    Off,
+    /// Replace sentinels and impossible values with NULL, except an impossible MMSI which
+    /// rejects the message.
+    #[default]
+    Normalize,
+    /// Replace sentinels with NULL and drop messages with impossible values.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// The field held the value AIS uses for "not available".
+    NotAvailable,
+    /// The field held a value outside what the field can physically be.
+    OutOfRange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationIssue {
+    pub field: &'static str,
+    pub value: String,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QualityReport {
+    /// `position`, `staticdata` or `aton`.
+    pub message_kind: &'static str,
+    pub mmsi: Option<i64>,
+    pub msgtime: Option<DateTime<Utc>>,
+    pub issues: Vec<ValidationIssue>,
+    /// Set when the message was left out of the ingestion because of its issues.
+    pub rejected: bool,
+}
+
+impl QualityReport {
+    /// Whether any field held a physically impossible value.
+    pub fn is_invalid(&self) -> bool {
+        self.issues.iter().any(|issue| issue.kind == IssueKind::OutOfRange)
+    }
+
+    pub fn out_of_range(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|issue| issue.kind == IssueKind::OutOfRange)
+    }
+}
+
+pub trait Validate {
+    /// The MMSI as received, before validation.
+    fn mmsi(&self) -> Option<i64>;
+
+    /// Replaces sentinels and impossible values in place with `None` and reports them.
+    fn validate(&mut self) -> QualityReport;
+}
+
+#[derive(Default)]
+struct Checker {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Checker {
+    fn sentinel<T: PartialEq + Display>(&mut self, field: &'static str, value: &mut Option<T>, sentinel: T) {
+        if value.as_ref() == Some(&sentinel) {
+            self.issues.push(ValidationIssue {
+                field,
+                value: sentinel.to_string(),
+                kind: IssueKind::NotAvailable,
+            });
+            *value = None;
+        }
+    }
+
+    fn range<T: PartialOrd + Display>(&mut self, field: &'static str, value: &mut Option<T>, min: T, max: T) {
+        if let Some(current) = value.as_ref() {
+            // `!(a <= b)` so that NaN counts as out of range.
+            if !(min <= *current && *current <= max) {
+                self.issues.push(ValidationIssue {
+                    field,
+                    value: current.to_string(),
+                    kind: IssueKind::OutOfRange,
+                });
+                *value = None;
+            }
+        }
+    }
+
+    // Text fields are padded with `@`, which is also the whole value when it is not available.
+    fn text(&mut self, field: &'static str, value: &mut Option<String>) {
+        if let Some(current) = value.as_mut() {
+            let trimmed = current.trim_end_matches('@').trim();
+            if trimmed.is_empty() {
+                self.issues.push(ValidationIssue {
+                    field,
+                    value: current.clone(),
+                    kind: IssueKind::NotAvailable,
+                });
+                *value = None;
+            } else if trimmed.len() != current.len() {
+                *current = trimmed.to_owned();
+            }
+        }
+    }
+
+    // The ship type is an eight bit code, larger ones can only come from JSON.
+    fn ship_type(&mut self, value: &mut Option<ShipType>) {
+        if let Some(ShipType::Unknown(code)) = *value {
+            if !(0..=255).contains(&code) {
+                self.issues.push(ValidationIssue {
+                    field: "ship_type",
+                    value: code.to_string(),
+                    kind: IssueKind::OutOfRange,
+                });
+                *value = None;
+            }
+        }
+    }
+
+    fn eta(&mut self, value: &mut Option<ReceivedEta>) {
+        if let Some(ReceivedEta::Invalid(input)) = value.as_ref() {
+            self.issues.push(ValidationIssue {
+                field: "eta",
+                value: input.clone(),
+                kind: IssueKind::OutOfRange,
+            });
+            *value = None;
+        }
+    }
+
+    fn mmsi(&mut self, value: &mut Option<i64>) {
+        self.range("mmsi", value, *MMSI_RANGE.start(), *MMSI_RANGE.end());
+    }
+
+    fn position(&mut self, latitude: &mut Option<f64>, longitude: &mut Option<f64>) {
+        self.sentinel("latitude", latitude, 91.0);
+        self.range("latitude", latitude, -90.0, 90.0);
+        self.sentinel("longitude", longitude, 181.0);
+        self.range("longitude", longitude, -180.0, 180.0);
+    }
+
+    fn dimensions(
+        &mut self,
+        dimension_a: &mut Option<i32>,
+        dimension_b: &mut Option<i32>,
+        dimension_c: &mut Option<i32>,
+        dimension_d: &mut Option<i32>,
+    ) {
+        self.sentinel("dimension_a", dimension_a, 0);
+        self.range("dimension_a", dimension_a, 1, 511);
+        self.sentinel("dimension_b", dimension_b, 0);
+        self.range("dimension_b", dimension_b, 1, 511);
+        self.sentinel("dimension_c", dimension_c, 0);
+        self.range("dimension_c", dimension_c, 1, 63);
+        self.sentinel("dimension_d", dimension_d, 0);
+        self.range("dimension_d", dimension_d, 1, 63);
+    }
+
+    fn report(self, message_kind: &'static str, mmsi: Option<i64>, msgtime: Option<DateTime<Utc>>) -> QualityReport {
+        QualityReport {
+            message_kind,
+            mmsi,
+            msgtime,
+            issues: self.issues,
+            rejected: false,
+        }
+    }
+}
+
+impl Validate for AISPositionData {
+    fn mmsi(&self) -> Option<i64> {
+        self.mmsi
+    }
+
+    fn validate(&mut self) -> QualityReport {
+        let mut checker = Checker::default();
+        checker.mmsi(&mut self.mmsi);
+        checker.position(&mut self.latitude, &mut self.longitude);
+        checker.sentinel("speed_over_ground", &mut self.speed_over_ground, 102.3);
+        checker.range("speed_over_ground", &mut self.speed_over_ground, 0.0, 102.2);
+        checker.sentinel("course_over_ground", &mut self.course_over_ground, 360.0);
+        checker.range("course_over_ground", &mut self.course_over_ground, 0.0, 359.9);
+        checker.sentinel("true_heading", &mut self.true_heading, 511);
+        checker.range("true_heading", &mut self.true_heading, 0, 359);
+        checker.sentinel("rate_of_turn", &mut self.rate_of_turn, -128);
+        checker.range("rate_of_turn", &mut self.rate_of_turn, -127, 127);
+        // Only search and rescue aircraft report an altitude.
+        checker.sentinel("altitude", &mut self.altitude, 4095.0);
+        checker.range("altitude", &mut self.altitude, 0.0, 4094.0);
+
+        checker.report("position", self.mmsi, self.msgtime)
+    }
+}
+
+impl Validate for AISStaticData {
+    fn mmsi(&self) -> Option<i64> {
+        self.mmsi
+    }
+
+    fn validate(&mut self) -> QualityReport {
+        let mut checker = Checker::default();
+        checker.mmsi(&mut self.mmsi);
+        checker.sentinel("imo_number", &mut self.imo_number, 0);
+        checker.range("imo_number", &mut self.imo_number, 1_000_000, 9_999_999);
+        checker.text("name", &mut self.name);
+        checker.text("call_sign", &mut self.call_sign);
+        checker.text("destination", &mut self.destination);
+        checker.eta(&mut self.eta);
+        checker.ship_type(&mut self.ship_type);
+        checker.dimensions(
+            &mut self.dimension_a,
+            &mut self.dimension_b,
+            &mut self.dimension_c,
+            &mut self.dimension_d,
+        );
+        checker.sentinel("ship_length", &mut self.ship_length, 0);
+        checker.range("ship_length", &mut self.ship_length, 1, 1022);
+        checker.sentinel("ship_width", &mut self.ship_width, 0);
+        checker.range("ship_width", &mut self.ship_width, 1, 126);
+        checker.sentinel("draught", &mut self.draught, 0);
+        checker.range("draught", &mut self.draught, 1, 255);
+
+        checker.report("staticdata", self.mmsi, self.msgtime)
+    }
+}
+
+impl Validate for AISAtonData {
+    fn mmsi(&self) -> Option<i64> {
+        self.mmsi
+    }
+
+    fn validate(&mut self) -> QualityReport {
+        let mut checker = Checker::default();
+        checker.mmsi(&mut self.mmsi);
+        checker.position(&mut self.latitude, &mut self.longitude);
+        checker.text("name", &mut self.name);
+        checker.dimensions(
+            &mut self.dimension_a,
+            &mut self.dimension_b,
+            &mut self.dimension_c,
+            &mut self.dimension_d,
+        );
+
+        checker.report("aton", self.mmsi, self.msgtime)
+    }
+}
+
+fn validate_all<T: Validate + Clone + Serialize>(
+    messages: &mut Vec<T>,
+    mode: ValidationMode,
+    reports: &mut Vec<QualityReport>,
+    rejected: &mut Vec<RejectedMessage>,
+) {
+    messages.retain_mut(|message| {
+        let unattributable = message.mmsi().is_some_and(|mmsi| !MMSI_RANGE.contains(&mmsi));
+        // Validation clears the offending values, a rejected message is kept as it was received.
+        let original = (mode == ValidationMode::Reject || unattributable).then(|| message.clone());
+        let mut report = message.validate();
+        report.rejected = unattributable || (mode == ValidationMode::Reject && report.is_invalid());
+        let keep = !report.rejected;
+        if let Some(original) = original.filter(|_| report.rejected) {
+            let reason = report
+                .out_of_range()
+                .map(|issue| format!("{}={}", issue.field, issue.value))
+                .collect::<Vec<_>>()
+                .join(", ");
+            rejected.push(RejectedMessage::new(
+                RejectionStage::Validation,
+                format!("out of range: {}", reason),
+                &original,
+            ));
+        }
+        if !report.issues.is_empty() {
+            reports.push(report);
+        }
+        keep
+    });
+}
+
+/// Validates every message of the batch according to `mode`, moving the rejected ones to
+/// `messages.rejected`, and returns the reports of the messages that had any issue.
+pub fn validate_messages(messages: &mut SplitAISMessages, mode: ValidationMode) -> Vec<QualityReport> {
+    let mut reports = Vec::new();
+    if mode == ValidationMode::Off {
+        return reports;
+    }
+
+    validate_all(&mut messages.static_data, mode, &mut reports, &mut messages.rejected);
+    validate_all(&mut messages.aton_data, mode, &mut reports, &mut messages.rejected);
+    validate_all(&mut messages.position_data, mode, &mut reports, &mut messages.rejected);
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn position() -> AISPositionData {
+        AISPositionData {
+            mmsi: Some(257012340),
+            msgtime: Some(Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap()),
+            latitude: Some(69.6489),
+            longitude: Some(18.9551),
+            speed_over_ground: Some(12.5),
+            course_over_ground: Some(45.0),
+            true_heading: Some(44),
+            rate_of_turn: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn static_data() -> AISStaticData {
+        AISStaticData {
+            mmsi: Some(257012340),
+            name: Some("POLARLYS@@@@".to_owned()),
+            draught: Some(65),
+            ..Default::default()
+        }
+    }
+
+    fn issues(report: &QualityReport) -> Vec<(&'static str, &str, IssueKind)> {
+        report
+            .issues
+            .iter()
+            .map(|issue| (issue.field, issue.value.as_str(), issue.kind))
+            .collect()
+    }
+
+    fn batch(position_data: Vec<AISPositionData>) -> SplitAISMessages {
+        SplitAISMessages {
+            position_data,
+            ..SplitAISMessages::default()
+        }
+    }
+
+    #[test]
+    fn clears_the_position_sentinels() {
+        let sentinels = [
+            ("latitude", AISPositionData { latitude: Some(91.0), ..position() }),
+            ("longitude", AISPositionData { longitude: Some(181.0), ..position() }),
+            ("speed_over_ground", AISPositionData { speed_over_ground: Some(102.3), ..position() }),
+            ("course_over_ground", AISPositionData { course_over_ground: Some(360.0), ..position() }),
+            ("true_heading", AISPositionData { true_heading: Some(511), ..position() }),
+            ("rate_of_turn", AISPositionData { rate_of_turn: Some(-128), ..position() }),
+            ("altitude", AISPositionData { altitude: Some(4095.0), ..position() }),
+        ];
+        for (field, mut message) in sentinels {
+            let report = message.validate();
+
+            assert_eq!(report.issues.len(), 1, "{}", field);
+            assert_eq!((report.issues[0].field, report.issues[0].kind), (field, IssueKind::NotAvailable));
+            let mut expected = serde_json::to_value(position()).unwrap();
+            expected[field_name(field)] = serde_json::Value::Null;
+            assert_eq!(serde_json::to_value(&message).unwrap(), expected);
+        }
+    }
+
+    // The JSON names of the position fields.
+    fn field_name(field: &str) -> &str {
+        match field {
+            "speed_over_ground" => "speedOverGround",
+            "course_over_ground" => "courseOverGround",
+            "true_heading" => "trueHeading",
+            "rate_of_turn" => "rateOfTurn",
+            field => field,
+        }
+    }
+
+    #[test]
+    fn keeps_valid_messages_as_they_are() {
+        let mut message = position();
+        assert!(message.validate().issues.is_empty());
+        assert_eq!(message, position());
+    }
+
+    #[test]
+    fn clears_impossible_values() {
+        let mut message = AISPositionData {
+            latitude: Some(95.0),
+            speed_over_ground: Some(f64::NAN),
+            true_heading: Some(400),
+            ..position()
+        };
+
+        let report = message.validate();
+
+        assert!(report.is_invalid());
+        assert_eq!(
+            issues(&report),
+            vec![
+                ("latitude", "95", IssueKind::OutOfRange),
+                ("speed_over_ground", "NaN", IssueKind::OutOfRange),
+                ("true_heading", "400", IssueKind::OutOfRange),
+            ]
+        );
+        assert_eq!((message.latitude, message.speed_over_ground, message.true_heading), (None, None, None));
+    }
+
+    #[test]
+    fn checks_the_static_fields() {
+        let mut message = AISStaticData {
+            imo_number: Some(0),
+            call_sign: Some("@@@@@@@".to_owned()),
+            draught: Some(256),
+            ship_length: Some(0),
+            ..static_data()
+        };
+
+        let report = message.validate();
+
+        assert_eq!(
+            issues(&report),
+            vec![
+                ("imo_number", "0", IssueKind::NotAvailable),
+                ("call_sign", "@@@@@@@", IssueKind::NotAvailable),
+                ("ship_length", "0", IssueKind::NotAvailable),
+                ("draught", "256", IssueKind::OutOfRange),
+            ]
+        );
+        assert_eq!(message.name.as_deref(), Some("POLARLYS"));
+        assert_eq!((message.imo_number, message.call_sign, message.draught), (None, None, None));
+    }
+
+    #[test]
+    fn normalizes_impossible_values_in_normalize_mode() {
+        let mut messages = batch(vec![AISPositionData { longitude: Some(200.0), ..position() }]);
+
+        let reports = validate_messages(&mut messages, ValidationMode::Normalize);
+
+        assert_eq!(messages.position_data.len(), 1);
+        assert_eq!(messages.position_data[0].longitude, None);
+        assert!(messages.rejected.is_empty());
+        assert_eq!(reports.len(), 1);
+        assert!(!reports[0].rejected);
+    }
+
+    #[test]
+    fn rejects_impossible_values_in_reject_mode() {
+        let invalid = AISPositionData { longitude: Some(200.0), ..position() };
+        let not_available = AISPositionData { true_heading: Some(511), ..position() };
+        let mut messages = batch(vec![invalid.clone(), not_available]);
+
+        let reports = validate_messages(&mut messages, ValidationMode::Reject);
+
+        // Sentinels alone do not reject a message.
+        assert_eq!(messages.position_data.len(), 1);
+        assert_eq!(messages.position_data[0].true_heading, None);
+        assert_eq!(messages.rejected.len(), 1);
+        assert_eq!(messages.rejected[0].stage, RejectionStage::Validation);
+        assert_eq!(messages.rejected[0].reason, "out of range: longitude=200");
+        assert_eq!(messages.rejected[0].payload, serde_json::to_value(&invalid).unwrap());
+        assert_eq!(reports.iter().map(|report| report.rejected).collect::<Vec<_>>(), vec![true, false]);
+    }
+
+    #[test]
+    fn rejects_an_impossible_mmsi_in_both_modes() {
+        for mode in [ValidationMode::Normalize, ValidationMode::Reject] {
+            let invalid = AISPositionData { mmsi: Some(1_000_000_000), latitude: Some(91.0), ..position() };
+            let mut messages = batch(vec![invalid.clone(), position()]);
+
+            let reports = validate_messages(&mut messages, mode);
+
+            assert_eq!(messages.position_data, vec![position()], "{:?}", mode);
+            assert_eq!(messages.rejected.len(), 1);
+            assert_eq!(messages.rejected[0].reason, "out of range: mmsi=1000000000");
+            assert_eq!(messages.rejected[0].payload, serde_json::to_value(&invalid).unwrap());
+            assert!(reports[0].rejected);
+        }
+    }
+
+    #[test]
+    fn leaves_messages_alone_when_off() {
+        let invalid = AISPositionData { mmsi: Some(0), latitude: Some(91.0), ..position() };
+        let mut messages = batch(vec![invalid.clone()]);
+
+        assert!(validate_messages(&mut messages, ValidationMode::Off).is_empty());
+        assert_eq!(messages.position_data, vec![invalid]);
+        assert!(messages.rejected.is_empty());
+    }
+}
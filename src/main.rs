@@ -1,173 +1,717 @@
 extern crate dotenv;
 
-use barents::database::configuration;
-use barents::database::postgres::{insert_aton_data, insert_position_data, insert_request_log, insert_static_data};
-use barents::live_ais::response_structs::{
-    AISAtonData, AISLatestResponses, AISPositionData, AISStaticData,
+mod cli;
+
+use barents::database::configuration::{
+    self, FilterSettings, IngestionMode, IngestionSettings, PartitionSettings, Settings, SinkKind,
+};
+use barents::database::postgres::{
+    get_checkpoint, update_checkpoint,
+    IngestionCheckpoint, IngestionReport, InsertMetrics, RequestLog, RequestOutcome, RequestSource,
+};
+use barents::database::latest_position::{get_fleet_picture, get_fleet_position, FleetPosition};
+use barents::database::migrations::{
+    baseline_migrations, ensure_compatible_schema, run_migrations, schema_status,
 };
-use barents::live_ais::{ais_stream::AisLiveAPI, response_structs::GetAISLatestResponse};
+use barents::database::partitions::{get_position_partitions, maintain_position_partitions};
+use barents::database::queries::{
+    export_aton_data, export_position_data, export_static_data, get_database_stats, ExportFilter,
+};
+use barents::database::spatial::{find_atons, find_fleet_positions, find_positions, SpatialFilter};
+use barents::database::rejected_messages::{list_rejected_messages, mark_reprocessed, RejectedMessageFilter};
+use barents::database::vessels::{get_vessel, get_vessel_history, Vessel, VesselChange};
+use barents::live_ais::response_structs::{AISLatestResponses, AisMessage, SplitAISMessages};
+use barents::live_ais::validation::{validate_messages, QualityReport};
+use barents::live_ais::ais_stream::{AisLiveAPI, ResponseErrorMessages};
+use barents::live_ais::mock_server::{parse_json_lines, MockScript, MockServer};
+use barents::live_ais::query::AisQuery;
+use barents::live_ais::retry::FailedRequests;
+use barents::nmea::decoder::NmeaError;
+use barents::nmea::encoder::NmeaEncoder;
+use barents::sinks::json_lines::JsonLinesSink;
+use barents::sinks::postgres::PostgresSink;
+use barents::sinks::AisSink;
+use barents::sources::barentswatch::{fetch_latest, LatestSource, LiveStreamSource};
+use barents::sources::nmea::{NmeaFileSource, NmeaSocketSource};
+use barents::sources::replay::JsonLinesSource;
+use barents::sources::{AisSource, Batching};
 use chrono::Utc;
+use clap::Parser;
+use cli::{
+    AreaFilterArgs, AreaKind, Cli, Command, DaemonArgs, ExportArgs, ExportFormat, ExportKind, FetchArgs, ListenArgs, MigrateArgs,
+    MockServerArgs, PartitionsArgs, RejectedCommand, RejectedFilterArgs, ReplayArgs, ReplayFormat,
+    QueryCommand, StreamArgs,
+};
 use dotenv::dotenv;
-use log::{debug, warn};
-use rayon::iter::ParallelIterator;
-use rayon::prelude::IntoParallelRefIterator;
+use log::{debug, info, warn};
 use sqlx::{PgPool};
+use serde::Serialize;
 use std::convert::TryFrom;
-use std::sync::{Arc, Mutex};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
 use std::{env, error::Error};
-use sqlx::types::Uuid;
-use tokio::task;
+use tokio_stream::StreamExt;
 
-struct LastHourAISMessage {
-    status_code: i32,
-    number_of_items: i64,
-    ais_response: GetAISLatestResponse,
-}
+// Key of the checkpoint row used by the daemon in log.checkpoints.
+const LATEST_AIS_CHECKPOINT: &str = "barentswatch_latest_ais";
+
+// How often long running sources re-run the maintenance of the sink, daily partitions need a new
+// one every day.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(3600);
 
-struct SplitAISMessages {
-    static_data: Vec<AISStaticData>,
-    aton_data: Vec<AISAtonData>,
-    position_data: Vec<AISPositionData>,
+#[derive(Serialize)]
+struct VesselSummary {
+    vessel: Option<Vessel>,
+    position: Option<FleetPosition>,
+    history: Vec<VesselChange>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
     env_logger::init();
+    let cli = Cli::parse();
 
-    debug!("Reading configuration file for Postgres");
-    let config = configuration::get_configuration()?;
+    debug!("Reading configuration file {}", cli.config.display());
+    let mut config = configuration::get_configuration_from(&cli.config)?;
+    let database_url = cli.database_url.as_deref();
 
-    let db_settings = configuration::DatabaseSettings {
-        username: config.database.username.to_string(),
-        password: config.database.password.to_string(),
-        host: config.database.host.to_string(),
-        port: config.database.port,
-        database_name: config.database.database_name.to_string(),
+    let command = match cli.command {
+        Some(command) => command,
+        None => match config.ingestion.mode {
+            IngestionMode::Latest => Command::Fetch(FetchArgs::default()),
+            IngestionMode::Stream => Command::Stream(StreamArgs { batch_size: None, batch_timeout_seconds: None }),
+            IngestionMode::Daemon => Command::Daemon(DaemonArgs { interval_seconds: None, initial_lookback_hours: None }),
+            IngestionMode::Nmea => Command::Listen(ListenArgs {
+                transport: None,
+                address: None,
+                batch_size: None,
+                batch_timeout_seconds: None,
+            }),
+        },
     };
-    let connection_string = db_settings.connection_string();
-    debug!("Connection string: {}", connection_string);
-    let connection_pool = PgPool::connect(&connection_string)
+
+    match command {
+        // The mock API needs neither a database nor credentials.
+        Command::MockServer(args) => run_mock_server(&args).await,
+        // Migrations run before the schema check, which would refuse an outdated schema.
+        Command::Migrate(args) => migrate(&connect(database_url, &config).await, &args).await,
+        Command::Fetch(args) => {
+            let mut source = fetch_source(&args, &mut config)?;
+            ingest(database_url, &config, source.as_mut()).await
+        }
+        Command::Stream(args) => {
+            let mut source = stream_source(&args, &mut config)?;
+            ingest(database_url, &config, source.as_mut()).await
+        }
+        Command::Listen(args) => {
+            let mut source = listen_source(&args, &mut config);
+            ingest(database_url, &config, source.as_mut()).await
+        }
+        Command::Replay(args) => {
+            let mut source = replay_source(&args, &config);
+            ingest(database_url, &config, source.as_mut()).await
+        }
+        Command::Daemon(args) => {
+            if let Some(interval_seconds) = args.interval_seconds {
+                config.ingestion.poll_interval_seconds = interval_seconds;
+            }
+            if let Some(initial_lookback_hours) = args.initial_lookback_hours {
+                config.ingestion.initial_lookback_hours = initial_lookback_hours;
+            }
+            // The checkpoint is kept in the database whichever sink the messages go to.
+            let connection_pool = open_database(database_url, &config).await?;
+            let sink: Box<dyn AisSink> = match config.sink.kind {
+                SinkKind::Postgres => Box::new(PostgresSink::new(
+                    connection_pool.clone(),
+                    &config.ingestion,
+                    config.partitioning.clone(),
+                )),
+                SinkKind::JsonLines => Box::new(JsonLinesSink::new(&config.sink.directory)),
+            };
+            run_daemon(connection_pool, sink.as_ref(), ais_client(&config)?, &config.ingestion, &config.filter).await;
+            Ok(())
+        }
+        Command::Export(args) => export(&open_database(database_url, &config).await?, args).await,
+        Command::Query(QueryCommand::Vessel { mmsi }) => {
+            let connection_pool = open_database(database_url, &config).await?;
+            let vessel = VesselSummary {
+                vessel: get_vessel(&connection_pool, mmsi).await?,
+                position: get_fleet_position(&connection_pool, mmsi).await?,
+                history: get_vessel_history(&connection_pool, mmsi).await?,
+            };
+            if vessel.vessel.is_none() && vessel.position.is_none() {
+                return Err(format!("No data stored for MMSI {}", mmsi).into());
+            }
+            println!("{}", serde_json::to_string_pretty(&vessel)?);
+            Ok(())
+        }
+        Command::Query(QueryCommand::Fleet { since }) => {
+            let connection_pool = open_database(database_url, &config).await?;
+            let mut writer = BufWriter::new(io::stdout());
+            let mut positions = get_fleet_picture(&connection_pool, since);
+            while let Some(position) = positions.next().await {
+                writeln!(writer, "{}", serde_json::to_string(&position?)?)?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+        Command::Query(QueryCommand::Area { kind, bbox, filter }) => {
+            let connection_pool = open_database(database_url, &config).await?;
+            query_area(&connection_pool, kind, &SpatialFilter::BoundingBox(bbox), filter).await
+        }
+        Command::Query(QueryCommand::Near { kind, latitude, longitude, meters, filter }) => {
+            let connection_pool = open_database(database_url, &config).await?;
+            let area = SpatialFilter::Radius { latitude, longitude, meters };
+            query_area(&connection_pool, kind, &area, filter).await
+        }
+        Command::Rejected(RejectedCommand::List { filter, all }) => {
+            let connection_pool = open_database(database_url, &config).await?;
+            let filter = RejectedMessageFilter {
+                include_reprocessed: all,
+                ..rejected_filter(filter)
+            };
+            let mut writer = BufWriter::new(io::stdout());
+            for message in list_rejected_messages(&connection_pool, &filter).await? {
+                writeln!(writer, "{}", serde_json::to_string(&message)?)?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+        Command::Rejected(RejectedCommand::Reprocess { filter }) => {
+            let connection_pool = open_database(database_url, &config).await?;
+            let sink = PostgresSink::new(connection_pool.clone(), &config.ingestion, config.partitioning.clone());
+            reprocess_rejected(&connection_pool, &sink, &rejected_filter(filter), &config.ingestion).await
+        }
+        Command::Stats => {
+            let stats = get_database_stats(&open_database(database_url, &config).await?).await?;
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            Ok(())
+        }
+        Command::Partitions(args) => {
+            partitions(&open_database(database_url, &config).await?, &config.partitioning, args).await
+        }
+    }
+}
+
+async fn connect(database_url: Option<&str>, config: &Settings) -> PgPool {
+    let connection_string = database_url
+        .map(str::to_owned)
+        .unwrap_or_else(|| config.database.connection_string());
+    PgPool::connect(&connection_string)
         .await
-        .expect("Failed to connect to Postgres");
+        .expect("Failed to connect to Postgres")
+}
 
-    let ais = AisLiveAPI::new(
+// Connects and checks that the schema is one this binary can work with.
+async fn open_database(database_url: Option<&str>, config: &Settings) -> Result<PgPool, Box<dyn Error>> {
+    let connection_pool = connect(database_url, config).await;
+    let schema = ensure_compatible_schema(&connection_pool, config.database.auto_migrate).await?;
+    debug!("Database schema is at version {:?}", schema.current_version);
+
+    Ok(connection_pool)
+}
+
+// Writes a source into the configured sink, writing to files needs no database.
+async fn ingest(database_url: Option<&str>, config: &Settings, source: &mut dyn AisSource) -> Result<(), Box<dyn Error>> {
+    match config.sink.kind {
+        SinkKind::JsonLines => {
+            let sink = JsonLinesSink::new(&config.sink.directory);
+            run_ingestion(&sink, source, &config.ingestion).await
+        }
+        SinkKind::Postgres => {
+            let connection_pool = open_database(database_url, config).await?;
+            let sink = PostgresSink::new(connection_pool, &config.ingestion, config.partitioning.clone());
+            run_ingestion(&sink, source, &config.ingestion).await
+        }
+    }
+}
+
+// The sources of the ingestion commands, with the options of the command applied to the
+// configuration.
+fn fetch_source(args: &FetchArgs, config: &mut Settings) -> Result<Box<dyn AisSource>, Box<dyn Error>> {
+    let since = args.since.unwrap_or_else(|| {
+        let lookback_hours = args.lookback_hours.unwrap_or(config.ingestion.initial_lookback_hours);
+        Utc::now() - chrono::Duration::hours(lookback_hours)
+    });
+    if !args.mmsi.is_empty() {
+        config.filter.mmsi = args.mmsi.clone();
+    }
+    if !args.ship_types.is_empty() {
+        config.filter.ship_types = args.ship_types.clone();
+    }
+    if !args.country_codes.is_empty() {
+        config.filter.country_codes = args.country_codes.clone();
+    }
+    if !args.message_types.is_empty() {
+        config.filter.message_types = args.message_types.iter().copied().map(Into::into).collect();
+    }
+    if let Some(area) = &args.area {
+        config.filter.area = Some(area.corners().to_vec());
+    }
+    if let Some(model_format) = args.model_format {
+        config.filter.model_format = model_format.into();
+    }
+    let query = AisQuery::from_settings(since, &config.filter);
+    Ok(Box::new(LatestSource::new(ais_client(config)?, query)))
+}
+
+fn stream_source(args: &StreamArgs, config: &mut Settings) -> Result<Box<dyn AisSource>, Box<dyn Error>> {
+    if let Some(batch_size) = args.batch_size {
+        config.ingestion.stream_batch_size = batch_size;
+    }
+    if let Some(batch_timeout_seconds) = args.batch_timeout_seconds {
+        config.ingestion.stream_batch_timeout_seconds = batch_timeout_seconds;
+    }
+    Ok(Box::new(LiveStreamSource::new(ais_client(config)?, Batching::from(&config.ingestion))))
+}
+
+fn listen_source(args: &ListenArgs, config: &mut Settings) -> Box<dyn AisSource> {
+    if let Some(transport) = args.transport {
+        config.nmea.transport = transport.into();
+    }
+    if let Some(address) = &args.address {
+        config.nmea.address = address.clone();
+    }
+    if let Some(batch_size) = args.batch_size {
+        config.ingestion.stream_batch_size = batch_size;
+    }
+    if let Some(batch_timeout_seconds) = args.batch_timeout_seconds {
+        config.ingestion.stream_batch_timeout_seconds = batch_timeout_seconds;
+    }
+    Box::new(NmeaSocketSource::new(config.nmea.clone(), Batching::from(&config.ingestion)))
+}
+
+fn replay_source(args: &ReplayArgs, config: &Settings) -> Box<dyn AisSource> {
+    let batch_size = args.batch_size.unwrap_or(config.ingestion.stream_batch_size);
+    match args.format {
+        ReplayFormat::Json => Box::new(JsonLinesSource::new(args.path.clone(), batch_size)),
+        ReplayFormat::Nmea => {
+            let source = NmeaFileSource::new(args.path.clone(), batch_size);
+            Box::new(match args.base_time {
+                Some(base_time) => source.with_base_time(base_time),
+                None => source,
+            })
+        }
+    }
+}
+
+async fn run_ingestion(sink: &dyn AisSink, source: &mut dyn AisSource, settings: &IngestionSettings) -> Result<(), Box<dyn Error>> {
+    let failed_batches = ingest_source(sink, source, settings).await?;
+    if failed_batches > 0 {
+        return Err(format!("{} batches were not written successfully", failed_batches).into());
+    }
+
+    Ok(())
+}
+
+fn ais_client(config: &Settings) -> Result<AisLiveAPI, Box<dyn Error>> {
+    Ok(AisLiveAPI::new(
         "client_credentials".to_owned(),
-        env::var("CLIENT_ID").unwrap().to_owned(),
-        env::var("CLIENT_SECRET").unwrap().to_owned(),
+        env::var("CLIENT_ID").map_err(|_| "CLIENT_ID is not set")?,
+        env::var("CLIENT_SECRET").map_err(|_| "CLIENT_SECRET is not set")?,
         barents::live_ais::ais_stream::ScopeType::Ais,
-    );
-
-    let last_hour = fetch_last_hours_ais(ais).await?;
-    let log_id = insert_request_log(
-            connection_pool.clone(),
-            &last_hour.ais_response.api_endpoint,
-            &last_hour.status_code,
-            &last_hour.number_of_items,
+    )
+    .with_endpoints(config.api.clone())
+    .with_retry(config.retry.clone())
+    .with_token_settings(config.token.clone()))
+}
+
+async fn run_mock_server(args: &MockServerArgs) -> Result<(), Box<dyn Error>> {
+    let fixtures = |path: &Option<PathBuf>| -> Result<Option<Vec<serde_json::Value>>, Box<dyn Error>> {
+        match path {
+            Some(path) => Ok(Some(parse_json_lines(&std::fs::read_to_string(path)?)?)),
+            None => Ok(None),
+        }
+    };
+    let mut script = MockScript {
+        token_expires_in: args.token_expires_in,
+        failures: args.failures.clone(),
+        ..MockScript::default()
+    };
+    if let Some(latest) = fixtures(&args.latest)? {
+        script.latest = latest;
+    }
+    if let Some(stream) = fixtures(&args.stream)? {
+        script.stream = stream;
+    }
+
+    let server = MockServer::start(script, args.address).await?;
+    let api = server.api_settings();
+    println!("Serving the mock API, set api.base_url to {} and api.token_url to {}", api.base_url, api.token_url);
+    std::future::pending::<()>().await;
+    Ok(())
+}
+
+async fn migrate(connection_pool: &PgPool, args: &MigrateArgs) -> Result<(), Box<dyn Error>> {
+    if let Some(version) = args.baseline {
+        baseline_migrations(connection_pool, version).await?;
+    }
+    let status = if args.status {
+        schema_status(connection_pool).await?
+    } else {
+        run_migrations(connection_pool).await?
+    };
+
+    println!("Current schema version: {}", status.current_version.map_or("none".to_owned(), |version| version.to_string()));
+    println!("Latest known version:   {}", status.latest_version.map_or("none".to_owned(), |version| version.to_string()));
+    if !status.pending.is_empty() {
+        println!("Pending migrations:     {:?}", status.pending);
+    }
+    if status.is_incompatible() {
+        return Err(format!(
+            "Database schema is incompatible with this binary: dirty {:?}, unknown {:?}, modified {:?}",
+            status.dirty, status.unknown, status.modified
         )
-        .await?;
-    insert_ais_items(connection_pool, log_id, last_hour).await?;
+        .into());
+    }
 
     Ok(())
 }
 
-async fn insert_ais_items(connection_pool: PgPool, log_id: Uuid, last_hour: LastHourAISMessage) -> Result<(), Box<dyn Error>> {
-    if let Some(messages) = last_hour.ais_response.ais_latest_responses {
-        // TODO: Handle errors.
-        let split_messages = process_ais_items(messages).unwrap();
-
-        let aton_handle = task::spawn(insert_aton_data(
-            connection_pool.clone(),
-            split_messages.aton_data,
-            log_id.clone(),
-        ));
-        let static_handle = task::spawn(insert_static_data(
-            connection_pool.clone(),
-            split_messages.static_data,
-            log_id.clone(),
-        ));
-        let position_handle = task::spawn(insert_position_data(
-            connection_pool.clone(),
-            split_messages.position_data,
-            log_id.clone(),
-        ));
-
-        let res = tokio::try_join!(aton_handle, static_handle, position_handle);
-        match res {
-            Ok(..) => {
-                debug!("Threads completed");
-            }
-            Err(error) => warn!("There was an error in one of the threads: {:?}", error)
+async fn partitions(connection_pool: &PgPool, settings: &PartitionSettings, args: PartitionsArgs) -> Result<(), Box<dyn Error>> {
+    if !args.list {
+        let report = maintain_position_partitions(connection_pool, settings).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    for partition in get_position_partitions(connection_pool).await? {
+        println!("{}", serde_json::to_string(&partition)?);
+    }
+
+    Ok(())
+}
+
+async fn query_area(connection_pool: &PgPool, kind: AreaKind, area: &SpatialFilter, args: AreaFilterArgs) -> Result<(), Box<dyn Error>> {
+    let filter = ExportFilter {
+        since: args.since,
+        until: args.until,
+        mmsi: args.mmsi,
+    };
+    let mut writer = BufWriter::new(io::stdout());
+    match kind {
+        AreaKind::Position => write_json_lines(&mut writer, &find_positions(connection_pool, area, &filter).await?)?,
+        AreaKind::Aton => write_json_lines(&mut writer, &find_atons(connection_pool, area, &filter).await?)?,
+        AreaKind::Fleet => {
+            write_json_lines(&mut writer, &find_fleet_positions(connection_pool, area, &filter).await?)?
         }
     }
+    writer.flush()?;
+    Ok(())
+}
 
+fn write_json_lines<T: Serialize>(writer: &mut impl Write, rows: &[T]) -> Result<(), Box<dyn Error>> {
+    for row in rows {
+        writeln!(writer, "{}", serde_json::to_string(row)?)?;
+    }
     Ok(())
 }
 
-fn process_ais_items(ais_items: AISLatestResponses) -> Result<SplitAISMessages, Box<dyn Error>> {
-    let static_data: Arc<Mutex<Vec<AISStaticData>>> = Arc::new(Mutex::new(Vec::new()));
-    let aton_data: Arc<Mutex<Vec<AISAtonData>>> = Arc::new(Mutex::new(Vec::new()));
-    let position_data: Arc<Mutex<Vec<AISPositionData>>> = Arc::new(Mutex::new(Vec::new()));
-
-    ais_items
-        .par_iter()
-        .for_each(|item| match &item.type_field {
-            None => {}
-            Some(item_type) => {
-                if item_type.eq_ignore_ascii_case("staticdata") {
-                    let mut res = static_data.lock().unwrap();
-                    let stat: AISStaticData = (item).into();
-                    res.push(stat);
-                } else if item_type.eq_ignore_ascii_case("aton") {
-                    let mut res = aton_data.lock().unwrap();
-                    let aton: AISAtonData = (item).into();
-                    res.push(aton);
-                } else if item_type.eq_ignore_ascii_case("position") {
-                    let mut res = position_data.lock().unwrap();
-                    let position: AISPositionData = (item).into();
-                    res.push(position);
+async fn export(connection_pool: &PgPool, args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let filter = ExportFilter {
+        since: args.since,
+        until: args.until,
+        mmsi: args.mmsi,
+    };
+    let mut writer: BufWriter<Box<dyn Write>> = BufWriter::new(match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    });
+
+    let mut encoder = NmeaEncoder::default();
+    let mut number_of_rows = 0;
+    match args.kind {
+        ExportKind::Position => {
+            let mut rows = export_position_data(connection_pool, &filter);
+            while let Some(row) = rows.next().await {
+                let row = row?;
+                match args.format {
+                    ExportFormat::Json => writeln!(writer, "{}", serde_json::to_string(&row)?)?,
+                    ExportFormat::Nmea => write_sentences(&mut writer, encoder.encode_position(&row))?,
                 }
+                number_of_rows += 1;
             }
-        });
-    let static_data = static_data.lock().unwrap().clone();
-    let aton_data = aton_data.lock().unwrap().clone();
-    let position_data = position_data.lock().unwrap().clone();
-
-    let split_data = SplitAISMessages {
-        static_data,
-        aton_data,
-        position_data,
-    };
+        }
+        ExportKind::Static => {
+            let mut rows = export_static_data(connection_pool, &filter);
+            while let Some(row) = rows.next().await {
+                let row = row?;
+                match args.format {
+                    ExportFormat::Json => writeln!(writer, "{}", serde_json::to_string(&row)?)?,
+                    ExportFormat::Nmea => write_sentences(&mut writer, encoder.encode_static_data(&row))?,
+                }
+                number_of_rows += 1;
+            }
+        }
+        ExportKind::Aton => {
+            let mut rows = export_aton_data(connection_pool, &filter);
+            while let Some(row) = rows.next().await {
+                let row = row?;
+                match args.format {
+                    ExportFormat::Json => writeln!(writer, "{}", serde_json::to_string(&row)?)?,
+                    ExportFormat::Nmea => write_sentences(&mut writer, encoder.encode_aton(&row))?,
+                }
+                number_of_rows += 1;
+            }
+        }
+    }
+    writer.flush()?;
+    info!("Exported {} rows", number_of_rows);
+
+    Ok(())
+}
+
+fn rejected_filter(args: RejectedFilterArgs) -> RejectedMessageFilter {
+    RejectedMessageFilter {
+        stage: args.stage.map(Into::into),
+        log_id: args.log_id,
+        since: args.since,
+        include_reprocessed: false,
+        limit: args.limit,
+    }
+}
+
+// Ingests the pending rejected messages in batches, each recorded as a request of its own, and
+// marks a batch re-processed once its request succeeded. Messages rejected again get a new row.
+async fn reprocess_rejected(
+    connection_pool: &PgPool,
+    sink: &dyn AisSink,
+    filter: &RejectedMessageFilter,
+    settings: &IngestionSettings,
+) -> Result<(), Box<dyn Error>> {
+    let rejected = list_rejected_messages(connection_pool, filter).await?;
+    if rejected.is_empty() {
+        info!("No rejected messages to re-process");
+        return Ok(());
+    }
+
+    let mut failed_batches = 0;
+    for batch in rejected.chunks(settings.stream_batch_size.max(1)) {
+        let messages = batch
+            .iter()
+            .map(|message| serde_json::from_value::<AisMessage>(message.payload.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let request = RequestLog {
+            source: RequestSource::Reprocess,
+            api_endpoint: "log.rejected_messages".to_owned(),
+            status_code: None,
+            number_of_messages: i64::try_from(messages.len()).unwrap_or_default(),
+            unknown_messages: 0,
+            quality_reports: Vec::new(),
+        };
+        let report = ingest_ais_items(sink, request, messages, settings).await?;
+        if report.outcome != RequestOutcome::Success {
+            failed_batches += 1;
+            warn!(
+                "Re-processing finished as {}, the messages stay pending: {}",
+                report.outcome.as_str(),
+                report.error.unwrap_or_default()
+            );
+            continue;
+        }
+        let ids: Vec<i64> = batch.iter().map(|message| message.id).collect();
+        mark_reprocessed(connection_pool, &ids, report.log_id).await?;
+        info!(
+            "Re-processed {} rejected messages as request {}, {} rejected again",
+            ids.len(),
+            report.log_id,
+            report.metrics.rejected_messages
+        );
+    }
+    if failed_batches > 0 {
+        return Err(format!("{} batches were not re-processed", failed_batches).into());
+    }
 
-    Ok(split_data)
+    Ok(())
 }
 
-async fn fetch_last_hours_ais(mut ais: AisLiveAPI) -> Result<LastHourAISMessage, Box<dyn Error>> {
-    let last_hour = ais
-        .get_latest_ais(Utc::now() - chrono::Duration::hours(1))
-        .await?;
+// Rows that cannot be encoded, such as those without an MMSI, are skipped with a warning.
+fn write_sentences(writer: &mut impl Write, sentences: Result<Vec<String>, NmeaError>) -> io::Result<()> {
+    match sentences {
+        Ok(sentences) => {
+            for sentence in sentences {
+                writeln!(writer, "{}", sentence)?;
+            }
+        }
+        Err(error) => warn!("Skipping row: {}", error),
+    }
+    Ok(())
+}
 
-    let status_code: i32;
-    let number_of_items: i64;
+async fn run_daemon(connection_pool: PgPool, sink: &dyn AisSink, ais: AisLiveAPI, settings: &IngestionSettings, filter: &FilterSettings) {
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.poll_interval_seconds.max(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    info!("Starting daemon, polling every {} seconds", settings.poll_interval_seconds);
 
-    match i32::try_from(last_hour.status_code) {
-        Ok(val) => status_code = val,
-        Err(_) => {
-            warn!("Failed to convert the status code into i16 data type. Defaulting to 0");
-            status_code = 0;
+    loop {
+        interval.tick().await;
+        sink.maintain().await;
+        if let Err(error) = poll_latest_ais(connection_pool.clone(), sink, &ais, settings, filter).await {
+            match error.downcast_ref::<ResponseErrorMessages>() {
+                Some(ResponseErrorMessages::CircuitOpen { until }) => {
+                    info!("Polling is paused until {} after repeated failures", until)
+                }
+                _ => warn!("Polling the latest AIS messages failed, retrying on the next tick: {}", error),
+            }
         }
+    }
+}
+
+// Fetches everything newer than the stored checkpoint and only moves the checkpoint forward once
+// the messages have been written, so a failed poll is picked up again by the next one.
+async fn poll_latest_ais(connection_pool: PgPool, sink: &dyn AisSink, ais: &AisLiveAPI, settings: &IngestionSettings, filter: &FilterSettings) -> Result<(), Box<dyn Error>> {
+    let checkpoint = get_checkpoint(connection_pool.clone(), LATEST_AIS_CHECKPOINT).await?;
+    let last_msgtime = checkpoint.as_ref().and_then(|checkpoint| checkpoint.last_msgtime);
+    let since = match &checkpoint {
+        Some(checkpoint) => checkpoint.last_msgtime.unwrap_or(checkpoint.last_request_time),
+        None => Utc::now() - chrono::Duration::hours(settings.initial_lookback_hours),
     };
-    match i64::try_from(last_hour.content_length.unwrap_or_default()) {
-        Ok(val) => number_of_items = val,
-        Err(_) => {
-            warn!("Failed to convert the content length into i64 data type. Defaulting to 0");
-            number_of_items = 0;
+
+    let request_time = Utc::now();
+    let latest = fetch_latest(ais, &AisQuery::from_settings(since, filter)).await;
+    record_failed_requests(sink, RequestSource::BarentsWatch, &ais.failed_requests()).await;
+    let latest = latest?;
+    let mut messages = latest.messages;
+
+    // Messages in the second of the checkpoint are kept, some of them may have arrived after the
+    // previous poll. The ones it already wrote are skipped as duplicates on insert.
+    if let Some(last_msgtime) = last_msgtime {
+        messages.retain(|item| item.msgtime().is_none_or(|msgtime| msgtime >= last_msgtime));
+    }
+    let newest_msgtime = messages
+        .iter()
+        .filter_map(|item| item.msgtime())
+        .max()
+        .max(last_msgtime);
+
+    let number_of_items = i64::try_from(messages.len()).unwrap_or_default();
+    let request = RequestLog {
+        source: RequestSource::BarentsWatch,
+        api_endpoint: latest.endpoint,
+        status_code: latest.status_code,
+        number_of_messages: number_of_items,
+        unknown_messages: 0,
+        quality_reports: Vec::new(),
+    };
+    let report = ingest_ais_items(sink, request, messages, settings).await?;
+    if report.outcome != RequestOutcome::Success {
+        return Err(format!(
+            "ingestion finished as {}, keeping the checkpoint: {}",
+            report.outcome.as_str(),
+            report.error.unwrap_or_default()
+        )
+        .into());
+    }
+
+    update_checkpoint(
+        connection_pool,
+        LATEST_AIS_CHECKPOINT,
+        &IngestionCheckpoint {
+            last_msgtime: newest_msgtime,
+            last_request_time: request_time,
+        },
+        report.log_id,
+    )
+    .await?;
+    info!("Ingested {} new messages, checkpoint at {:?}", number_of_items, newest_msgtime);
+
+    Ok(())
+}
+
+// Writes the batches of a source, each recorded as its own request, until the source ends. Returns
+// the number of batches that were not written successfully.
+async fn ingest_source(sink: &dyn AisSink, source: &mut dyn AisSource, settings: &IngestionSettings) -> Result<usize, Box<dyn Error>> {
+    sink.maintain().await;
+    let mut last_maintenance = tokio::time::Instant::now();
+    let kind = source.kind();
+    let failed_requests = source.failed_requests();
+    let mut failed_batches = 0;
+
+    let mut batches = source.batches();
+    while let Some(batch) = batches.next().await {
+        record_failed_requests(sink, kind, &failed_requests).await;
+        let batch = batch?;
+        if last_maintenance.elapsed() >= MAINTENANCE_INTERVAL {
+            sink.maintain().await;
+            last_maintenance = tokio::time::Instant::now();
         }
+        let number_of_items = i64::try_from(batch.messages.len()).unwrap_or_default();
+        debug!("Writing a batch of {} messages from {}", number_of_items, batch.endpoint);
+        let request = RequestLog {
+            source: kind,
+            api_endpoint: batch.endpoint,
+            status_code: batch.status_code,
+            number_of_messages: number_of_items,
+            unknown_messages: 0,
+            quality_reports: Vec::new(),
+        };
+        let report = ingest_ais_items(sink, request, batch.messages, settings).await?;
+        if report.outcome != RequestOutcome::Success {
+            failed_batches += 1;
+            warn!(
+                "Batch {} finished as {}: {}",
+                report.log_id,
+                report.outcome.as_str(),
+                report.error.unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(failed_batches)
+}
+
+// Records each failed attempt as a failed request without messages. A failure to record them is
+// only logged, it should not stop the ingestion of what did arrive.
+async fn record_failed_requests(sink: &dyn AisSink, source: RequestSource, failed_requests: &FailedRequests) {
+    for failed in failed_requests.take() {
+        let request = RequestLog {
+            source,
+            api_endpoint: failed.endpoint,
+            status_code: failed.status_code,
+            number_of_messages: 0,
+            unknown_messages: 0,
+            quality_reports: Vec::new(),
+        };
+        let recorded = match sink.log_request(&request).await {
+            Ok(log_id) => {
+                let report = IngestionReport {
+                    log_id,
+                    outcome: RequestOutcome::Failed,
+                    error: Some(failed.error),
+                    metrics: InsertMetrics::default(),
+                };
+                sink.complete_request(&report).await
+            }
+            Err(error) => Err(error),
+        };
+        if let Err(error) = recorded {
+            warn!("Could not record a failed request to {}: {}", request.api_endpoint, error);
+        }
+    }
+}
+
+async fn ingest_ais_items(sink: &dyn AisSink, request: RequestLog, messages: AISLatestResponses, settings: &IngestionSettings) -> Result<IngestionReport, Box<dyn Error>> {
+    let mut split_messages: SplitAISMessages = messages.into_iter().collect();
+    if split_messages.unknown > 0 {
+        debug!("Skipping {} messages of an unknown type", split_messages.unknown);
+    }
+    let unparsable = split_messages.rejected.len() - split_messages.unknown;
+    if unparsable > 0 {
+        warn!("Rejected {} messages that do not parse", unparsable);
+    }
+    let quality_reports = validate_messages(&mut split_messages, settings.validation);
+    let rejected = quality_reports.iter().filter(|report| report.rejected).count();
+    if rejected > 0 {
+        warn!("Rejected {} messages with impossible values", rejected);
+    }
+    let request = RequestLog {
+        unknown_messages: i64::try_from(split_messages.unknown).unwrap_or(i64::MAX),
+        quality_reports: quality_reports.into_iter().filter(QualityReport::is_invalid).collect(),
+        ..request
     };
 
-    Ok(LastHourAISMessage {
-        status_code,
-        number_of_items,
-        ais_response: last_hour,
-    })
+    let report = sink.ingest(&request, &split_messages).await?;
+
+    Ok(report)
 }
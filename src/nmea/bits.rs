@@ -0,0 +1,171 @@
+// Bit level access to the 6-bit armored payload of AIVDM sentences.
+
+/// Converts a payload character to its 6-bit value, `None` for characters outside the armoring
+/// alphabet.
+pub(crate) fn dearmor(c: char) -> Option<u8> {
+    let value = match c {
+        '0'..='W' => c as u8 - 48,
+        '`'..='w' => c as u8 - 56,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Converts a 6-bit value to its payload character.
+pub(crate) fn armor(value: u8) -> char {
+    let value = value & 0x3f;
+    if value < 40 {
+        (value + 48) as char
+    } else {
+        (value + 56) as char
+    }
+}
+
+/// Reads fields out of a dearmored payload. Reading past the end yields zero bits, which lets
+/// messages that were sent a few bits short still decode.
+pub(crate) struct BitReader {
+    bits: Vec<bool>,
+}
+
+impl BitReader {
+    pub(crate) fn from_payload(payload: &str, fill_bits: u8) -> Result<Self, char> {
+        let mut bits = Vec::with_capacity(payload.len() * 6);
+        for c in payload.chars() {
+            let value = dearmor(c).ok_or(c)?;
+            bits.extend((0..6).rev().map(|bit| value & (1 << bit) != 0));
+        }
+        let length = bits.len().saturating_sub(usize::from(fill_bits));
+        bits.truncate(length);
+        Ok(BitReader { bits })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub(crate) fn unsigned(&self, start: usize, length: usize) -> u64 {
+        (start..start + length).fold(0, |value, index| {
+            (value << 1) | u64::from(self.bits.get(index).copied().unwrap_or(false))
+        })
+    }
+
+    /// A two's complement field.
+    pub(crate) fn signed(&self, start: usize, length: usize) -> i64 {
+        let value = self.unsigned(start, length) as i64;
+        if length > 0 && value & (1 << (length - 1)) != 0 {
+            value - (1 << length)
+        } else {
+            value
+        }
+    }
+
+    /// A string of 6-bit characters with the `@` padding and trailing spaces removed.
+    pub(crate) fn text(&self, start: usize, length: usize) -> String {
+        let text: String = (start..start + length)
+            .step_by(6)
+            .map(|index| {
+                let value = self.unsigned(index, 6) as u8;
+                if value < 32 {
+                    (value + 64) as char
+                } else {
+                    value as char
+                }
+            })
+            .collect();
+        let end = text.find('@').unwrap_or(text.len());
+        text[..end].trim_end().to_owned()
+    }
+}
+
+/// Builds a payload field by field, the reverse of [`BitReader`].
+#[derive(Default)]
+pub(crate) struct BitWriter {
+    bits: Vec<bool>,
+}
+
+impl BitWriter {
+    pub(crate) fn unsigned(&mut self, value: u64, length: usize) {
+        self.bits
+            .extend((0..length).rev().map(|bit| bit < 64 && value & (1 << bit) != 0));
+    }
+
+    pub(crate) fn signed(&mut self, value: i64, length: usize) {
+        self.unsigned(value as u64, length);
+    }
+
+    pub(crate) fn flag(&mut self, value: bool) {
+        self.bits.push(value);
+    }
+
+    /// Writes `text` as 6-bit characters, upper-cased and padded with `@` to `length` bits.
+    pub(crate) fn text(&mut self, text: &str, length: usize) {
+        let mut chars = text.chars().map(|c| c.to_ascii_uppercase());
+        for _ in 0..length / 6 {
+            let value = match chars.next() {
+                Some(c @ '@'..='_') => c as u8 - 64,
+                Some(c @ ' '..='?') => c as u8,
+                Some(_) => b'?',
+                None => 0,
+            };
+            self.unsigned(u64::from(value), 6);
+        }
+    }
+
+    /// The armored payload and the number of fill bits added to complete the last character.
+    pub(crate) fn finish(mut self) -> (String, u8) {
+        let fill_bits = (6 - self.bits.len() % 6) % 6;
+        self.bits.resize(self.bits.len() + fill_bits, false);
+        let payload = self
+            .bits
+            .chunks(6)
+            .map(|chunk| armor(chunk.iter().fold(0, |value, bit| (value << 1) | u8::from(*bit))))
+            .collect();
+        (payload, fill_bits as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armors_every_six_bit_value_reversibly() {
+        for value in 0..64 {
+            assert_eq!(dearmor(armor(value)), Some(value));
+        }
+        assert_eq!(dearmor('X'), None);
+        assert_eq!(dearmor('x'), None);
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let mut writer = BitWriter::default();
+        writer.unsigned(18, 6);
+        writer.signed(-1234, 28);
+        writer.flag(true);
+        writer.text("ever diadem", 120);
+        writer.unsigned(511, 9);
+        let (payload, fill_bits) = writer.finish();
+
+        let reader = BitReader::from_payload(&payload, fill_bits).unwrap();
+        assert_eq!(reader.len(), 6 + 28 + 1 + 120 + 9);
+        assert_eq!(reader.unsigned(0, 6), 18);
+        assert_eq!(reader.signed(6, 28), -1234);
+        assert_eq!(reader.unsigned(34, 1), 1);
+        assert_eq!(reader.text(35, 120), "EVER DIADEM");
+        assert_eq!(reader.unsigned(155, 9), 511);
+    }
+
+    #[test]
+    fn reads_zero_bits_past_the_end() {
+        let reader = BitReader::from_payload("w", 2).unwrap();
+
+        assert_eq!(reader.len(), 4);
+        assert_eq!(reader.unsigned(0, 8), 0b1111_0000);
+    }
+
+    #[test]
+    fn refuses_characters_outside_the_armoring() {
+        assert_eq!(BitReader::from_payload("15M!", 0).err(), Some('!'));
+    }
+}
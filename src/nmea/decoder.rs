@@ -0,0 +1,654 @@
+use crate::live_ais::ais_types::{AtonType, EpfdType, Eta, NavigationalStatus, ReceivedEta, ShipType};
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData, AisMessage};
+use crate::nmea::bits::BitReader;
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::json;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Fragments of multi-sentence messages kept at most, the oldest is dropped beyond this.
+const MAX_PENDING_MESSAGES: usize = 64;
+
+/// The fragment count is a single digit in AIVDM, so a message has at most nine sentences.
+const MAX_FRAGMENTS: usize = 9;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NmeaError {
+    #[error("not an AIVDM/AIVDO sentence: {0}")]
+    InvalidSentence(String),
+
+    #[error("checksum mismatch, sentence says {expected:02X} but it is {actual:02X}")]
+    ChecksumMismatch { expected: u8, actual: u8 },
+
+    #[error("invalid character {0:?} in the payload")]
+    InvalidPayloadCharacter(char),
+
+    #[error("message type {message_type} needs at least {expected} bits, got {actual}")]
+    PayloadTooShort {
+        message_type: u8,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("no tag block with the time the message was received")]
+    MissingTime,
+
+    #[error("cannot encode a message without {0}")]
+    MissingField(&'static str),
+
+    #[error("cannot encode a message of unknown type")]
+    UnsupportedMessage,
+}
+
+#[derive(Hash, PartialEq, Eq, Clone)]
+struct FragmentKey {
+    sequence_id: String,
+    channel: String,
+}
+
+struct PendingMessage {
+    fragments: Vec<Option<String>>,
+    /// The time of the first fragment, the message is decoded with it.
+    msgtime: Option<DateTime<Utc>>,
+    first_seen: DateTime<Utc>,
+}
+
+/// Decodes `!AIVDM`/`!AIVDO` sentences into the same message types as the BarentsWatch API.
+///
+/// Multi-sentence messages are buffered until their last fragment arrives. NMEA sentences carry no
+/// date, so `msgtime` is the receive time passed in, or the `c:` timestamp of a tag block in front
+/// of the first sentence of the message. Message types 1, 2, 3, 4, 5, 18, 19, 21, 24 and 27 are decoded; others come
+/// out as [`AisMessage::Unknown`] with the raw payload.
+#[derive(Default)]
+pub struct NmeaDecoder {
+    pending: HashMap<FragmentKey, PendingMessage>,
+}
+
+impl NmeaDecoder {
+    pub fn new() -> Self {
+        NmeaDecoder::default()
+    }
+
+    /// Decodes one line. Returns no messages while a multi-sentence message is incomplete, and
+    /// more than one for message types that carry both position and static data.
+    pub fn decode_sentence(
+        &mut self,
+        line: &str,
+        received_at: DateTime<Utc>,
+    ) -> Result<Vec<AisMessage>, NmeaError> {
+        self.decode(line, Some(received_at))
+    }
+
+    /// Decodes one line of a recording such as a receiver log, where the time the line is read says
+    /// nothing about when it was received. A message whose first sentence has no tag block with a
+    /// `c:` timestamp fails with [`NmeaError::MissingTime`].
+    pub fn decode_recorded(&mut self, line: &str) -> Result<Vec<AisMessage>, NmeaError> {
+        self.decode(line, None)
+    }
+
+    fn decode(&mut self, line: &str, received_at: Option<DateTime<Utc>>) -> Result<Vec<AisMessage>, NmeaError> {
+        let (tag_time, sentence) = strip_tag_block(line.trim())?;
+        let msgtime = tag_time.or(received_at);
+        let sentence = Sentence::parse(sentence)?;
+
+        if sentence.fragment_count <= 1 {
+            let msgtime = msgtime.ok_or(NmeaError::MissingTime)?;
+            return decode_payload(&sentence.payload, sentence.fill_bits, msgtime);
+        }
+
+        let key = FragmentKey {
+            sequence_id: sentence.sequence_id.clone(),
+            channel: sentence.channel.clone(),
+        };
+        if sentence.fragment_number == 1 {
+            self.evict_oldest();
+            self.pending.insert(
+                key.clone(),
+                PendingMessage {
+                    fragments: vec![None; sentence.fragment_count],
+                    msgtime,
+                    first_seen: Utc::now(),
+                },
+            );
+        }
+        let Some(pending) = self.pending.get_mut(&key) else {
+            // The first fragment was lost, nothing to attach this one to.
+            return Ok(Vec::new());
+        };
+        if pending.fragments.len() != sentence.fragment_count {
+            self.pending.remove(&key);
+            return Ok(Vec::new());
+        }
+        pending.fragments[sentence.fragment_number - 1] = Some(sentence.payload);
+        if sentence.fragment_number != sentence.fragment_count {
+            return Ok(Vec::new());
+        }
+
+        let pending = self.pending.remove(&key).expect("pending message was just looked up");
+        let Some(payload) = pending.fragments.into_iter().collect::<Option<String>>() else {
+            return Ok(Vec::new());
+        };
+        let msgtime = pending.msgtime.ok_or(NmeaError::MissingTime)?;
+        decode_payload(&payload, sentence.fill_bits, msgtime)
+    }
+
+    /// Whether fragments of an incomplete message are waiting for the rest.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn evict_oldest(&mut self) {
+        if self.pending.len() < MAX_PENDING_MESSAGES {
+            return;
+        }
+        if let Some(oldest) = self
+            .pending
+            .iter()
+            .min_by_key(|(_, pending)| pending.first_seen)
+            .map(|(key, _)| key.clone())
+        {
+            self.pending.remove(&oldest);
+        }
+    }
+}
+
+struct Sentence {
+    fragment_count: usize,
+    fragment_number: usize,
+    sequence_id: String,
+    channel: String,
+    payload: String,
+    fill_bits: u8,
+}
+
+impl Sentence {
+    fn parse(sentence: &str) -> Result<Self, NmeaError> {
+        let invalid = || NmeaError::InvalidSentence(sentence.to_owned());
+        let body = sentence.strip_prefix('!').ok_or_else(invalid)?;
+        let (body, checksum) = body.rsplit_once('*').ok_or_else(invalid)?;
+        let expected = u8::from_str_radix(checksum.get(..2).ok_or_else(invalid)?, 16).map_err(|_| invalid())?;
+        let actual = nmea_checksum(body);
+        if expected != actual {
+            return Err(NmeaError::ChecksumMismatch { expected, actual });
+        }
+
+        let fields: Vec<&str> = body.split(',').collect();
+        let [address, fragment_count, fragment_number, sequence_id, channel, payload, fill_bits] = fields[..] else {
+            return Err(invalid());
+        };
+        if address.len() != 5 || !(address.ends_with("VDM") || address.ends_with("VDO")) {
+            return Err(invalid());
+        }
+        let fragment_count: usize = fragment_count.parse().map_err(|_| invalid())?;
+        let fragment_number: usize = fragment_number.parse().map_err(|_| invalid())?;
+        if !(1..=MAX_FRAGMENTS).contains(&fragment_count) || fragment_number == 0 || fragment_number > fragment_count {
+            return Err(invalid());
+        }
+
+        Ok(Sentence {
+            fragment_count,
+            fragment_number,
+            sequence_id: sequence_id.to_owned(),
+            channel: channel.to_owned(),
+            payload: payload.to_owned(),
+            fill_bits: fill_bits.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// XOR of every character between the `!` or `$` and the `*`.
+pub(crate) fn nmea_checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+// Receivers can put an NMEA 4.10 tag block (`\s:station,c:1700000000*hh\`) in front of the
+// sentence. Its `c:` field is the receive time in UNIX seconds or milliseconds.
+fn strip_tag_block(line: &str) -> Result<(Option<DateTime<Utc>>, &str), NmeaError> {
+    let Some(rest) = line.strip_prefix('\\') else {
+        return Ok((None, line));
+    };
+    let invalid = || NmeaError::InvalidSentence(line.to_owned());
+    let (tag_block, sentence) = rest.split_once('\\').ok_or_else(invalid)?;
+    let (fields, checksum) = tag_block.rsplit_once('*').ok_or_else(invalid)?;
+    let expected = u8::from_str_radix(checksum, 16).map_err(|_| invalid())?;
+    let actual = nmea_checksum(fields);
+    if expected != actual {
+        return Err(NmeaError::ChecksumMismatch { expected, actual });
+    }
+
+    let received_at = fields
+        .split(',')
+        .find_map(|field| field.strip_prefix("c:"))
+        .and_then(|time| time.parse::<i64>().ok())
+        .and_then(|time| {
+            if time > 10_000_000_000 {
+                Utc.timestamp_millis_opt(time).single()
+            } else {
+                Utc.timestamp_opt(time, 0).single()
+            }
+        });
+    Ok((received_at, sentence))
+}
+
+/// Decodes a complete, reassembled payload.
+pub fn decode_payload(
+    payload: &str,
+    fill_bits: u8,
+    received_at: DateTime<Utc>,
+) -> Result<Vec<AisMessage>, NmeaError> {
+    let bits = BitReader::from_payload(payload, fill_bits).map_err(NmeaError::InvalidPayloadCharacter)?;
+    let message_type = bits.unsigned(0, 6) as u8;
+    let minimum_bits = match message_type {
+        1..=4 | 18 => 168,
+        5 => 420,
+        19 => 312,
+        21 => 272,
+        24 => 160,
+        27 => 96,
+        _ => 38,
+    };
+    if bits.len() < minimum_bits {
+        return Err(NmeaError::PayloadTooShort {
+            message_type,
+            expected: minimum_bits,
+            actual: bits.len(),
+        });
+    }
+
+    let messages = match message_type {
+        1..=3 => vec![AisMessage::Position(class_a_position(&bits, received_at))],
+        4 => vec![AisMessage::Position(base_station_report(&bits, received_at))],
+        5 => vec![AisMessage::StaticData(static_and_voyage_data(&bits, received_at))],
+        18 => vec![AisMessage::Position(class_b_position(&bits, received_at))],
+        19 => vec![
+            AisMessage::Position(class_b_position(&bits, received_at)),
+            AisMessage::StaticData(extended_class_b_static(&bits, received_at)),
+        ],
+        21 => vec![AisMessage::Aton(aid_to_navigation_report(&bits, received_at))],
+        24 => vec![AisMessage::StaticData(static_data_report(&bits, received_at))],
+        27 => vec![AisMessage::Position(long_range_position(&bits, received_at))],
+        _ => vec![AisMessage::Unknown(json!({
+            "type": "nmea",
+            "messageType": message_type,
+            "mmsi": bits.unsigned(8, 30),
+            "msgtime": received_at,
+            "payload": payload,
+            "fillBits": fill_bits,
+        }))],
+    };
+    Ok(messages)
+}
+
+fn mmsi(bits: &BitReader) -> Option<i64> {
+    Some(bits.unsigned(8, 30) as i64)
+}
+
+// Positions are in 1/10000 minute.
+fn coordinate(bits: &BitReader, start: usize, length: usize) -> Option<f64> {
+    Some(bits.signed(start, length) as f64 / 600_000.0)
+}
+
+fn text(bits: &BitReader, start: usize, length: usize) -> Option<String> {
+    Some(bits.text(start, length)).filter(|text| !text.is_empty())
+}
+
+fn dimension(bits: &BitReader, start: usize, length: usize) -> Option<i32> {
+    Some(bits.unsigned(start, length) as i32)
+}
+
+fn position(message_type: u8, ais_class: &str, received_at: DateTime<Utc>) -> AISPositionData {
+    AISPositionData {
+        type_field: Some("Position".to_owned()),
+        message_type: Some(i64::from(message_type)),
+        ais_class: Some(ais_class.to_owned()),
+        msgtime: Some(received_at),
+        ..Default::default()
+    }
+}
+
+fn class_a_position(bits: &BitReader, received_at: DateTime<Utc>) -> AISPositionData {
+    AISPositionData {
+        mmsi: mmsi(bits),
+        navigational_status: Some(NavigationalStatus::from(bits.unsigned(38, 4) as i64)),
+        rate_of_turn: Some(bits.signed(42, 8)),
+        speed_over_ground: Some(bits.unsigned(50, 10) as f64 / 10.0),
+        longitude: coordinate(bits, 61, 28),
+        latitude: coordinate(bits, 89, 27),
+        course_over_ground: Some(bits.unsigned(116, 12) as f64 / 10.0),
+        true_heading: Some(bits.unsigned(128, 9) as i64),
+        ..position(bits.unsigned(0, 6) as u8, "A", received_at)
+    }
+}
+
+// Base stations report their own clock, which is used as msgtime when it is set.
+fn base_station_report(bits: &BitReader, received_at: DateTime<Utc>) -> AISPositionData {
+    let msgtime = Utc
+        .with_ymd_and_hms(
+            bits.unsigned(38, 14) as i32,
+            bits.unsigned(52, 4) as u32,
+            bits.unsigned(56, 5) as u32,
+            bits.unsigned(61, 5) as u32,
+            bits.unsigned(66, 6) as u32,
+            bits.unsigned(72, 6) as u32,
+        )
+        .single()
+        .unwrap_or(received_at);
+    AISPositionData {
+        mmsi: mmsi(bits),
+        longitude: coordinate(bits, 79, 28),
+        latitude: coordinate(bits, 107, 27),
+        ..position(4, "A", msgtime)
+    }
+}
+
+fn class_b_position(bits: &BitReader, received_at: DateTime<Utc>) -> AISPositionData {
+    AISPositionData {
+        mmsi: mmsi(bits),
+        speed_over_ground: Some(bits.unsigned(46, 10) as f64 / 10.0),
+        longitude: coordinate(bits, 57, 28),
+        latitude: coordinate(bits, 85, 27),
+        course_over_ground: Some(bits.unsigned(112, 12) as f64 / 10.0),
+        true_heading: Some(bits.unsigned(124, 9) as i64),
+        ..position(bits.unsigned(0, 6) as u8, "B", received_at)
+    }
+}
+
+// Long range reports use their own resolution and "not available" values, which are mapped to
+// `None` here because they do not match the sentinels of the other position reports.
+fn long_range_position(bits: &BitReader, received_at: DateTime<Utc>) -> AISPositionData {
+    let speed_over_ground = bits.unsigned(79, 6);
+    let course_over_ground = bits.unsigned(85, 9);
+    AISPositionData {
+        mmsi: mmsi(bits),
+        navigational_status: Some(NavigationalStatus::from(bits.unsigned(40, 4) as i64)),
+        longitude: Some(bits.signed(44, 18) as f64 / 600.0),
+        latitude: Some(bits.signed(62, 17) as f64 / 600.0),
+        speed_over_ground: (speed_over_ground != 63).then_some(speed_over_ground as f64),
+        course_over_ground: (course_over_ground != 511).then_some(course_over_ground as f64),
+        ..position(27, "A", received_at)
+    }
+}
+
+fn static_data(message_type: u8, report_class: &str, received_at: DateTime<Utc>) -> AISStaticData {
+    AISStaticData {
+        type_field: Some("Staticdata".to_owned()),
+        message_type: Some(i64::from(message_type)),
+        report_class: Some(report_class.to_owned()),
+        msgtime: Some(received_at),
+        ..Default::default()
+    }
+}
+
+fn with_dimensions(data: AISStaticData, bits: &BitReader, start: usize) -> AISStaticData {
+    let dimension_a = dimension(bits, start, 9);
+    let dimension_b = dimension(bits, start + 9, 9);
+    let dimension_c = dimension(bits, start + 18, 6);
+    let dimension_d = dimension(bits, start + 24, 6);
+    AISStaticData {
+        ship_length: dimension_a.zip(dimension_b).map(|(a, b)| a + b),
+        ship_width: dimension_c.zip(dimension_d).map(|(c, d)| c + d),
+        dimension_a,
+        dimension_b,
+        dimension_c,
+        dimension_d,
+        ..data
+    }
+}
+
+fn static_and_voyage_data(bits: &BitReader, received_at: DateTime<Utc>) -> AISStaticData {
+    let eta = Eta::from_raw(
+        bits.unsigned(274, 4) as u32,
+        bits.unsigned(278, 5) as u32,
+        bits.unsigned(283, 5) as u32,
+        bits.unsigned(288, 6) as u32,
+    )
+    .filter(Eta::is_available)
+    .map(ReceivedEta::from);
+    let data = AISStaticData {
+        mmsi: mmsi(bits),
+        imo_number: Some(bits.unsigned(40, 30) as i64),
+        call_sign: text(bits, 70, 42),
+        name: text(bits, 112, 120),
+        ship_type: Some(ShipType::from(bits.unsigned(232, 8) as i64)),
+        position_fixing_device_type: Some(EpfdType::from(bits.unsigned(270, 4) as i64)),
+        eta,
+        // In 1/10 m.
+        draught: Some(bits.unsigned(294, 8) as i32),
+        destination: text(bits, 302, 120),
+        ..static_data(5, "A", received_at)
+    };
+    with_dimensions(data, bits, 240)
+}
+
+fn extended_class_b_static(bits: &BitReader, received_at: DateTime<Utc>) -> AISStaticData {
+    let data = AISStaticData {
+        mmsi: mmsi(bits),
+        name: text(bits, 143, 120),
+        ship_type: Some(ShipType::from(bits.unsigned(263, 8) as i64)),
+        position_fixing_device_type: Some(EpfdType::from(bits.unsigned(301, 4) as i64)),
+        ..static_data(19, "B", received_at)
+    };
+    with_dimensions(data, bits, 271)
+}
+
+// Class B static data comes in two parts, A with the name and B with the rest. Each part is
+// returned on its own; the vessel registry merges them.
+fn static_data_report(bits: &BitReader, received_at: DateTime<Utc>) -> AISStaticData {
+    let data = AISStaticData {
+        mmsi: mmsi(bits),
+        ..static_data(24, "B", received_at)
+    };
+    if bits.unsigned(38, 2) == 0 {
+        return AISStaticData {
+            name: text(bits, 40, 120),
+            ..data
+        };
+    }
+
+    let data = AISStaticData {
+        ship_type: Some(ShipType::from(bits.unsigned(40, 8) as i64)),
+        call_sign: text(bits, 90, 42),
+        ..data
+    };
+    // Auxiliary craft (MMSI 98XXXYYYY) carry the MMSI of their mother ship instead of dimensions.
+    if (980_000_000..990_000_000).contains(&data.mmsi.unwrap_or_default()) {
+        data
+    } else {
+        with_dimensions(data, bits, 132)
+    }
+}
+
+fn aid_to_navigation_report(bits: &BitReader, received_at: DateTime<Utc>) -> AISAtonData {
+    // Names longer than 20 characters continue after the fixed part of the message.
+    let extension_bits = (bits.len().saturating_sub(272) / 6) * 6;
+    let name = text(bits, 43, 120).map(|name| {
+        if name.len() == 20 {
+            name + &bits.text(272, extension_bits)
+        } else {
+            name
+        }
+    });
+
+    AISAtonData {
+        type_field: Some("Aton".to_owned()),
+        message_type: Some(21),
+        mmsi: mmsi(bits),
+        msgtime: Some(received_at),
+        type_of_aids_to_navigation: Some(AtonType::from(bits.unsigned(38, 5) as i64)),
+        name,
+        longitude: coordinate(bits, 164, 28),
+        latitude: coordinate(bits, 192, 27),
+        dimension_a: dimension(bits, 219, 9),
+        dimension_b: dimension(bits, 228, 9),
+        dimension_c: dimension(bits, 237, 6),
+        dimension_d: dimension(bits, 243, 6),
+        type_of_electronic_fixing_device: Some(EpfdType::from(bits.unsigned(249, 4) as i64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentence(body: &str) -> String {
+        format!("!{}*{:02X}", body, nmea_checksum(body))
+    }
+
+    const STATIC_FIRST: &str = "!AIVDM,2,1,1,A,55?MbV02;H;s<HtKR20EHE:0@T4@Dn2222222216L961O5Gf0NSQEp6ClRp8,0*1C";
+    const STATIC_LAST: &str = "!AIVDM,2,2,1,A,88888888880,2*25";
+
+    fn decode(lines: &[&str]) -> Vec<AisMessage> {
+        let mut decoder = NmeaDecoder::new();
+        lines
+            .iter()
+            .flat_map(|line| decoder.decode_sentence(line, Utc::now()).unwrap())
+            .collect()
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < 1e-6, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn decodes_a_class_a_position_report() {
+        let messages = decode(&["!AIVDM,1,1,,B,15M67FC000G?ufbE`FepT@3n00Sa,0*5C"]);
+
+        let [AisMessage::Position(position)] = &messages[..] else {
+            panic!("expected one position, got {:?}", messages);
+        };
+        assert_eq!(position.message_type, Some(1));
+        assert_eq!(position.mmsi, Some(366053209));
+        assert_eq!(position.navigational_status, Some(NavigationalStatus::RestrictedManoeuvrability));
+        assert_eq!(position.rate_of_turn, Some(0));
+        assert_close(position.speed_over_ground, 0.0);
+        assert_close(position.longitude, -122.341618);
+        assert_close(position.latitude, 37.802118);
+        assert_close(position.course_over_ground, 219.3);
+        assert_eq!(position.true_heading, Some(1));
+    }
+
+    #[test]
+    fn reassembles_static_and_voyage_data_from_two_fragments() {
+        let messages = decode(&[STATIC_FIRST, STATIC_LAST]);
+
+        let [AisMessage::StaticData(static_data)] = &messages[..] else {
+            panic!("expected one static message, got {:?}", messages);
+        };
+        assert_eq!(static_data.mmsi, Some(351759000));
+        assert_eq!(static_data.imo_number, Some(9134270));
+        assert_eq!(static_data.call_sign.as_deref(), Some("3FOF8"));
+        assert_eq!(static_data.name.as_deref(), Some("EVER DIADEM"));
+        assert_eq!(static_data.ship_type, Some(ShipType::from(70)));
+        assert_eq!(
+            (static_data.dimension_a, static_data.dimension_b, static_data.dimension_c, static_data.dimension_d),
+            (Some(225), Some(70), Some(1), Some(31))
+        );
+        assert_eq!(static_data.position_fixing_device_type, Some(EpfdType::from(1)));
+        assert_eq!(static_data.eta, Eta::from_raw(5, 15, 14, 0).map(ReceivedEta::from));
+        assert_eq!(static_data.draught, Some(122));
+        assert_eq!(static_data.destination.as_deref(), Some("NEW YORK"));
+    }
+
+    #[test]
+    fn decodes_a_class_b_position_report() {
+        let messages = decode(&["!AIVDM,1,1,,A,B52K>;h00Fc>jpUlNV@ikwpUoP06,0*4C"]);
+
+        let [AisMessage::Position(position)] = &messages[..] else {
+            panic!("expected one position, got {:?}", messages);
+        };
+        assert_eq!(position.message_type, Some(18));
+        assert_eq!(position.ais_class.as_deref(), Some("B"));
+        assert_eq!(position.mmsi, Some(338087471));
+        assert_close(position.speed_over_ground, 0.1);
+        assert_close(position.longitude, -74.072132);
+        assert_close(position.latitude, 40.68454);
+        assert_close(position.course_over_ground, 79.6);
+        assert_eq!(position.true_heading, Some(511));
+    }
+
+    #[test]
+    fn decodes_an_aid_to_navigation_report() {
+        let messages = decode(&["!AIVDM,1,1,,B,E>kb9O9aS@7PUh10dh19@;0Tah2cWrfP:l?M`00003vP100,0*01"]);
+
+        let [AisMessage::Aton(aton)] = &messages[..] else {
+            panic!("expected one aid to navigation, got {:?}", messages);
+        };
+        assert_eq!(aton.mmsi, Some(993692028));
+        assert_eq!(aton.name.as_deref(), Some("SF OAK BAY BR VAIS E"));
+        assert_eq!(aton.type_of_aids_to_navigation, Some(AtonType::from(19)));
+        assert_close(aton.longitude, -122.369867);
+        assert_close(aton.latitude, 37.805622);
+        assert_eq!(aton.type_of_electronic_fixing_device, Some(EpfdType::from(7)));
+    }
+
+    #[test]
+    fn decodes_both_parts_of_class_b_static_data() {
+        let messages = decode(&[
+            "!AIVDM,1,1,,A,H42O55i18tMET00000000000000,2*6D",
+            "!AIVDM,1,1,,A,H42O55lti4hhhilD3nink000?050,0*40",
+        ]);
+
+        let [AisMessage::StaticData(part_a), AisMessage::StaticData(part_b)] = &messages[..] else {
+            panic!("expected two static messages, got {:?}", messages);
+        };
+        assert_eq!(part_a.mmsi, Some(271041815));
+        assert_eq!(part_a.name.as_deref(), Some("PROGUY"));
+        assert_eq!(part_a.ship_type, None);
+        assert_eq!(part_b.mmsi, Some(271041815));
+        assert_eq!(part_b.name, None);
+        assert_eq!(part_b.ship_type, Some(ShipType::from(60)));
+        assert_eq!(part_b.call_sign.as_deref(), Some("TC6163"));
+        assert_eq!(
+            (part_b.dimension_a, part_b.dimension_b, part_b.dimension_c, part_b.dimension_d),
+            (Some(0), Some(15), Some(0), Some(5))
+        );
+    }
+
+    #[test]
+    fn takes_msgtime_from_the_tag_block() {
+        let mut decoder = NmeaDecoder::new();
+        let line = "\\c:1700000000*5F\\!AIVDM,1,1,,B,15M67FC000G?ufbE`FepT@3n00Sa,0*5C";
+
+        let messages = decoder.decode_sentence(line, Utc::now()).unwrap();
+
+        assert_eq!(messages[0].msgtime(), Utc.timestamp_opt(1_700_000_000, 0).single());
+    }
+
+    #[test]
+    fn decodes_recorded_sentences_only_with_the_time_of_the_first_fragment() {
+        let mut decoder = NmeaDecoder::new();
+        let tagged = format!("\\c:1700000000*5F\\{}", STATIC_FIRST);
+
+        let untagged = decoder.decode_recorded("!AIVDM,1,1,,B,15M67FC000G?ufbE`FepT@3n00Sa,0*5C");
+        assert_eq!(decoder.decode_recorded(&tagged), Ok(Vec::new()));
+        let messages = decoder.decode_recorded(STATIC_LAST).unwrap();
+
+        assert_eq!(untagged, Err(NmeaError::MissingTime));
+        assert_eq!(messages[0].msgtime(), Utc.timestamp_opt(1_700_000_000, 0).single());
+        assert_eq!(decoder.decode_recorded(STATIC_FIRST), Ok(Vec::new()));
+        assert_eq!(decoder.decode_recorded(STATIC_LAST), Err(NmeaError::MissingTime));
+    }
+
+    #[test]
+    fn rejects_a_wrong_checksum() {
+        let mut decoder = NmeaDecoder::new();
+
+        let result = decoder.decode_sentence("!AIVDM,1,1,,B,15M67FC000G?ufbE`FepT@3n00Sa,0*5D", Utc::now());
+
+        assert_eq!(result, Err(NmeaError::ChecksumMismatch { expected: 0x5D, actual: 0x5C }));
+    }
+
+    #[test]
+    fn rejects_fragment_counts_an_aivdm_sentence_cannot_have() {
+        let mut decoder = NmeaDecoder::new();
+
+        for count in ["0", "10", "999999999"] {
+            let line = sentence(&format!("AIVDM,{},1,,A,15M67FC000G?ufbE`FepT@3n00Sa,0", count));
+            assert!(matches!(
+                decoder.decode_sentence(&line, Utc::now()),
+                Err(NmeaError::InvalidSentence(_))
+            ));
+        }
+    }
+}
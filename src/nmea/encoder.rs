@@ -0,0 +1,473 @@
+use crate::live_ais::ais_types::ReceivedEta;
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData, AisMessage};
+use crate::nmea::bits::BitWriter;
+use crate::nmea::decoder::{nmea_checksum, NmeaError};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+/// Payload characters per sentence, which keeps sentences within the 82 characters NMEA allows.
+const MAX_PAYLOAD_PER_SENTENCE: usize = 60;
+
+/// Encodes messages as `!AIVDM` sentences, the reverse of
+/// [`NmeaDecoder`](crate::nmea::decoder::NmeaDecoder).
+///
+/// Position reports become type 1 (class A) or 18 (class B) and base stations type 4; static data
+/// becomes type 5 for class A and the two parts of type 24 for class B; aids to navigation become
+/// type 21. Fields that are `None` are written as the AIS "not available" value.
+pub struct NmeaEncoder {
+    channel: char,
+    sequence_id: u8,
+}
+
+impl Default for NmeaEncoder {
+    fn default() -> Self {
+        NmeaEncoder::new('A')
+    }
+}
+
+impl NmeaEncoder {
+    /// `channel` is the radio channel the sentences claim to be received on, `A` or `B`.
+    pub fn new(channel: char) -> Self {
+        NmeaEncoder { channel, sequence_id: 0 }
+    }
+
+    pub fn encode(&mut self, message: &AisMessage) -> Result<Vec<String>, NmeaError> {
+        match message {
+            AisMessage::Position(position) => self.encode_position(position),
+            AisMessage::StaticData(static_data) => self.encode_static_data(static_data),
+            AisMessage::Aton(aton) => self.encode_aton(aton),
+            AisMessage::Unknown(_) | AisMessage::Invalid(_) => Err(NmeaError::UnsupportedMessage),
+        }
+    }
+
+    pub fn encode_position(&mut self, position: &AISPositionData) -> Result<Vec<String>, NmeaError> {
+        let mmsi = position.mmsi.ok_or(NmeaError::MissingField("mmsi"))?;
+        let message_type = match position.message_type {
+            Some(message_type @ 1..=4) => message_type as u8,
+            Some(18 | 19) => 18,
+            _ if position.ais_class.as_deref() == Some("B") => 18,
+            _ => 1,
+        };
+
+        let mut bits = header(message_type, mmsi);
+        match message_type {
+            4 => {
+                let msgtime = position.msgtime.ok_or(NmeaError::MissingField("msgtime"))?;
+                bits.unsigned(msgtime.year() as u64, 14);
+                bits.unsigned(u64::from(msgtime.month()), 4);
+                bits.unsigned(u64::from(msgtime.day()), 5);
+                bits.unsigned(u64::from(msgtime.hour()), 5);
+                bits.unsigned(u64::from(msgtime.minute()), 6);
+                bits.unsigned(u64::from(msgtime.second()), 6);
+                bits.flag(false);
+                write_coordinates(&mut bits, position.latitude, position.longitude);
+                // EPFD, spare, RAIM and radio status.
+                bits.unsigned(0, 4 + 10 + 1 + 19);
+            }
+            18 => {
+                bits.unsigned(0, 8);
+                write_motion(&mut bits, position);
+                // Reserved, then the carrier sense unit flag and the rest of the capability flags,
+                // RAIM and radio status.
+                bits.unsigned(0, 2);
+                bits.flag(true);
+                bits.unsigned(0, 6 + 20);
+            }
+            _ => {
+                let navigational_status = position.navigational_status.map(i64::from).unwrap_or(15);
+                bits.unsigned(navigational_status.clamp(0, 15) as u64, 4);
+                let rate_of_turn = position.rate_of_turn.unwrap_or(-128).clamp(-128, 127);
+                bits.signed(rate_of_turn, 8);
+                write_motion(&mut bits, position);
+                // Maneuver indicator, spare, RAIM and radio status.
+                bits.unsigned(0, 2 + 3 + 1 + 19);
+            }
+        }
+        Ok(self.sentences(bits))
+    }
+
+    pub fn encode_static_data(&mut self, static_data: &AISStaticData) -> Result<Vec<String>, NmeaError> {
+        let mmsi = static_data.mmsi.ok_or(NmeaError::MissingField("mmsi"))?;
+        let class_b = static_data.report_class.as_deref() == Some("B")
+            || matches!(static_data.message_type, Some(19 | 24));
+        if class_b {
+            // Part A carries the name and part B the rest; a part is only sent when it has data, so
+            // that receivers merging the parts do not overwrite what they know with blanks.
+            let mut sentences = Vec::new();
+            if let Some(name) = &static_data.name {
+                let mut part_a = header(24, mmsi);
+                part_a.unsigned(0, 2);
+                part_a.text(name, 120);
+                sentences.extend(self.sentences(part_a));
+            }
+
+            let dimensions = [
+                static_data.dimension_a,
+                static_data.dimension_b,
+                static_data.dimension_c,
+                static_data.dimension_d,
+            ];
+            if static_data.ship_type.is_some()
+                || static_data.call_sign.is_some()
+                || dimensions.iter().any(Option::is_some)
+                || static_data.name.is_none()
+            {
+                let mut part_b = header(24, mmsi);
+                part_b.unsigned(1, 2);
+                part_b.unsigned(ship_type(static_data), 8);
+                // Vendor id.
+                part_b.text("", 42);
+                part_b.text(static_data.call_sign.as_deref().unwrap_or_default(), 42);
+                write_dimensions(&mut part_b, dimensions);
+                part_b.unsigned(epfd(static_data.position_fixing_device_type.map(i64::from)), 4);
+                part_b.unsigned(0, 2);
+                sentences.extend(self.sentences(part_b));
+            }
+            return Ok(sentences);
+        }
+
+        let mut bits = header(5, mmsi);
+        // AIS version.
+        bits.unsigned(0, 2);
+        bits.unsigned(static_data.imo_number.unwrap_or_default().clamp(0, (1 << 30) - 1) as u64, 30);
+        bits.text(static_data.call_sign.as_deref().unwrap_or_default(), 42);
+        bits.text(static_data.name.as_deref().unwrap_or_default(), 120);
+        bits.unsigned(ship_type(static_data), 8);
+        write_dimensions(
+            &mut bits,
+            [
+                static_data.dimension_a,
+                static_data.dimension_b,
+                static_data.dimension_c,
+                static_data.dimension_d,
+            ],
+        );
+        bits.unsigned(epfd(static_data.position_fixing_device_type.map(i64::from)), 4);
+        let eta = static_data.eta.as_ref().and_then(ReceivedEta::eta).unwrap_or_default();
+        bits.unsigned(u64::from(eta.month.unwrap_or(0)), 4);
+        bits.unsigned(u64::from(eta.day.unwrap_or(0)), 5);
+        bits.unsigned(u64::from(eta.hour.unwrap_or(24)), 5);
+        bits.unsigned(u64::from(eta.minute.unwrap_or(60)), 6);
+        bits.unsigned(static_data.draught.unwrap_or_default().clamp(0, 255) as u64, 8);
+        bits.text(static_data.destination.as_deref().unwrap_or_default(), 120);
+        // DTE and spare.
+        bits.unsigned(0, 2);
+        Ok(self.sentences(bits))
+    }
+
+    pub fn encode_aton(&mut self, aton: &AISAtonData) -> Result<Vec<String>, NmeaError> {
+        let mmsi = aton.mmsi.ok_or(NmeaError::MissingField("mmsi"))?;
+        let name = aton.name.as_deref().unwrap_or_default();
+        let (name, extension) = name.split_at(name.char_indices().nth(20).map_or(name.len(), |(index, _)| index));
+
+        let mut bits = header(21, mmsi);
+        let aid_type = aton.type_of_aids_to_navigation.map(i64::from).unwrap_or_default();
+        bits.unsigned(aid_type.clamp(0, 31) as u64, 5);
+        bits.text(name, 120);
+        bits.flag(false);
+        write_coordinates(&mut bits, aton.latitude, aton.longitude);
+        write_dimensions(
+            &mut bits,
+            [aton.dimension_a, aton.dimension_b, aton.dimension_c, aton.dimension_d],
+        );
+        bits.unsigned(epfd(aton.type_of_electronic_fixing_device.map(i64::from)), 4);
+        bits.unsigned(timestamp(aton.msgtime), 6);
+        // Off position, reserved, RAIM, virtual, assigned and spare.
+        bits.unsigned(0, 1 + 8 + 1 + 1 + 1 + 1);
+        // The extension holds up to 14 more characters.
+        let extension: String = extension.chars().take(14).collect();
+        bits.text(&extension, extension.len() * 6);
+        Ok(self.sentences(bits))
+    }
+
+    // Splits the payload over as many sentences as it needs. Multi-sentence messages get a
+    // sequential message id from 0 to 9 to tie their fragments together.
+    fn sentences(&mut self, bits: BitWriter) -> Vec<String> {
+        let (payload, fill_bits) = bits.finish();
+        let chunks: Vec<&str> = payload
+            .as_bytes()
+            .chunks(MAX_PAYLOAD_PER_SENTENCE)
+            .map(|chunk| std::str::from_utf8(chunk).expect("payload is ASCII"))
+            .collect();
+        let sequence_id = if chunks.len() > 1 {
+            let sequence_id = self.sequence_id;
+            self.sequence_id = (self.sequence_id + 1) % 10;
+            sequence_id.to_string()
+        } else {
+            String::new()
+        };
+
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let fill_bits = if index + 1 == chunks.len() { fill_bits } else { 0 };
+                let body = format!(
+                    "AIVDM,{},{},{},{},{},{}",
+                    chunks.len(),
+                    index + 1,
+                    sequence_id,
+                    self.channel,
+                    chunk,
+                    fill_bits
+                );
+                format!("!{}*{:02X}", body, nmea_checksum(&body))
+            })
+            .collect()
+    }
+}
+
+fn header(message_type: u8, mmsi: i64) -> BitWriter {
+    let mut bits = BitWriter::default();
+    bits.unsigned(u64::from(message_type), 6);
+    // Repeat indicator.
+    bits.unsigned(0, 2);
+    bits.unsigned(mmsi.clamp(0, (1 << 30) - 1) as u64, 30);
+    bits
+}
+
+// Positions are in 1/10000 minute, 91 and 181 degrees when not available.
+fn write_coordinates(bits: &mut BitWriter, latitude: Option<f64>, longitude: Option<f64>) {
+    let longitude = longitude.filter(|longitude| longitude.abs() <= 180.0).unwrap_or(181.0);
+    let latitude = latitude.filter(|latitude| latitude.abs() <= 90.0).unwrap_or(91.0);
+    bits.signed((longitude * 600_000.0).round() as i64, 28);
+    bits.signed((latitude * 600_000.0).round() as i64, 27);
+}
+
+// Speed, accuracy, position, course, heading and time stamp, which class A and B reports share.
+fn write_motion(bits: &mut BitWriter, position: &AISPositionData) {
+    // 102.2 knots stands for that speed or faster, so only 102.3 itself is "not available".
+    let speed_over_ground = match position.speed_over_ground.map(|speed| (speed * 10.0).round()) {
+        Some(speed) if speed != 1023.0 => speed.clamp(0.0, 1022.0) as u64,
+        _ => 1023,
+    };
+    bits.unsigned(speed_over_ground, 10);
+    bits.flag(false);
+    write_coordinates(bits, position.latitude, position.longitude);
+    let course_over_ground = position
+        .course_over_ground
+        .map_or(3600, |course| (course * 10.0).round().clamp(0.0, 3600.0) as u64);
+    bits.unsigned(course_over_ground, 12);
+    bits.unsigned(position.true_heading.map_or(511, |heading| heading.clamp(0, 511) as u64), 9);
+    bits.unsigned(timestamp(position.msgtime), 6);
+}
+
+fn write_dimensions(bits: &mut BitWriter, [a, b, c, d]: [Option<i32>; 4]) {
+    bits.unsigned(a.unwrap_or_default().clamp(0, 511) as u64, 9);
+    bits.unsigned(b.unwrap_or_default().clamp(0, 511) as u64, 9);
+    bits.unsigned(c.unwrap_or_default().clamp(0, 63) as u64, 6);
+    bits.unsigned(d.unwrap_or_default().clamp(0, 63) as u64, 6);
+}
+
+fn ship_type(static_data: &AISStaticData) -> u64 {
+    static_data
+        .ship_type
+        .map(i64::from)
+        .unwrap_or_default()
+        .clamp(0, 255) as u64
+}
+
+fn epfd(epfd: Option<i64>) -> u64 {
+    epfd.unwrap_or_default().clamp(0, 15) as u64
+}
+
+// The UTC second of the report, 60 when not available.
+fn timestamp(msgtime: Option<DateTime<Utc>>) -> u64 {
+    msgtime.map_or(60, |msgtime| u64::from(msgtime.second()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::live_ais::ais_types::{AtonType, EpfdType, Eta, NavigationalStatus, ShipType};
+    use crate::nmea::decoder::NmeaDecoder;
+    use chrono::TimeZone;
+
+    fn msgtime() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 18, 8, 0, 7).unwrap()
+    }
+
+    fn round_trip(message: AisMessage) -> Vec<AisMessage> {
+        let mut decoder = NmeaDecoder::new();
+        NmeaEncoder::default()
+            .encode(&message)
+            .unwrap()
+            .iter()
+            .flat_map(|sentence| decoder.decode_sentence(sentence, msgtime()).unwrap())
+            .collect()
+    }
+
+    fn class_a_position() -> AISPositionData {
+        AISPositionData {
+            type_field: Some("Position".to_owned()),
+            message_type: Some(1),
+            ais_class: Some("A".to_owned()),
+            msgtime: Some(msgtime()),
+            mmsi: Some(257012340),
+            navigational_status: Some(NavigationalStatus::from(0)),
+            rate_of_turn: Some(-5),
+            speed_over_ground: Some(12.5),
+            longitude: Some(18.75),
+            latitude: Some(69.5),
+            course_over_ground: Some(254.5),
+            true_heading: Some(254),
+            altitude: None,
+        }
+    }
+
+    #[test]
+    fn round_trips_a_class_a_position() {
+        let position = AisMessage::Position(class_a_position());
+
+        assert_eq!(round_trip(position.clone()), vec![position]);
+    }
+
+    #[test]
+    fn round_trips_the_not_available_values() {
+        let position = AisMessage::Position(AISPositionData {
+            navigational_status: Some(NavigationalStatus::from(15)),
+            rate_of_turn: Some(-128),
+            speed_over_ground: Some(102.3),
+            longitude: Some(181.0),
+            latitude: Some(91.0),
+            course_over_ground: Some(360.0),
+            true_heading: Some(511),
+            ..class_a_position()
+        });
+
+        assert_eq!(round_trip(position.clone()), vec![position]);
+    }
+
+    #[test]
+    fn writes_missing_values_as_not_available() {
+        let position = AisMessage::Position(AISPositionData {
+            navigational_status: None,
+            rate_of_turn: None,
+            speed_over_ground: None,
+            longitude: None,
+            latitude: None,
+            course_over_ground: None,
+            true_heading: None,
+            ..class_a_position()
+        });
+
+        let [AisMessage::Position(decoded)] = &round_trip(position)[..] else {
+            panic!("expected one position");
+        };
+        assert_eq!(decoded.navigational_status, Some(NavigationalStatus::from(15)));
+        assert_eq!(decoded.rate_of_turn, Some(-128));
+        assert_eq!(decoded.speed_over_ground, Some(102.3));
+        assert_eq!(decoded.longitude, Some(181.0));
+        assert_eq!(decoded.latitude, Some(91.0));
+        assert_eq!(decoded.course_over_ground, Some(360.0));
+        assert_eq!(decoded.true_heading, Some(511));
+    }
+
+    #[test]
+    fn reports_speeds_above_the_range_as_the_highest_speed() {
+        let position = AisMessage::Position(AISPositionData {
+            speed_over_ground: Some(150.0),
+            ..class_a_position()
+        });
+
+        let [AisMessage::Position(decoded)] = &round_trip(position)[..] else {
+            panic!("expected one position");
+        };
+        assert_eq!(decoded.speed_over_ground, Some(102.2));
+    }
+
+    #[test]
+    fn round_trips_a_class_b_position() {
+        let position = AisMessage::Position(AISPositionData {
+            message_type: Some(18),
+            ais_class: Some("B".to_owned()),
+            navigational_status: None,
+            rate_of_turn: None,
+            ..class_a_position()
+        });
+
+        assert_eq!(round_trip(position.clone()), vec![position]);
+    }
+
+    #[test]
+    fn round_trips_static_and_voyage_data_over_two_sentences() {
+        let static_data = AisMessage::StaticData(AISStaticData {
+            type_field: Some("Staticdata".to_owned()),
+            message_type: Some(5),
+            report_class: Some("A".to_owned()),
+            msgtime: Some(msgtime()),
+            mmsi: Some(351759000),
+            imo_number: Some(9134270),
+            call_sign: Some("3FOF8".to_owned()),
+            name: Some("EVER DIADEM".to_owned()),
+            ship_type: Some(ShipType::from(70)),
+            dimension_a: Some(225),
+            dimension_b: Some(70),
+            dimension_c: Some(1),
+            dimension_d: Some(31),
+            ship_length: Some(295),
+            ship_width: Some(32),
+            position_fixing_device_type: Some(EpfdType::from(1)),
+            eta: Eta::from_raw(5, 15, 14, 0).map(ReceivedEta::from),
+            draught: Some(122),
+            destination: Some("NEW YORK".to_owned()),
+        });
+
+        assert_eq!(NmeaEncoder::default().encode(&static_data).unwrap().len(), 2);
+        assert_eq!(round_trip(static_data.clone()), vec![static_data]);
+    }
+
+    #[test]
+    fn splits_class_b_static_data_into_its_two_parts() {
+        let part_a = AISStaticData {
+            type_field: Some("Staticdata".to_owned()),
+            message_type: Some(24),
+            report_class: Some("B".to_owned()),
+            msgtime: Some(msgtime()),
+            mmsi: Some(271041815),
+            name: Some("PROGUY".to_owned()),
+            ..Default::default()
+        };
+        let part_b = AISStaticData {
+            name: None,
+            ship_type: Some(ShipType::from(60)),
+            call_sign: Some("TC6163".to_owned()),
+            dimension_a: Some(0),
+            dimension_b: Some(15),
+            dimension_c: Some(0),
+            dimension_d: Some(5),
+            ship_length: Some(15),
+            ship_width: Some(5),
+            ..part_a.clone()
+        };
+        let static_data = AisMessage::StaticData(AISStaticData {
+            name: part_a.name.clone(),
+            ..part_b.clone()
+        });
+
+        assert_eq!(
+            round_trip(static_data),
+            vec![AisMessage::StaticData(part_a), AisMessage::StaticData(part_b)]
+        );
+    }
+
+    #[test]
+    fn round_trips_an_aid_to_navigation_with_a_long_name() {
+        let aton = AisMessage::Aton(AISAtonData {
+            type_field: Some("Aton".to_owned()),
+            message_type: Some(21),
+            msgtime: Some(msgtime()),
+            mmsi: Some(993692028),
+            type_of_aids_to_navigation: Some(AtonType::from(19)),
+            name: Some("SF OAK BAY BR VAIS EAST".to_owned()),
+            longitude: Some(-122.25),
+            latitude: Some(37.5),
+            dimension_a: Some(5),
+            dimension_b: Some(5),
+            dimension_c: Some(3),
+            dimension_d: Some(3),
+            type_of_electronic_fixing_device: Some(EpfdType::from(7)),
+        });
+
+        assert_eq!(round_trip(aton.clone()), vec![aton]);
+    }
+}
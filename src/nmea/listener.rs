@@ -0,0 +1,229 @@
+use crate::database::configuration::{NmeaSettings, NmeaTransport};
+use crate::live_ais::response_structs::AisMessage;
+use crate::nmea::decoder::{NmeaDecoder, NmeaError};
+use chrono::Utc;
+use futures::Stream;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+// Messages buffered between the sockets and the consumer of the stream.
+const CHANNEL_CAPACITY: usize = 1024;
+
+// Largest datagram accepted, a UDP datagram can hold several sentences.
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+#[derive(Error, Debug)]
+pub enum ListenerError {
+    #[error("network error: {0}")]
+    Io(std::io::Error),
+
+    #[error("invalid sentence from {peer}: {error}")]
+    InvalidSentence { peer: SocketAddr, error: NmeaError },
+}
+
+/// Reads NMEA sentences from a local receiver as configured and yields the decoded messages.
+///
+/// The sockets are served by a background task that stops once the stream is dropped. A lost
+/// `tcp_client` connection is re-established after the configured delay, a failure to bind the
+/// `tcp_server` or `udp` address is yielded and ends the stream.
+pub fn listen_nmea(settings: &NmeaSettings) -> impl Stream<Item = Result<AisMessage, ListenerError>> {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let address = settings.address.clone();
+    let reconnect_delay = Duration::from_secs(settings.reconnect_delay_seconds);
+
+    match settings.transport {
+        NmeaTransport::TcpClient => tokio::spawn(run_tcp_client(address, reconnect_delay, sender)),
+        NmeaTransport::TcpServer => tokio::spawn(run_tcp_server(address, sender)),
+        NmeaTransport::Udp => tokio::spawn(run_udp(address, sender)),
+    };
+
+    ReceiverStream::new(receiver)
+}
+
+/// Like [`listen_nmea`] for `tcp_server`, accepting connections on a listener that is already
+/// bound.
+pub fn listen_tcp(listener: TcpListener) -> impl Stream<Item = Result<AisMessage, ListenerError>> {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(serve_tcp(listener, sender));
+    ReceiverStream::new(receiver)
+}
+
+/// Like [`listen_nmea`] for `udp`, reading datagrams from a socket that is already bound.
+pub fn listen_udp(socket: UdpSocket) -> impl Stream<Item = Result<AisMessage, ListenerError>> {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(serve_udp(socket, sender));
+    ReceiverStream::new(receiver)
+}
+
+/// The endpoint recorded in log.requests for batches read with these settings.
+pub fn nmea_endpoint(settings: &NmeaSettings) -> String {
+    match settings.transport {
+        NmeaTransport::TcpClient | NmeaTransport::TcpServer => format!("tcp://{}", settings.address),
+        NmeaTransport::Udp => format!("udp://{}", settings.address),
+    }
+}
+
+type Sender = mpsc::Sender<Result<AisMessage, ListenerError>>;
+
+async fn run_tcp_client(address: String, reconnect_delay: Duration, sender: Sender) {
+    loop {
+        match TcpStream::connect(&address).await {
+            Ok(stream) => {
+                let peer = stream.peer_addr().unwrap_or_else(|_| unspecified_address());
+                info!("Connected to the NMEA receiver at {}", peer);
+                if let Err(error) = read_sentences(stream, peer, &sender).await {
+                    if sender.send(Err(ListenerError::Io(error))).await.is_err() {
+                        return;
+                    }
+                }
+                warn!("NMEA receiver at {} closed the connection, reconnecting.", peer);
+            }
+            Err(error) => {
+                if sender.send(Err(ListenerError::Io(error))).await.is_err() {
+                    return;
+                }
+            }
+        }
+        if sender.is_closed() {
+            return;
+        }
+        tokio::time::sleep(reconnect_delay).await;
+    }
+}
+
+async fn run_tcp_server(address: String, sender: Sender) {
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            let _ = sender.send(Err(ListenerError::Io(error))).await;
+            return;
+        }
+    };
+    info!("Listening for NMEA connections on {}", address);
+    serve_tcp(listener, sender).await;
+}
+
+async fn serve_tcp(listener: TcpListener, sender: Sender) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    info!("Accepted an NMEA connection from {}", peer);
+                    let sender = sender.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = read_sentences(stream, peer, &sender).await {
+                            let _ = sender.send(Err(ListenerError::Io(error))).await;
+                        }
+                        debug!("NMEA connection from {} closed", peer);
+                    });
+                }
+                Err(error) => {
+                    if sender.send(Err(ListenerError::Io(error))).await.is_err() {
+                        return;
+                    }
+                }
+            },
+            _ = sender.closed() => return,
+        }
+    }
+}
+
+async fn run_udp(address: String, sender: Sender) {
+    let socket = match UdpSocket::bind(&address).await {
+        Ok(socket) => socket,
+        Err(error) => {
+            let _ = sender.send(Err(ListenerError::Io(error))).await;
+            return;
+        }
+    };
+    info!("Listening for NMEA datagrams on {}", address);
+    serve_udp(socket, sender).await;
+}
+
+async fn serve_udp(socket: UdpSocket, sender: Sender) {
+    // Fragments of multi-sentence messages are matched on their sequential id and channel, which
+    // senders pick independently, so each peer gets its own decoder. It is dropped once it has no
+    // incomplete message left.
+    let mut decoders: HashMap<SocketAddr, NmeaDecoder> = HashMap::new();
+    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+    loop {
+        let received = tokio::select! {
+            received = socket.recv_from(&mut buffer) => received,
+            _ = sender.closed() => return,
+        };
+        match received {
+            Ok((length, peer)) => {
+                let datagram = String::from_utf8_lossy(&buffer[..length]);
+                let decoder = decoders.entry(peer).or_default();
+                for line in datagram.lines() {
+                    if !send_decoded(decoder, line, peer, &sender).await {
+                        return;
+                    }
+                }
+                if !decoder.has_pending() {
+                    decoders.remove(&peer);
+                }
+            }
+            Err(error) => {
+                if sender.send(Err(ListenerError::Io(error))).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Reads lines until the peer closes the connection or the stream is dropped.
+async fn read_sentences(stream: impl AsyncRead + Unpin, peer: SocketAddr, sender: &Sender) -> std::io::Result<()> {
+    let mut decoder = NmeaDecoder::new();
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = tokio::select! {
+            read = reader.read_until(b'\n', &mut line) => read?,
+            _ = sender.closed() => return Ok(()),
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        if !send_decoded(&mut decoder, &String::from_utf8_lossy(&line), peer, sender).await {
+            return Ok(());
+        }
+    }
+}
+
+// Decodes one line and sends its messages on, returning false once the stream was dropped.
+async fn send_decoded(decoder: &mut NmeaDecoder, line: &str, peer: SocketAddr, sender: &Sender) -> bool {
+    // Receivers and multiplexers also forward GPS and other sentences, only AIS is of interest.
+    let line = line.trim();
+    if !(line.contains("VDM,") || line.contains("VDO,")) {
+        return true;
+    }
+
+    let decoded = decoder
+        .decode_sentence(line, Utc::now())
+        .map_err(|error| ListenerError::InvalidSentence { peer, error });
+    match decoded {
+        Ok(messages) => {
+            for message in messages {
+                if sender.send(Ok(message)).await.is_err() {
+                    return false;
+                }
+            }
+            true
+        }
+        Err(error) => sender.send(Err(error)).await.is_ok(),
+    }
+}
+
+fn unspecified_address() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 0))
+}
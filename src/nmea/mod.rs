@@ -0,0 +1,4 @@
+mod bits;
+pub mod decoder;
+pub mod encoder;
+pub mod listener;
@@ -0,0 +1,121 @@
+use crate::database::postgres::{IngestionReport, InsertedRows, RequestLog};
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData, RejectedMessage};
+use crate::sinks::{next_log_id, AisSink, RequestRecord, SinkError};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use sqlx::types::Uuid;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+/// Appends to JSON lines files in a directory, for deployments without a database.
+///
+/// Messages go to `static.jsonl`, `aton.jsonl` and `position.jsonl` in the BarentsWatch format, so
+/// they can be replayed into Postgres later, rejected messages to `rejected.jsonl` and completed
+/// requests to `requests.jsonl`. The files are only appended to, duplicates are not detected.
+pub struct JsonLinesSink {
+    directory: PathBuf,
+    // Requests between being logged and completed.
+    pending: Mutex<HashMap<Uuid, RequestRecord>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        JsonLinesSink {
+            directory: directory.as_ref().to_path_buf(),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn append<T: Serialize>(&self, file_name: &str, records: &[T]) -> Result<u64, SinkError> {
+        if records.is_empty() {
+            return Ok(0);
+        }
+        let path = self.directory.join(file_name);
+        let file_error = |error| SinkError::File {
+            path: path.clone(),
+            error,
+        };
+
+        let mut lines = String::new();
+        for record in records {
+            lines.push_str(&serde_json::to_string(record).map_err(|error| file_error(error.into()))?);
+            lines.push('\n');
+        }
+        tokio::fs::create_dir_all(&self.directory).await.map_err(file_error)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(file_error)?;
+        // One write per batch, so concurrent batches do not interleave within a line.
+        file.write_all(lines.as_bytes()).await.map_err(file_error)?;
+        file.flush().await.map_err(file_error)?;
+
+        Ok(records.len() as u64)
+    }
+
+    async fn append_messages<T: Serialize>(&self, file_name: &str, messages: &[T]) -> Result<InsertedRows, SinkError> {
+        let rows = self.append(file_name, messages).await?;
+        Ok(InsertedRows::new(rows))
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, RequestRecord>> {
+        self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl AisSink for JsonLinesSink {
+    fn log_request<'a>(&'a self, request: &'a RequestLog) -> BoxFuture<'a, Result<Uuid, SinkError>> {
+        let log_id = next_log_id();
+        self.pending().insert(log_id, RequestRecord::new(log_id, request));
+        Box::pin(async move { Ok(log_id) })
+    }
+
+    fn write_static_data<'a>(&'a self, _log_id: Uuid, static_data: &'a [AISStaticData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        Box::pin(self.append_messages("static.jsonl", static_data))
+    }
+
+    fn write_aton_data<'a>(&'a self, _log_id: Uuid, aton_data: &'a [AISAtonData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        Box::pin(self.append_messages("aton.jsonl", aton_data))
+    }
+
+    fn write_position_data<'a>(&'a self, _log_id: Uuid, position_data: &'a [AISPositionData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        Box::pin(self.append_messages("position.jsonl", position_data))
+    }
+
+    fn write_rejected<'a>(&'a self, log_id: Uuid, rejected: &'a [RejectedMessage]) -> BoxFuture<'a, Result<u64, SinkError>> {
+        let log_id = log_id.to_string();
+        let lines: Vec<RejectedLine> = rejected
+            .iter()
+            .map(|message| RejectedLine {
+                log_id: log_id.clone(),
+                message,
+            })
+            .collect();
+        Box::pin(async move { self.append("rejected.jsonl", &lines).await })
+    }
+
+    fn complete_request<'a>(&'a self, report: &'a IngestionReport) -> BoxFuture<'a, Result<(), SinkError>> {
+        let request = self.pending().remove(&report.log_id);
+        Box::pin(async move {
+            if let Some(mut request) = request {
+                request.complete(report);
+                self.append("requests.jsonl", &[request]).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// A line of `rejected.jsonl`, the message with the request it was rejected from.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RejectedLine<'a> {
+    log_id: String,
+    #[serde(flatten)]
+    message: &'a RejectedMessage,
+}
@@ -0,0 +1,105 @@
+use crate::database::postgres::{IngestionReport, InsertedRows, RequestLog};
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData, RejectedMessage};
+use crate::sinks::{next_log_id, AisSink, RequestRecord, SinkError};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use sqlx::types::Uuid;
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+
+// Kind, MMSI, msgtime and message type, the natural key Postgres deduplicates on.
+type MessageKey = (&'static str, i64, DateTime<Utc>, i64);
+
+/// What a [`MemorySink`] holds.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    pub requests: Vec<RequestRecord>,
+    pub static_data: Vec<AISStaticData>,
+    pub aton_data: Vec<AISAtonData>,
+    pub position_data: Vec<AISPositionData>,
+    pub rejected: Vec<RejectedMessage>,
+    keys: HashSet<MessageKey>,
+}
+
+impl MemoryStore {
+    // Like NULLs in a unique index, a message missing part of its key never counts as a duplicate.
+    fn is_new(&mut self, kind: &'static str, mmsi: Option<i64>, msgtime: Option<DateTime<Utc>>, message_type: Option<i64>) -> bool {
+        match (mmsi, msgtime, message_type) {
+            (Some(mmsi), Some(msgtime), Some(message_type)) => self.keys.insert((kind, mmsi, msgtime, message_type)),
+            _ => true,
+        }
+    }
+}
+
+/// Keeps everything in memory, for tests of the ingestion that should not need a database.
+#[derive(Default)]
+pub struct MemorySink {
+    store: Mutex<MemoryStore>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        MemorySink::default()
+    }
+
+    pub fn store(&self) -> MutexGuard<'_, MemoryStore> {
+        self.store.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl AisSink for MemorySink {
+    fn log_request<'a>(&'a self, request: &'a RequestLog) -> BoxFuture<'a, Result<Uuid, SinkError>> {
+        let log_id = next_log_id();
+        self.store().requests.push(RequestRecord::new(log_id, request));
+        Box::pin(async move { Ok(log_id) })
+    }
+
+    fn write_static_data<'a>(&'a self, _log_id: Uuid, static_data: &'a [AISStaticData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        let mut store = self.store();
+        let mut rows = 0;
+        for message in static_data {
+            if store.is_new("staticdata", message.mmsi, message.msgtime, message.message_type) {
+                store.static_data.push(message.clone());
+                rows += 1;
+            }
+        }
+        Box::pin(async move { Ok(InsertedRows::new(rows)) })
+    }
+
+    fn write_aton_data<'a>(&'a self, _log_id: Uuid, aton_data: &'a [AISAtonData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        let mut store = self.store();
+        let mut rows = 0;
+        for message in aton_data {
+            if store.is_new("aton", message.mmsi, message.msgtime, message.message_type) {
+                store.aton_data.push(message.clone());
+                rows += 1;
+            }
+        }
+        Box::pin(async move { Ok(InsertedRows::new(rows)) })
+    }
+
+    fn write_position_data<'a>(&'a self, _log_id: Uuid, position_data: &'a [AISPositionData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        let mut store = self.store();
+        let mut rows = 0;
+        for message in position_data {
+            if store.is_new("position", message.mmsi, message.msgtime, message.message_type) {
+                store.position_data.push(message.clone());
+                rows += 1;
+            }
+        }
+        Box::pin(async move { Ok(InsertedRows::new(rows)) })
+    }
+
+    fn write_rejected<'a>(&'a self, _log_id: Uuid, rejected: &'a [RejectedMessage]) -> BoxFuture<'a, Result<u64, SinkError>> {
+        self.store().rejected.extend_from_slice(rejected);
+        Box::pin(async move { Ok(rejected.len() as u64) })
+    }
+
+    fn complete_request<'a>(&'a self, report: &'a IngestionReport) -> BoxFuture<'a, Result<(), SinkError>> {
+        let log_id = report.log_id.to_string();
+        if let Some(request) = self.store().requests.iter_mut().find(|request| request.log_id == log_id) {
+            request.complete(report);
+        }
+        Box::pin(async move { Ok(()) })
+    }
+}
@@ -0,0 +1,196 @@
+//! Where ingested messages are written.
+//!
+//! The ingestion only talks to an [`AisSink`], so the same pipeline writes to Postgres, to plain
+//! files or to memory in tests.
+
+pub mod json_lines;
+pub mod memory;
+pub mod postgres;
+
+use crate::database::postgres::{log_metrics, IngestionReport, InsertMetrics, InsertedRows, RequestLog, RequestOutcome};
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData, RejectedMessage, SplitAISMessages};
+use crate::live_ais::validation::QualityReport;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use sqlx::types::Uuid;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("database error: {0}")]
+    Database(sqlx::Error),
+
+    #[error("could not write {}: {error}", path.display())]
+    File { path: PathBuf, error: std::io::Error },
+}
+
+/// Storage for ingested messages and the requests they arrived with.
+///
+/// Implementations provide the request log, a write per message kind and one for rejected messages;
+/// the provided
+/// [`ingest`](AisSink::ingest) combines them, writing each kind on its own so that a failed kind
+/// makes the outcome `partial` instead of losing the others. Sinks that can do better, such as
+/// committing everything in one transaction, override it.
+pub trait AisSink: Send + Sync {
+    /// Records a request before its messages are written and returns its id.
+    fn log_request<'a>(&'a self, request: &'a RequestLog) -> BoxFuture<'a, Result<Uuid, SinkError>>;
+
+    /// Returns the number of messages stored and the ones the sink refused, messages that were
+    /// stored before are skipped when the sink can tell.
+    fn write_static_data<'a>(&'a self, log_id: Uuid, static_data: &'a [AISStaticData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>>;
+
+    fn write_aton_data<'a>(&'a self, log_id: Uuid, aton_data: &'a [AISAtonData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>>;
+
+    fn write_position_data<'a>(&'a self, log_id: Uuid, position_data: &'a [AISPositionData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>>;
+
+    /// Keeps messages that were left out of the request, so they can be re-processed later.
+    fn write_rejected<'a>(&'a self, log_id: Uuid, rejected: &'a [RejectedMessage]) -> BoxFuture<'a, Result<u64, SinkError>>;
+
+    /// Records how the request logged with [`log_request`](AisSink::log_request) ended.
+    fn complete_request<'a>(&'a self, report: &'a IngestionReport) -> BoxFuture<'a, Result<(), SinkError>>;
+
+    /// Housekeeping run when the ingestion starts and about once an hour after, such as creating
+    /// partitions.
+    fn maintain(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// Records the request and writes its messages.
+    fn ingest<'a>(&'a self, request: &'a RequestLog, messages: &'a SplitAISMessages) -> BoxFuture<'a, Result<IngestionReport, SinkError>> {
+        Box::pin(ingest_per_kind(self, request, messages))
+    }
+}
+
+// The messages a sink neither wrote nor rejected. Saturating, as a sink may write more rows than
+// it was given messages.
+fn duplicates(messages: usize, inserted: &InsertedRows) -> u64 {
+    (messages as u64)
+        .saturating_sub(inserted.rejected.len() as u64)
+        .saturating_sub(inserted.rows)
+}
+
+async fn ingest_per_kind<S: AisSink + ?Sized>(
+    sink: &S,
+    request: &RequestLog,
+    messages: &SplitAISMessages,
+) -> Result<IngestionReport, SinkError> {
+    let start = Instant::now();
+    let log_id = sink.log_request(request).await?;
+    let mut metrics = InsertMetrics::default();
+    let mut errors = Vec::new();
+    let mut failed_kinds = 0;
+    let mut rejected = messages.rejected.clone();
+
+    match sink.write_static_data(log_id, &messages.static_data).await {
+        Ok(inserted) => {
+            metrics.static_rows = inserted.rows;
+            metrics.vessels_upserted = inserted.derived_rows;
+            metrics.duplicates_skipped += duplicates(messages.static_data.len(), &inserted);
+            rejected.extend(inserted.rejected);
+        }
+        Err(error) => {
+            failed_kinds += 1;
+            errors.push(format!("static data: {}", error));
+        }
+    }
+    match sink.write_aton_data(log_id, &messages.aton_data).await {
+        Ok(inserted) => {
+            metrics.aton_rows = inserted.rows;
+            metrics.duplicates_skipped += duplicates(messages.aton_data.len(), &inserted);
+            rejected.extend(inserted.rejected);
+        }
+        Err(error) => {
+            failed_kinds += 1;
+            errors.push(format!("aton data: {}", error));
+        }
+    }
+    match sink.write_position_data(log_id, &messages.position_data).await {
+        Ok(inserted) => {
+            metrics.position_rows = inserted.rows;
+            metrics.latest_positions_updated = inserted.derived_rows;
+            metrics.duplicates_skipped += duplicates(messages.position_data.len(), &inserted);
+            rejected.extend(inserted.rejected);
+        }
+        Err(error) => {
+            failed_kinds += 1;
+            errors.push(format!("position data: {}", error));
+        }
+    }
+    match sink.write_rejected(log_id, &rejected).await {
+        Ok(rows) => metrics.rejected_messages = rows,
+        Err(error) => errors.push(format!("rejected messages: {}", error)),
+    }
+    metrics.elapsed = start.elapsed();
+
+    let outcome = if errors.is_empty() {
+        RequestOutcome::Success
+    } else if failed_kinds == 3 {
+        RequestOutcome::Failed
+    } else {
+        RequestOutcome::Partial
+    };
+    let report = IngestionReport {
+        log_id,
+        outcome,
+        error: (!errors.is_empty()).then(|| errors.join("; ")),
+        metrics,
+    };
+    sink.complete_request(&report).await?;
+    log_metrics(&report.metrics);
+
+    Ok(report)
+}
+
+/// A request as the sinks without a log.requests table keep it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestRecord {
+    pub log_id: String,
+    pub created_at: DateTime<Utc>,
+    pub source: &'static str,
+    pub api_endpoint: String,
+    pub status_code: Option<i32>,
+    pub number_of_messages: i64,
+    pub unknown_messages: i64,
+    pub duplicates_skipped: u64,
+    /// `None` until the request was completed.
+    pub outcome: Option<&'static str>,
+    pub status_message: Option<String>,
+    pub validation_issues: Vec<QualityReport>,
+}
+
+impl RequestRecord {
+    pub fn new(log_id: Uuid, request: &RequestLog) -> Self {
+        RequestRecord {
+            log_id: log_id.to_string(),
+            created_at: Utc::now(),
+            source: request.source.as_str(),
+            api_endpoint: request.api_endpoint.clone(),
+            status_code: request.status_code,
+            number_of_messages: request.number_of_messages,
+            unknown_messages: request.unknown_messages,
+            duplicates_skipped: 0,
+            outcome: None,
+            status_message: None,
+            validation_issues: request.quality_reports.clone(),
+        }
+    }
+
+    pub fn complete(&mut self, report: &IngestionReport) {
+        self.duplicates_skipped = report.metrics.duplicates_skipped;
+        self.outcome = Some(report.outcome.as_str());
+        self.status_message = report.error.clone();
+    }
+}
+
+// Ids for sinks that do not get one from the database: the time in the high half keeps them
+// unique across runs, the counter within one.
+fn next_log_id() -> Uuid {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    Uuid::from_u64_pair(nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
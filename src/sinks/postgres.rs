@@ -0,0 +1,123 @@
+use crate::database::configuration::{IngestionSettings, PartitionSettings};
+use crate::database::latest_position::upsert_latest_positions;
+use crate::database::partitions::maintain_position_partitions;
+use crate::database::postgres::{
+    finish, ingest_atomically, insert_aton_data, insert_position_data, insert_request_log_with_outcome,
+    insert_static_data, record_duplicates_skipped, update_request_outcome, IngestionReport, InsertedRows, RequestLog,
+};
+use crate::database::rejected_messages::insert_rejected_messages;
+use crate::database::vessels::upsert_vessels;
+use crate::live_ais::response_structs::{AISAtonData, AISPositionData, AISStaticData, RejectedMessage, SplitAISMessages};
+use crate::sinks::{AisSink, SinkError};
+use futures::future::BoxFuture;
+use log::warn;
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+
+/// The ais and log schemas. With `atomic` a request is written by [`ingest_atomically`], otherwise
+/// each message kind is committed in a transaction of its own like the other sinks write them.
+pub struct PostgresSink {
+    pool: PgPool,
+    batch_size: usize,
+    atomic: bool,
+    partitioning: PartitionSettings,
+}
+
+impl PostgresSink {
+    pub fn new(pool: PgPool, settings: &IngestionSettings, partitioning: PartitionSettings) -> Self {
+        PostgresSink {
+            pool,
+            batch_size: settings.insert_batch_size,
+            atomic: settings.atomic,
+            partitioning,
+        }
+    }
+}
+
+impl AisSink for PostgresSink {
+    fn log_request<'a>(&'a self, request: &'a RequestLog) -> BoxFuture<'a, Result<Uuid, SinkError>> {
+        Box::pin(async move {
+            insert_request_log_with_outcome(&self.pool, request, None, None)
+                .await
+                .map_err(SinkError::Database)
+        })
+    }
+
+    fn write_static_data<'a>(&'a self, log_id: Uuid, static_data: &'a [AISStaticData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        Box::pin(async move {
+            let mut tx = self.pool.begin().await.map_err(SinkError::Database)?;
+            let result = async {
+                let mut inserted = insert_static_data(&mut tx, static_data, log_id, self.batch_size).await?;
+                inserted.derived_rows = upsert_vessels(&mut tx, &inserted.accepted(static_data), log_id).await?;
+                Ok(inserted)
+            }
+            .await;
+            finish(tx, result).await.map_err(SinkError::Database)
+        })
+    }
+
+    fn write_aton_data<'a>(&'a self, log_id: Uuid, aton_data: &'a [AISAtonData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        Box::pin(async move {
+            let mut tx = self.pool.begin().await.map_err(SinkError::Database)?;
+            let result = insert_aton_data(&mut tx, aton_data, log_id, self.batch_size).await;
+            finish(tx, result).await.map_err(SinkError::Database)
+        })
+    }
+
+    fn write_position_data<'a>(&'a self, log_id: Uuid, position_data: &'a [AISPositionData]) -> BoxFuture<'a, Result<InsertedRows, SinkError>> {
+        Box::pin(async move {
+            let mut tx = self.pool.begin().await.map_err(SinkError::Database)?;
+            let result = async {
+                let mut inserted = insert_position_data(&mut tx, position_data, log_id, self.batch_size).await?;
+                inserted.derived_rows =
+                    upsert_latest_positions(&mut tx, &inserted.accepted(position_data), log_id).await?;
+                Ok(inserted)
+            }
+            .await;
+            finish(tx, result).await.map_err(SinkError::Database)
+        })
+    }
+
+    fn write_rejected<'a>(&'a self, log_id: Uuid, rejected: &'a [RejectedMessage]) -> BoxFuture<'a, Result<u64, SinkError>> {
+        Box::pin(async move {
+            insert_rejected_messages(&self.pool, log_id, rejected)
+                .await
+                .map_err(SinkError::Database)
+        })
+    }
+
+    fn complete_request<'a>(&'a self, report: &'a IngestionReport) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            record_duplicates_skipped(&self.pool, report.log_id, report.metrics.duplicates_skipped)
+                .await
+                .map_err(SinkError::Database)?;
+            update_request_outcome(self.pool.clone(), report.log_id, report.outcome, report.error.as_deref())
+                .await
+                .map_err(SinkError::Database)
+        })
+    }
+
+    // Rows without a matching partition land in the default partition, so a failure here is not
+    // worth stopping the ingestion for.
+    fn maintain(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if !self.partitioning.enabled {
+                return;
+            }
+            if let Err(error) = maintain_position_partitions(&self.pool, &self.partitioning).await {
+                warn!("Partition maintenance failed: {}", error);
+            }
+        })
+    }
+
+    fn ingest<'a>(&'a self, request: &'a RequestLog, messages: &'a SplitAISMessages) -> BoxFuture<'a, Result<IngestionReport, SinkError>> {
+        if !self.atomic {
+            return Box::pin(super::ingest_per_kind(self, request, messages));
+        }
+        Box::pin(async move {
+            ingest_atomically(self.pool.clone(), request, messages, self.batch_size)
+                .await
+                .map_err(SinkError::Database)
+        })
+    }
+}
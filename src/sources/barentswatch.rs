@@ -0,0 +1,91 @@
+use crate::database::postgres::RequestSource;
+use crate::live_ais::ais_stream::{AisLiveAPI, ResponseErrorMessages};
+use crate::live_ais::query::AisQuery;
+use crate::live_ais::retry::FailedRequests;
+use crate::sources::{AisSource, Batching, SourceBatch, SourceError};
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use log::warn;
+
+/// Fetches the latest messages that match a query once, as one batch.
+pub struct LatestSource {
+    ais: AisLiveAPI,
+    query: AisQuery,
+}
+
+impl LatestSource {
+    pub fn new(ais: AisLiveAPI, query: AisQuery) -> Self {
+        LatestSource { ais, query }
+    }
+}
+
+impl AisSource for LatestSource {
+    fn kind(&self) -> RequestSource {
+        RequestSource::BarentsWatch
+    }
+
+    fn batches(&mut self) -> BoxStream<'_, Result<SourceBatch, SourceError>> {
+        stream::once(fetch_latest(&self.ais, &self.query))
+            .map(|batch| batch.map_err(SourceError::Api))
+            .boxed()
+    }
+
+    fn failed_requests(&self) -> FailedRequests {
+        self.ais.failed_requests()
+    }
+}
+
+/// The latest messages that match `query`.
+pub async fn fetch_latest(ais: &AisLiveAPI, query: &AisQuery) -> Result<SourceBatch, ResponseErrorMessages> {
+    let response = ais.query_latest_ais(query).await?;
+    Ok(SourceBatch {
+        endpoint: response.api_endpoint,
+        status_code: Some(i32::from(response.status_code)),
+        messages: response.ais_latest_responses.unwrap_or_default(),
+    })
+}
+
+/// Consumes the `/v1/ais` live stream in micro-batches.
+pub struct LiveStreamSource {
+    ais: AisLiveAPI,
+    batching: Batching,
+}
+
+impl LiveStreamSource {
+    pub fn new(ais: AisLiveAPI, batching: Batching) -> Self {
+        LiveStreamSource { ais, batching }
+    }
+}
+
+impl AisSource for LiveStreamSource {
+    fn kind(&self) -> RequestSource {
+        RequestSource::BarentsWatch
+    }
+
+    fn batches(&mut self) -> BoxStream<'_, Result<SourceBatch, SourceError>> {
+        let endpoint = self.ais.stream_endpoint();
+        let messages = self.ais.stream_ais().filter_map(|item| async move {
+            match item {
+                Ok(item) => Some(item),
+                Err(error) => {
+                    warn!("Error while reading the live AIS stream: {}", error);
+                    None
+                }
+            }
+        });
+
+        tokio_stream::StreamExt::chunks_timeout(messages, self.batching.size, self.batching.timeout)
+            .map(move |messages| {
+                Ok(SourceBatch {
+                    endpoint: endpoint.clone(),
+                    status_code: Some(200),
+                    messages,
+                })
+            })
+            .boxed()
+    }
+
+    fn failed_requests(&self) -> FailedRequests {
+        self.ais.failed_requests()
+    }
+}
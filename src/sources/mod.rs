@@ -0,0 +1,71 @@
+//! Where AIS messages are ingested from.
+//!
+//! Every source implements [`AisSource`] and hands the ingestion batches of typed messages along
+//! with what to record about them in log.requests, so a new source needs no change to the Postgres
+//! writers.
+
+pub mod barentswatch;
+pub mod nmea;
+pub mod replay;
+
+use crate::database::configuration::IngestionSettings;
+use crate::database::postgres::RequestSource;
+use crate::live_ais::ais_stream::ResponseErrorMessages;
+use crate::live_ais::response_structs::AisMessage;
+use crate::live_ais::retry::FailedRequests;
+use futures::stream::BoxStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("BarentsWatch request failed: {0}")]
+    Api(ResponseErrorMessages),
+
+    #[error("could not read {}: {error}", path.display())]
+    File { path: PathBuf, error: std::io::Error },
+}
+
+/// Messages read from a source in one go, recorded as one row of log.requests.
+pub struct SourceBatch {
+    /// URL, address or file the messages were read from.
+    pub endpoint: String,
+    /// HTTP status of the request, `None` for sources that are not HTTP.
+    pub status_code: Option<i32>,
+    pub messages: Vec<AisMessage>,
+}
+
+pub trait AisSource: Send {
+    fn kind(&self) -> RequestSource;
+
+    /// The messages of the source in batches. Finite sources end the stream once everything was
+    /// read, live ones never end it.
+    ///
+    /// Errors the source recovers from on its own, such as a dropped connection or an invalid
+    /// line, are logged and skipped. An error that is yielded ends the ingestion.
+    fn batches(&mut self) -> BoxStream<'_, Result<SourceBatch, SourceError>>;
+
+    /// Requests of the source that failed, including retried ones, which the ingestion records as
+    /// failed requests. A shared handle, so it can be drained while the batches are read.
+    fn failed_requests(&self) -> FailedRequests {
+        FailedRequests::default()
+    }
+}
+
+/// How sources that read message by message group them into batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batching {
+    pub size: usize,
+    /// Longest a live source waits to fill a batch before handing over what it has.
+    pub timeout: Duration,
+}
+
+impl From<&IngestionSettings> for Batching {
+    fn from(settings: &IngestionSettings) -> Self {
+        Batching {
+            size: settings.stream_batch_size.max(1),
+            timeout: Duration::from_secs(settings.stream_batch_timeout_seconds),
+        }
+    }
+}
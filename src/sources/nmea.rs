@@ -0,0 +1,149 @@
+use crate::database::configuration::{NmeaSettings, NmeaTransport};
+use crate::database::postgres::RequestSource;
+use crate::live_ais::response_structs::{AisMessage, InvalidMessage};
+use crate::nmea::decoder::{NmeaDecoder, NmeaError};
+use crate::nmea::listener::{listen_nmea, nmea_endpoint};
+use crate::sources::{AisSource, Batching, SourceBatch, SourceError};
+use async_stream::stream;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use log::warn;
+use std::path::PathBuf;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Sentences read from a local receiver over TCP or UDP, see [`listen_nmea`].
+pub struct NmeaSocketSource {
+    settings: NmeaSettings,
+    batching: Batching,
+}
+
+impl NmeaSocketSource {
+    pub fn new(settings: NmeaSettings, batching: Batching) -> Self {
+        NmeaSocketSource { settings, batching }
+    }
+}
+
+impl AisSource for NmeaSocketSource {
+    fn kind(&self) -> RequestSource {
+        match self.settings.transport {
+            NmeaTransport::TcpClient | NmeaTransport::TcpServer => RequestSource::NmeaTcp,
+            NmeaTransport::Udp => RequestSource::NmeaUdp,
+        }
+    }
+
+    fn batches(&mut self) -> BoxStream<'_, Result<SourceBatch, SourceError>> {
+        let endpoint = nmea_endpoint(&self.settings);
+        let messages = listen_nmea(&self.settings).filter_map(|item| async move {
+            match item {
+                Ok(item) => Some(item),
+                Err(error) => {
+                    warn!("Error while reading NMEA sentences: {}", error);
+                    None
+                }
+            }
+        });
+
+        tokio_stream::StreamExt::chunks_timeout(messages, self.batching.size, self.batching.timeout)
+            .map(move |messages| {
+                Ok(SourceBatch {
+                    endpoint: endpoint.clone(),
+                    status_code: None,
+                    messages,
+                })
+            })
+            .boxed()
+    }
+}
+
+/// A file of NMEA sentences, one per line, such as a receiver log.
+///
+/// `msgtime` is the `c:` timestamp of the tag block in front of a message. Messages without one are
+/// rejected unless a base time is given, so that replaying a file twice writes the same rows and old
+/// messages are not taken for current ones.
+pub struct NmeaFileSource {
+    path: PathBuf,
+    batch_size: usize,
+    base_time: Option<DateTime<Utc>>,
+}
+
+impl NmeaFileSource {
+    pub fn new(path: PathBuf, batch_size: usize) -> Self {
+        NmeaFileSource {
+            path,
+            batch_size: batch_size.max(1),
+            base_time: None,
+        }
+    }
+
+    /// The time messages without a tag block timestamp were received at.
+    pub fn with_base_time(mut self, base_time: DateTime<Utc>) -> Self {
+        self.base_time = Some(base_time);
+        self
+    }
+}
+
+impl AisSource for NmeaFileSource {
+    fn kind(&self) -> RequestSource {
+        RequestSource::NmeaFile
+    }
+
+    fn batches(&mut self) -> BoxStream<'_, Result<SourceBatch, SourceError>> {
+        let path = self.path.clone();
+        let batch_size = self.batch_size;
+        let base_time = self.base_time;
+        stream! {
+            let file = match File::open(&path).await {
+                Ok(file) => file,
+                Err(error) => {
+                    yield Err(SourceError::File { path, error });
+                    return;
+                }
+            };
+            let endpoint = path.display().to_string();
+            let mut lines = BufReader::new(file).lines();
+            let mut decoder = NmeaDecoder::new();
+            let mut messages = Vec::with_capacity(batch_size);
+            let mut line_number = 0;
+            loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(error) => {
+                        yield Err(SourceError::File { path, error });
+                        return;
+                    }
+                };
+                line_number += 1;
+                if !(line.contains("VDM,") || line.contains("VDO,")) {
+                    continue;
+                }
+                let decoded = match base_time {
+                    Some(base_time) => decoder.decode_sentence(&line, base_time),
+                    None => decoder.decode_recorded(&line),
+                };
+                match decoded {
+                    Ok(decoded) => messages.extend(decoded),
+                    // Kept as rejected, a base time makes them usable.
+                    Err(error @ NmeaError::MissingTime) => messages.push(AisMessage::Invalid(InvalidMessage {
+                        payload: line.into(),
+                        error: format!("line {}: {}", line_number, error),
+                    })),
+                    Err(error) => warn!("Skipping line {} of {}: {}", line_number, endpoint, error),
+                }
+                if messages.len() >= batch_size {
+                    yield Ok(SourceBatch {
+                        endpoint: endpoint.clone(),
+                        status_code: None,
+                        messages: std::mem::take(&mut messages),
+                    });
+                }
+            }
+            if !messages.is_empty() {
+                yield Ok(SourceBatch { endpoint, status_code: None, messages });
+            }
+        }
+        .boxed()
+    }
+}
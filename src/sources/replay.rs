@@ -0,0 +1,79 @@
+use crate::database::postgres::RequestSource;
+use crate::live_ais::response_structs::AisMessage;
+use crate::sources::{AisSource, SourceBatch, SourceError};
+use async_stream::stream;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use log::warn;
+use std::path::PathBuf;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// A JSON lines file of messages in the BarentsWatch format, as written by `barents export`, to
+/// replay recorded traffic through the ingestion.
+pub struct JsonLinesSource {
+    path: PathBuf,
+    batch_size: usize,
+}
+
+impl JsonLinesSource {
+    pub fn new(path: PathBuf, batch_size: usize) -> Self {
+        JsonLinesSource {
+            path,
+            batch_size: batch_size.max(1),
+        }
+    }
+}
+
+impl AisSource for JsonLinesSource {
+    fn kind(&self) -> RequestSource {
+        RequestSource::Replay
+    }
+
+    fn batches(&mut self) -> BoxStream<'_, Result<SourceBatch, SourceError>> {
+        let path = self.path.clone();
+        let batch_size = self.batch_size;
+        stream! {
+            let file = match File::open(&path).await {
+                Ok(file) => file,
+                Err(error) => {
+                    yield Err(SourceError::File { path, error });
+                    return;
+                }
+            };
+            let endpoint = path.display().to_string();
+            let mut lines = BufReader::new(file).lines();
+            let mut messages = Vec::with_capacity(batch_size);
+            let mut line_number = 0;
+            loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(error) => {
+                        yield Err(SourceError::File { path, error });
+                        return;
+                    }
+                };
+                line_number += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<AisMessage>(&line) {
+                    Ok(message) => messages.push(message),
+                    Err(error) => warn!("Skipping line {} of {}: {}", line_number, endpoint, error),
+                }
+                if messages.len() >= batch_size {
+                    yield Ok(SourceBatch {
+                        endpoint: endpoint.clone(),
+                        status_code: None,
+                        messages: std::mem::take(&mut messages),
+                    });
+                }
+            }
+            if !messages.is_empty() {
+                yield Ok(SourceBatch { endpoint, status_code: None, messages });
+            }
+        }
+        .boxed()
+    }
+}
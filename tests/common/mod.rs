@@ -0,0 +1,29 @@
+//! A migrated database of its own for the tests that need Postgres.
+
+use barents::database::migrations::run_migrations;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+
+/// Recreates the database `name` next to the one `DATABASE_URL` points at and migrates it. `None`
+/// when `DATABASE_URL` is not set, the test is then skipped.
+pub async fn scratch_database(name: &str) -> Option<PgPool> {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set, skipping");
+        return None;
+    };
+    let server = PgPool::connect(&url).await.expect("the database server is reachable");
+    server
+        .execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", name).as_str())
+        .await
+        .unwrap();
+    server.execute(format!("CREATE DATABASE {}", name).as_str()).await.unwrap();
+
+    let (base, _) = url.rsplit_once('/').expect("DATABASE_URL names a database");
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&format!("{}/{}", base, name))
+        .await
+        .unwrap();
+    run_migrations(&pool).await.expect("the migrations apply");
+    Some(pool)
+}
@@ -0,0 +1,359 @@
+//! The BarentsWatch client and the ingestion against the bundled mock API, without network access
+//! or a database.
+
+use barents::database::configuration::{RetrySettings, TokenSettings};
+use barents::database::postgres::{RequestLog, RequestOutcome, RequestSource};
+use barents::live_ais::ais_stream::{AisLiveAPI, ResponseErrorMessages, ScopeType};
+use barents::live_ais::mock_server::{MockEndpoint, MockRequest, MockScript, MockServer, ScriptedFailure};
+use barents::live_ais::query::{AisQuery, MessageKind, ModelFormat, Polygon};
+use barents::live_ais::response_structs::{AISPositionData, AisMessage, SplitAISMessages};
+use barents::sinks::memory::MemorySink;
+use barents::sinks::AisSink;
+use barents::sources::barentswatch::LatestSource;
+use barents::sources::AisSource;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use std::path::{Path, PathBuf};
+
+async fn start(script: MockScript) -> MockServer {
+    MockServer::start(script, "127.0.0.1:0".parse().unwrap())
+        .await
+        .expect("the mock server starts")
+}
+
+fn client(server: &MockServer) -> AisLiveAPI {
+    AisLiveAPI::new(
+        "client_credentials".to_owned(),
+        "client".to_owned(),
+        "secret".to_owned(),
+        ScopeType::Ais,
+    )
+    .with_endpoints(server.api_settings())
+    .with_retry(RetrySettings {
+        max_attempts: 3,
+        initial_backoff_ms: 1,
+        circuit_breaker_threshold: 2,
+        ..RetrySettings::default()
+    })
+}
+
+fn requests_to(server: &MockServer, endpoint: MockEndpoint) -> Vec<StatusCode> {
+    server
+        .requests()
+        .into_iter()
+        .filter(|request| request.endpoint == endpoint)
+        .map(|MockRequest { status, .. }| status)
+        .collect()
+}
+
+fn epoch() -> DateTime<Utc> {
+    DateTime::<Utc>::UNIX_EPOCH
+}
+
+#[tokio::test]
+async fn fetches_the_latest_messages_with_one_token() {
+    let server = start(MockScript::default()).await;
+    let ais = client(&server);
+
+    let first = ais.get_latest_ais(epoch()).await.unwrap();
+    ais.clone().get_latest_ais(epoch()).await.unwrap();
+
+    assert_eq!(first.content_length, Some(4));
+    assert_eq!(requests_to(&server, MockEndpoint::Token), vec![StatusCode::OK]);
+}
+
+#[tokio::test]
+async fn filters_the_latest_messages_by_since() {
+    let server = start(MockScript::default()).await;
+    let since = "2026-10-18T08:00:07Z".parse().unwrap();
+
+    let latest = client(&server).get_latest_ais(since).await.unwrap();
+
+    assert_eq!(latest.content_length, Some(2));
+}
+
+#[tokio::test]
+async fn retries_transient_failures_and_records_them() {
+    let server = start(MockScript {
+        failures: vec![
+            ScriptedFailure::new(MockEndpoint::Token, StatusCode::SERVICE_UNAVAILABLE),
+            ScriptedFailure::new(MockEndpoint::Latest, StatusCode::TOO_MANY_REQUESTS).with_retry_after(0),
+            ScriptedFailure::new(MockEndpoint::Latest, StatusCode::BAD_GATEWAY),
+        ],
+        ..MockScript::default()
+    })
+    .await;
+    let ais = client(&server);
+
+    let latest = ais.get_latest_ais(epoch()).await.unwrap();
+
+    assert_eq!(latest.content_length, Some(4));
+    let failed: Vec<Option<i32>> = ais
+        .failed_requests()
+        .take()
+        .into_iter()
+        .map(|failed| failed.status_code)
+        .collect();
+    assert_eq!(failed, vec![Some(503), Some(429), Some(502)]);
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() {
+    let server = start(MockScript {
+        failures: vec![ScriptedFailure::new(MockEndpoint::Latest, StatusCode::BAD_REQUEST)],
+        ..MockScript::default()
+    })
+    .await;
+
+    let result = client(&server).get_latest_ais(epoch()).await;
+
+    assert!(matches!(result, Err(ResponseErrorMessages::UnexpectedStatusCode(StatusCode::BAD_REQUEST))));
+    assert_eq!(requests_to(&server, MockEndpoint::Latest), vec![StatusCode::BAD_REQUEST]);
+}
+
+#[tokio::test]
+async fn fetches_a_new_token_when_the_current_one_is_refused() {
+    let server = start(MockScript::default()).await;
+    let ais = client(&server);
+    ais.get_latest_ais(epoch()).await.unwrap();
+
+    server.revoke_tokens();
+    ais.get_latest_ais(epoch()).await.unwrap();
+
+    assert_eq!(requests_to(&server, MockEndpoint::Token).len(), 2);
+    assert_eq!(
+        requests_to(&server, MockEndpoint::Latest),
+        vec![StatusCode::OK, StatusCode::UNAUTHORIZED, StatusCode::OK]
+    );
+}
+
+#[tokio::test]
+async fn refreshes_a_token_that_is_about_to_expire() {
+    // With a lifetime of 2 seconds half of it is kept as skew, so the token is replaced after one.
+    let server = start(MockScript {
+        token_expires_in: 2,
+        ..MockScript::default()
+    })
+    .await;
+    let ais = client(&server);
+
+    ais.get_latest_ais(epoch()).await.unwrap();
+    ais.get_latest_ais(epoch()).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    ais.get_latest_ais(epoch()).await.unwrap();
+
+    assert_eq!(requests_to(&server, MockEndpoint::Token).len(), 2);
+}
+
+#[tokio::test]
+async fn pauses_requests_after_repeated_failures() {
+    let server = start(MockScript {
+        failures: vec![ScriptedFailure::new(MockEndpoint::Latest, StatusCode::INTERNAL_SERVER_ERROR); 6],
+        ..MockScript::default()
+    })
+    .await;
+    let ais = client(&server);
+
+    for _ in 0..2 {
+        assert!(ais.get_latest_ais(epoch()).await.is_err());
+    }
+    let paused = ais.get_latest_ais(epoch()).await;
+
+    assert!(matches!(paused, Err(ResponseErrorMessages::CircuitOpen { .. })));
+    assert_eq!(requests_to(&server, MockEndpoint::Latest).len(), 6);
+}
+
+#[tokio::test]
+async fn reads_the_live_stream() {
+    let server = start(MockScript::default()).await;
+    let ais = client(&server);
+
+    let messages: Vec<_> = ais.stream_ais().take(4).collect().await;
+
+    assert!(messages.iter().all(Result::is_ok));
+    assert_eq!(requests_to(&server, MockEndpoint::Stream), vec![StatusCode::OK]);
+}
+
+fn cached_client(server: &MockServer, cache_path: &Path) -> AisLiveAPI {
+    client(server).with_token_settings(TokenSettings {
+        cache_path: Some(cache_path.to_owned()),
+        ..TokenSettings::default()
+    })
+}
+
+fn token_cache(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("barents-{}-{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn reuses_a_cached_token_of_the_same_identity_server_only() {
+    let first = start(MockScript::default()).await;
+    let second = start(MockScript::default()).await;
+    let cache_path = token_cache("token-url");
+
+    cached_client(&first, &cache_path).get_latest_ais(epoch()).await.unwrap();
+    cached_client(&first, &cache_path).get_latest_ais(epoch()).await.unwrap();
+    cached_client(&second, &cache_path).get_latest_ais(epoch()).await.unwrap();
+
+    assert_eq!(requests_to(&first, MockEndpoint::Token).len(), 1);
+    assert_eq!(requests_to(&second, MockEndpoint::Token).len(), 1);
+    assert_eq!(requests_to(&second, MockEndpoint::Latest), vec![StatusCode::OK]);
+    std::fs::remove_file(&cache_path).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn keeps_an_existing_token_cache_from_other_users() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let server = start(MockScript::default()).await;
+    let cache_path = token_cache("permissions");
+    std::fs::write(&cache_path, "").unwrap();
+    std::fs::set_permissions(&cache_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    cached_client(&server, &cache_path).get_latest_ais(epoch()).await.unwrap();
+
+    let mode = std::fs::metadata(&cache_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    std::fs::remove_file(&cache_path).unwrap();
+}
+
+fn mmsis(messages: &[AisMessage]) -> Vec<Option<i64>> {
+    messages.iter().map(AisMessage::mmsi).collect()
+}
+
+#[tokio::test]
+async fn sends_the_filters_to_the_server() {
+    let server = start(MockScript::default()).await;
+    let query = AisQuery::new(epoch())
+        .with_mmsi([257012340])
+        .with_message_types([MessageKind::Position]);
+
+    let latest = client(&server).query_latest_ais(&query).await.unwrap();
+
+    assert_eq!(mmsis(&latest.ais_latest_responses.unwrap()), vec![Some(257012340)]);
+    assert_eq!(requests_to(&server, MockEndpoint::Combined), vec![StatusCode::OK]);
+    assert!(requests_to(&server, MockEndpoint::Latest).is_empty());
+}
+
+#[tokio::test]
+async fn filters_locally_when_the_server_cannot() {
+    let server = start(MockScript {
+        server_filters: false,
+        ..MockScript::default()
+    })
+    .await;
+    let ais = client(&server);
+    // Fishing vessels only, the fixtures have none.
+    let fishing = AisQuery::new(epoch()).with_ship_types([30]);
+    let cargo = AisQuery::new(epoch()).with_ship_types([70]);
+
+    let fishing = ais.query_latest_ais(&fishing).await.unwrap();
+    let cargo = ais.query_latest_ais(&cargo).await.unwrap();
+
+    assert_eq!(fishing.content_length, Some(0));
+    // The position matches through the static data of the same vessel.
+    assert_eq!(mmsis(&cargo.ais_latest_responses.unwrap()), vec![Some(257012340), Some(257012340)]);
+    assert_eq!(requests_to(&server, MockEndpoint::Combined), vec![StatusCode::NOT_FOUND]);
+    assert_eq!(requests_to(&server, MockEndpoint::Latest), vec![StatusCode::OK, StatusCode::OK]);
+}
+
+#[tokio::test]
+async fn filters_by_area_the_same_on_either_side() {
+    let server = start(MockScript::default()).await;
+    let mut api = server.api_settings();
+    api.server_filters = false;
+    let local = client(&server).with_endpoints(api);
+    // Around Tromsø, leaving out the vessel off Hammerfest.
+    let query = AisQuery::new(epoch()).with_area(Polygon::bounding_box(18.5, 69.5, 19.5, 69.8));
+
+    let filtered_by_server = client(&server).query_latest_ais(&query).await.unwrap();
+    let filtered_here = local.query_latest_ais(&query).await.unwrap();
+
+    let expected = vec![Some(257012340), Some(257012340), Some(992591234)];
+    assert_eq!(mmsis(&filtered_by_server.ais_latest_responses.unwrap()), expected);
+    assert_eq!(mmsis(&filtered_here.ais_latest_responses.unwrap()), expected);
+}
+
+#[tokio::test]
+async fn reads_geojson_responses() {
+    let server = start(MockScript::default()).await;
+    let query = AisQuery::new(epoch())
+        .with_country_codes(["nor"])
+        .with_model_format(ModelFormat::Geojson);
+
+    let latest = client(&server).query_latest_ais(&query).await.unwrap();
+
+    let messages = latest.ais_latest_responses.unwrap();
+    assert_eq!(messages.len(), 4);
+    assert!(matches!(
+        &messages[0],
+        AisMessage::Position(position) if position.latitude == Some(69.6489) && position.longitude == Some(18.9551)
+    ));
+}
+
+#[tokio::test]
+async fn ingests_the_latest_messages_into_a_sink() {
+    let server = start(MockScript::default()).await;
+    let mut source = LatestSource::new(client(&server), AisQuery::new(epoch()));
+    let sink = MemorySink::new();
+
+    let batch = source.batches().next().await.unwrap().unwrap();
+    let request = RequestLog {
+        source: source.kind(),
+        api_endpoint: batch.endpoint.clone(),
+        status_code: batch.status_code,
+        number_of_messages: i64::try_from(batch.messages.len()).unwrap(),
+        unknown_messages: 0,
+        quality_reports: Vec::new(),
+    };
+    let messages: SplitAISMessages = batch.messages.into_iter().collect();
+    let report = sink.ingest(&request, &messages).await.unwrap();
+
+    assert_eq!(report.outcome, RequestOutcome::Success);
+    let store = sink.store();
+    assert_eq!(store.requests.len(), 1);
+    assert_eq!(store.requests[0].source, RequestSource::BarentsWatch.as_str());
+    assert_eq!(
+        (store.position_data.len(), store.static_data.len(), store.aton_data.len()),
+        (2, 1, 1)
+    );
+}
+
+#[tokio::test]
+async fn skips_only_duplicates_with_a_complete_key() {
+    let sink = MemorySink::new();
+    let request = RequestLog {
+        source: RequestSource::Replay,
+        api_endpoint: "memory".to_owned(),
+        status_code: None,
+        number_of_messages: 2,
+        unknown_messages: 0,
+        quality_reports: Vec::new(),
+    };
+    let complete = AISPositionData {
+        mmsi: Some(257012340),
+        message_type: Some(1),
+        msgtime: Some(epoch()),
+        ..Default::default()
+    };
+    // Postgres does not consider rows with a NULL in the unique index equal either.
+    let without_msgtime = AISPositionData {
+        msgtime: None,
+        ..complete.clone()
+    };
+    let messages = SplitAISMessages {
+        position_data: vec![complete, without_msgtime],
+        ..SplitAISMessages::default()
+    };
+
+    sink.ingest(&request, &messages).await.unwrap();
+    let report = sink.ingest(&request, &messages).await.unwrap();
+
+    assert_eq!(report.metrics.position_rows, 1);
+    assert_eq!(report.metrics.duplicates_skipped, 1);
+    assert_eq!(sink.store().position_data.len(), 3);
+}
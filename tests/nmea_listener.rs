@@ -0,0 +1,134 @@
+//! The NMEA listener against sockets on the loopback interface.
+
+use barents::database::configuration::{NmeaSettings, NmeaTransport};
+use barents::live_ais::response_structs::{AISStaticData, AisMessage};
+use barents::nmea::encoder::NmeaEncoder;
+use barents::nmea::listener::{listen_nmea, listen_tcp, listen_udp, ListenerError};
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, UdpSocket};
+
+const POSITION: &str = "!AIVDM,1,1,,B,15M67FC000G?ufbE`FepT@3n00Sa,0*5C";
+const STATIC_DATA: [&str; 2] = [
+    "!AIVDM,2,1,1,A,55?MbV02;H;s<HtKR20EHE:0@T4@Dn2222222216L961O5Gf0NSQEp6ClRp8,0*1C",
+    "!AIVDM,2,2,1,A,88888888880,2*25",
+];
+
+async fn next_messages(
+    messages: impl Stream<Item = Result<AisMessage, ListenerError>>,
+    count: usize,
+) -> Vec<AisMessage> {
+    tokio::time::timeout(Duration::from_secs(5), messages.take(count).collect::<Vec<_>>())
+        .await
+        .expect("the messages arrive in time")
+        .into_iter()
+        .map(|message| message.expect("the sentences decode"))
+        .collect()
+}
+
+fn mmsis(messages: &[AisMessage]) -> Vec<Option<i64>> {
+    messages.iter().map(AisMessage::mmsi).collect()
+}
+
+fn names(messages: &[AisMessage]) -> Vec<Option<&str>> {
+    messages
+        .iter()
+        .map(|message| match message {
+            AisMessage::StaticData(static_data) => static_data.name.as_deref(),
+            _ => None,
+        })
+        .collect()
+}
+
+// Class A static data, which is long enough to be sent in two sentences.
+fn two_sentences(mmsi: i64, name: &str) -> Vec<String> {
+    let static_data = AISStaticData {
+        mmsi: Some(mmsi),
+        name: Some(name.to_owned()),
+        destination: Some("TROMSO".to_owned()),
+        report_class: Some("A".to_owned()),
+        ..Default::default()
+    };
+    let sentences = NmeaEncoder::default()
+        .encode(&AisMessage::StaticData(static_data))
+        .unwrap();
+    assert_eq!(sentences.len(), 2);
+    sentences
+}
+
+#[tokio::test]
+async fn decodes_udp_datagrams() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let messages = listen_udp(socket);
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    peer.send_to(POSITION.as_bytes(), address).await.unwrap();
+    for sentence in STATIC_DATA {
+        peer.send_to(sentence.as_bytes(), address).await.unwrap();
+    }
+
+    let messages = next_messages(messages, 2).await;
+    assert_eq!(mmsis(&messages), vec![Some(366053209), Some(351759000)]);
+    assert_eq!(names(&messages), vec![None, Some("EVER DIADEM")]);
+}
+
+#[tokio::test]
+async fn keeps_the_fragments_of_udp_peers_apart() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let messages = listen_udp(socket);
+    let first_peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let second_peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    // Both senders number their first multi-sentence message 0 on channel A.
+    let first = two_sentences(257012340, "POLARLYS");
+    let second = two_sentences(258123450, "NORDLYS");
+
+    for (first, second) in first.iter().zip(&second) {
+        first_peer.send_to(first.as_bytes(), address).await.unwrap();
+        second_peer.send_to(second.as_bytes(), address).await.unwrap();
+    }
+
+    let messages = next_messages(messages, 2).await;
+    assert_eq!(mmsis(&messages), vec![Some(257012340), Some(258123450)]);
+    assert_eq!(names(&messages), vec![Some("POLARLYS"), Some("NORDLYS")]);
+}
+
+#[tokio::test]
+async fn reads_sentences_from_tcp_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let messages = listen_tcp(listener);
+
+    let mut connection = tokio::net::TcpStream::connect(address).await.unwrap();
+    let lines = [
+        "$GPGGA,080007.00,6938.934,N,01857.306,E,1,08,0.9,12.0,M,,M,,*4F",
+        POSITION,
+        STATIC_DATA[0],
+        STATIC_DATA[1],
+    ];
+    connection.write_all((lines.join("\r\n") + "\r\n").as_bytes()).await.unwrap();
+    connection.shutdown().await.unwrap();
+
+    let messages = next_messages(messages, 2).await;
+    assert_eq!(mmsis(&messages), vec![Some(366053209), Some(351759000)]);
+    assert_eq!(names(&messages), vec![None, Some("EVER DIADEM")]);
+}
+
+#[tokio::test]
+async fn connects_to_a_receiver_as_a_tcp_client() {
+    let receiver = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let settings = NmeaSettings {
+        transport: NmeaTransport::TcpClient,
+        address: receiver.local_addr().unwrap().to_string(),
+        reconnect_delay_seconds: 1,
+    };
+    let messages = listen_nmea(&settings);
+
+    let (mut connection, _) = receiver.accept().await.unwrap();
+    connection.write_all(format!("{}\n", POSITION).as_bytes()).await.unwrap();
+
+    let messages = next_messages(messages, 1).await;
+    assert_eq!(mmsis(&messages), vec![Some(366053209)]);
+}
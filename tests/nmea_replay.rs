@@ -0,0 +1,109 @@
+//! Replaying recorded NMEA sentences into a sink, without network access or a database.
+
+use barents::database::postgres::{IngestionReport, RequestLog};
+use barents::live_ais::response_structs::SplitAISMessages;
+use barents::sinks::memory::MemorySink;
+use barents::sinks::AisSink;
+use barents::sources::nmea::NmeaFileSource;
+use barents::sources::AisSource;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use std::path::{Path, PathBuf};
+
+const POSITION: &str = "!AIVDM,1,1,,B,15M67FC000G?ufbE`FepT@3n00Sa,0*5C";
+const STATIC_DATA: [&str; 2] = [
+    "!AIVDM,2,1,1,A,55?MbV02;H;s<HtKR20EHE:0@T4@Dn2222222216L961O5Gf0NSQEp6ClRp8,0*1C",
+    "!AIVDM,2,2,1,A,88888888880,2*25",
+];
+
+// A tag block with the receive time, as receivers and multiplexers put in front of a sentence.
+fn tagged(time: i64, sentence: &str) -> String {
+    let tag = format!("c:{}", time);
+    let checksum = tag.bytes().fold(0, |checksum, byte| checksum ^ byte);
+    format!("\\{}*{:02X}\\{}", tag, checksum, sentence)
+}
+
+fn recording(name: &str, lines: &[String]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("barents-{}-{}.nmea", name, std::process::id()));
+    std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+    path
+}
+
+async fn replay(sink: &MemorySink, path: &Path, base_time: Option<DateTime<Utc>>) -> Vec<IngestionReport> {
+    let source = NmeaFileSource::new(path.to_owned(), 100);
+    let mut source = match base_time {
+        Some(base_time) => source.with_base_time(base_time),
+        None => source,
+    };
+    let mut reports = Vec::new();
+    let batches: Vec<_> = source.batches().collect().await;
+    for batch in batches {
+        let batch = batch.unwrap();
+        let request = RequestLog {
+            source: source.kind(),
+            api_endpoint: batch.endpoint.clone(),
+            status_code: batch.status_code,
+            number_of_messages: i64::try_from(batch.messages.len()).unwrap(),
+            unknown_messages: 0,
+            quality_reports: Vec::new(),
+        };
+        let messages: SplitAISMessages = batch.messages.into_iter().collect();
+        reports.push(sink.ingest(&request, &messages).await.unwrap());
+    }
+    reports
+}
+
+#[tokio::test]
+async fn replaying_a_recording_twice_writes_nothing_new() {
+    // The tag block of the first fragment dates the whole message.
+    let path = recording(
+        "twice",
+        &[
+            tagged(1_700_000_000, POSITION),
+            tagged(1_700_000_003, STATIC_DATA[0]),
+            STATIC_DATA[1].to_owned(),
+        ],
+    );
+    let sink = MemorySink::new();
+
+    let first = replay(&sink, &path, None).await;
+    let second = replay(&sink, &path, None).await;
+
+    assert_eq!((first[0].metrics.position_rows, first[0].metrics.static_rows), (1, 1));
+    assert_eq!(second[0].metrics.total_rows(), 0);
+    assert_eq!(second[0].metrics.duplicates_skipped, 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn rejects_messages_without_a_receive_time() {
+    let path = recording("untagged", &[POSITION.to_owned(), tagged(1_700_000_000, POSITION)]);
+    let sink = MemorySink::new();
+
+    let reports = replay(&sink, &path, None).await;
+
+    assert_eq!(reports[0].metrics.position_rows, 1);
+    assert_eq!(reports[0].metrics.rejected_messages, 1);
+    let store = sink.store();
+    assert_eq!(store.rejected[0].payload, POSITION);
+    assert!(store.rejected[0].reason.starts_with("line 1:"));
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn dates_untagged_messages_with_the_base_time() {
+    let path = recording("base-time", &[POSITION.to_owned()]);
+    let base_time: DateTime<Utc> = "2023-11-14T22:13:20Z".parse().unwrap();
+    let sink = MemorySink::new();
+
+    replay(&sink, &path, Some(base_time)).await;
+    let second = replay(&sink, &path, Some(base_time)).await;
+
+    assert_eq!(second[0].metrics.total_rows(), 0);
+    let store = sink.store();
+    assert_eq!(store.position_data.len(), 1);
+    assert_eq!(store.position_data[0].msgtime, Some(base_time));
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
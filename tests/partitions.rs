@@ -0,0 +1,100 @@
+//! Partition maintenance and the retention policy against Postgres.
+
+mod common;
+
+use barents::database::configuration::{
+    IngestionSettings, PartitionInterval, PartitionSettings, RetentionAction,
+};
+use barents::database::partitions::maintain_position_partitions;
+use barents::database::postgres::{RequestLog, RequestSource};
+use barents::live_ais::response_structs::{AISPositionData, SplitAISMessages};
+use barents::sinks::postgres::PostgresSink;
+use barents::sinks::AisSink;
+use chrono::{Duration, Utc};
+use sqlx::{query_scalar, PgPool};
+
+async fn write_positions(pool: &PgPool, ages: &[Duration]) {
+    let sink = PostgresSink::new(pool.clone(), &IngestionSettings::default(), PartitionSettings::default());
+    let request = RequestLog {
+        source: RequestSource::Replay,
+        api_endpoint: "test".to_owned(),
+        status_code: None,
+        number_of_messages: ages.len() as i64,
+        unknown_messages: 0,
+        quality_reports: Vec::new(),
+    };
+    let messages = SplitAISMessages {
+        position_data: ages
+            .iter()
+            .map(|age| AISPositionData {
+                type_field: Some("Position".to_owned()),
+                message_type: Some(1),
+                mmsi: Some(257012340),
+                msgtime: Some(Utc::now() - *age),
+                latitude: Some(69.6489),
+                longitude: Some(18.9551),
+                ..Default::default()
+            })
+            .collect(),
+        ..SplitAISMessages::default()
+    };
+    let report = sink.ingest(&request, &messages).await.unwrap();
+    assert_eq!(report.metrics.position_rows, ages.len() as u64);
+}
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+    query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+fn retention(days: i64, action: RetentionAction) -> PartitionSettings {
+    PartitionSettings {
+        enabled: true,
+        interval: PartitionInterval::Daily,
+        premake: 1,
+        retention_days: Some(days),
+        retention_action: action,
+    }
+}
+
+#[tokio::test]
+async fn archives_expired_messages_of_the_default_partition() {
+    let Some(pool) = common::scratch_database("barents_test_retention_archive").await else {
+        return;
+    };
+    write_positions(&pool, &[Duration::days(400), Duration::days(100), Duration::hours(1)]).await;
+
+    let report = maintain_position_partitions(&pool, &retention(30, RetentionAction::Archive))
+        .await
+        .unwrap();
+
+    assert_eq!(count(&pool, "ais.ais_position_data_default").await, 0);
+    assert_eq!(count(&pool, "ais.ais_position_data").await, 1);
+    let [archived] = &report.archived[..] else {
+        panic!("expected one archived partition, got {:?}", report.archived);
+    };
+    assert_eq!(count(&pool, &format!("ais_archive.{}", archived)).await, 2);
+}
+
+#[tokio::test]
+async fn drops_expired_messages_of_the_default_partition() {
+    let Some(pool) = common::scratch_database("barents_test_retention_drop").await else {
+        return;
+    };
+    write_positions(&pool, &[Duration::days(100), Duration::hours(1)]).await;
+    maintain_position_partitions(&pool, &retention(30, RetentionAction::Drop))
+        .await
+        .unwrap();
+    // A late message from before the cutoff, after the partitions exist.
+    write_positions(&pool, &[Duration::days(60)]).await;
+
+    let report = maintain_position_partitions(&pool, &retention(30, RetentionAction::Drop))
+        .await
+        .unwrap();
+
+    assert_eq!(report.dropped.len(), 1);
+    assert_eq!(count(&pool, "ais.ais_position_data_default").await, 0);
+    assert_eq!(count(&pool, "ais.ais_position_data").await, 1);
+}
@@ -0,0 +1,128 @@
+//! The spatial lookups against Postgres. Without PostGIS they use the haversine fallback.
+
+mod common;
+
+use barents::database::configuration::{IngestionSettings, PartitionSettings};
+use barents::database::postgres::{RequestLog, RequestSource};
+use barents::database::queries::ExportFilter;
+use barents::database::spatial::{
+    find_atons, find_fleet_positions, find_positions, postgis_enabled, BoundingBox, SpatialFilter,
+};
+use barents::live_ais::response_structs::{AISAtonData, AISPositionData, SplitAISMessages};
+use barents::sinks::postgres::PostgresSink;
+use barents::sinks::AisSink;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+const TROMSO: (f64, f64) = (69.6489, 18.9551);
+const HAMMERFEST: (f64, f64) = (70.6634, 23.6821);
+
+fn position(mmsi: i64, msgtime: DateTime<Utc>, (latitude, longitude): (f64, f64)) -> AISPositionData {
+    AISPositionData {
+        type_field: Some("Position".to_owned()),
+        message_type: Some(1),
+        mmsi: Some(mmsi),
+        msgtime: Some(msgtime),
+        latitude: Some(latitude),
+        longitude: Some(longitude),
+        ..Default::default()
+    }
+}
+
+async fn write(pool: &PgPool, messages: SplitAISMessages) {
+    let sink = PostgresSink::new(pool.clone(), &IngestionSettings::default(), PartitionSettings::default());
+    let request = RequestLog {
+        source: RequestSource::Replay,
+        api_endpoint: "test".to_owned(),
+        status_code: None,
+        number_of_messages: 0,
+        unknown_messages: 0,
+        quality_reports: Vec::new(),
+    };
+    sink.ingest(&request, &messages).await.unwrap();
+}
+
+fn near((latitude, longitude): (f64, f64), meters: f64) -> SpatialFilter {
+    SpatialFilter::Radius { latitude, longitude, meters }
+}
+
+fn mmsis<T>(rows: &[T], mmsi: impl Fn(&T) -> Option<i64>) -> Vec<i64> {
+    rows.iter().filter_map(mmsi).collect()
+}
+
+#[tokio::test]
+async fn finds_messages_near_a_point_and_in_a_box() {
+    let Some(pool) = common::scratch_database("barents_test_spatial").await else {
+        return;
+    };
+    assert!(!postgis_enabled(&pool).await.unwrap(), "this test is about the fallback without PostGIS");
+    let now = Utc::now();
+    // 0.1 degrees of longitude east of Tromsø is 3.87 km.
+    let east_of_tromso = (TROMSO.0, TROMSO.1 + 0.1);
+    write(
+        &pool,
+        SplitAISMessages {
+            position_data: vec![
+                position(257000001, now - Duration::hours(2), TROMSO),
+                position(257000002, now - Duration::hours(1), east_of_tromso),
+                position(257000003, now - Duration::hours(1), HAMMERFEST),
+                position(257000001, now, HAMMERFEST),
+            ],
+            aton_data: vec![AISAtonData {
+                type_field: Some("AtonReport".to_owned()),
+                message_type: Some(21),
+                mmsi: Some(992576001),
+                msgtime: Some(now),
+                latitude: Some(TROMSO.0 + 0.01),
+                longitude: Some(TROMSO.1),
+                ..Default::default()
+            }],
+            ..SplitAISMessages::default()
+        },
+    )
+    .await;
+    let everything = ExportFilter::default();
+
+    let positions = find_positions(&pool, &near(TROMSO, 4_000.0), &everything).await.unwrap();
+    assert_eq!(mmsis(&positions, |row| row.mmsi), vec![257000001, 257000002]);
+    let positions = find_positions(&pool, &near(TROMSO, 3_700.0), &everything).await.unwrap();
+    assert_eq!(mmsis(&positions, |row| row.mmsi), vec![257000001]);
+
+    let in_the_box = SpatialFilter::BoundingBox("15,68,20,71".parse::<BoundingBox>().unwrap());
+    let positions = find_positions(&pool, &in_the_box, &everything).await.unwrap();
+    assert_eq!(mmsis(&positions, |row| row.mmsi), vec![257000001, 257000002]);
+    let recent = ExportFilter {
+        since: Some(now - Duration::minutes(90)),
+        ..ExportFilter::default()
+    };
+    let positions = find_positions(&pool, &in_the_box, &recent).await.unwrap();
+    assert_eq!(mmsis(&positions, |row| row.mmsi), vec![257000002]);
+
+    let atons = find_atons(&pool, &near(TROMSO, 2_000.0), &everything).await.unwrap();
+    assert_eq!(mmsis(&atons, |row| row.mmsi), vec![992576001]);
+    assert!(find_atons(&pool, &near(HAMMERFEST, 2_000.0), &everything).await.unwrap().is_empty());
+
+    // The first vessel has moved on to Hammerfest since.
+    let fleet = find_fleet_positions(&pool, &near(TROMSO, 4_000.0), &everything).await.unwrap();
+    assert_eq!(mmsis(&fleet, |row| row.mmsi), vec![257000002]);
+    let fleet = find_fleet_positions(&pool, &near(HAMMERFEST, 1_000.0), &everything).await.unwrap();
+    assert_eq!(mmsis(&fleet, |row| row.mmsi), vec![257000001, 257000003]);
+    let only = ExportFilter {
+        mmsi: Some(257000003),
+        ..ExportFilter::default()
+    };
+    let fleet = find_fleet_positions(&pool, &near(HAMMERFEST, 1_000.0), &only).await.unwrap();
+    assert_eq!(mmsis(&fleet, |row| row.mmsi), vec![257000003]);
+}
+
+#[test]
+fn parses_a_bounding_box() {
+    let bbox: BoundingBox = "-10.5, 68, 32, 72".parse().unwrap();
+    assert_eq!(
+        (bbox.min_longitude, bbox.min_latitude, bbox.max_longitude, bbox.max_latitude),
+        (-10.5, 68.0, 32.0, 72.0)
+    );
+    assert!("15,68,32".parse::<BoundingBox>().is_err());
+    assert!("15,68,32,north".parse::<BoundingBox>().is_err());
+    assert!("32,68,15,72".parse::<BoundingBox>().is_err());
+}